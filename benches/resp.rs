@@ -0,0 +1,93 @@
+/*
+ * Copyright 2017-2022 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::hint::black_box;
+
+use bytes::BytesMut;
+use criterion::{criterion_group, criterion_main, Criterion};
+use tokio_util::codec::{Decoder, Encoder};
+
+use redis_async::resp::{RespCodec, RespValue};
+
+/// Integers below 1000 are formatted with a lookup table, larger ones a digit at a time
+fn encode_integers(c: &mut Criterion) {
+    let mut codec = RespCodec::default();
+    let mut bytes = BytesMut::with_capacity(64);
+    for (name, start) in [("small", 0), ("large", 1_000_000_000)] {
+        c.bench_function(&format!("encode {} integers", name), |b| {
+            b.iter(|| {
+                for val in start..start + 1000 {
+                    bytes.clear();
+                    codec
+                        .encode(&RespValue::Integer(black_box(val)), &mut bytes)
+                        .unwrap();
+                }
+            })
+        });
+    }
+}
+
+/// The array and bulk string headers of a command
+fn encode_command(c: &mut Criterion) {
+    let codec = RespCodec::default();
+    let mut bytes = BytesMut::with_capacity(64);
+    c.bench_function("encode command", |b| {
+        b.iter(|| {
+            bytes.clear();
+            codec
+                .encode_command(3, black_box(["SET", "key", "value"]), &mut bytes)
+                .unwrap();
+        })
+    });
+}
+
+fn decode_long_simple_string(c: &mut Criterion) {
+    let mut frame = vec![b'+'];
+    frame.extend(std::iter::repeat_n(b'x', 1 << 20));
+    frame.extend_from_slice(b"\r\n");
+    let mut codec = RespCodec::default();
+    c.bench_function("decode 1MB simple string", |b| {
+        b.iter(|| {
+            let mut bytes = BytesMut::from(&frame[..]);
+            codec.decode(&mut bytes).unwrap().unwrap()
+        })
+    });
+}
+
+/// An array which arrives one byte at a time, each byte only being decoded once
+fn decode_incrementally(c: &mut Criterion) {
+    let count = 10_000;
+    let mut input = format!("*{}\r\n", count).into_bytes();
+    for i in 0..count {
+        input.extend_from_slice(format!(":{}\r\n", i).as_bytes());
+    }
+    let mut codec = RespCodec::default();
+    c.bench_function("decode array a byte at a time", |b| {
+        b.iter(|| {
+            let mut bytes = BytesMut::new();
+            for byte in &input {
+                bytes.extend_from_slice(&[*byte]);
+                if let Some(value) = codec.decode(&mut bytes).unwrap() {
+                    return value;
+                }
+            }
+            unreachable!("The array is complete")
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    encode_integers,
+    encode_command,
+    decode_long_simple_string,
+    decode_incrementally
+);
+criterion_main!(benches);
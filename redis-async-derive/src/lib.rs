@@ -0,0 +1,140 @@
+/*
+ * Copyright 2017-2022 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Derive macros for `redis-async`, these are re-exported by `redis-async` when its `derive`
+//! feature is enabled and should be used from there.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, GenericParam, LitStr};
+
+/// Derive `FromResp` for a struct with named fields.
+///
+/// The struct is converted from a RESP `Map`, or a flat array of alternating keys and values as
+/// returned by commands like `HGETALL`.  Each field is converted from the value whose key matches
+/// the field's name, keys without a matching field are ignored.
+///
+/// Fields can be customised with the `resp` attribute:
+///
+/// * `#[resp(rename = "key")]` - use the value of `key` rather than the field's name.
+/// * `#[resp(default)]` - use `Default::default()` if the key is missing, otherwise a missing key
+///   is an error.
+#[proc_macro_derive(FromResp, attributes(resp))]
+pub fn derive_from_resp(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match from_resp(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+struct FieldAttrs {
+    key: Option<LitStr>,
+    default: bool,
+}
+
+fn field_attrs(field: &syn::Field) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs {
+        key: None,
+        default: false,
+    };
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("resp"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                attrs.key = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("default") {
+                attrs.default = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown resp attribute, expected `rename` or `default`"))
+            }
+        })?;
+    }
+    Ok(attrs)
+}
+
+fn from_resp(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "FromResp can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "FromResp can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut initialisers = Vec::with_capacity(fields.len());
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let attrs = field_attrs(field)?;
+        let key = attrs
+            .key
+            .unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+        let missing = if attrs.default {
+            quote! { ::std::default::Default::default() }
+        } else {
+            quote! {
+                return ::std::result::Result::Err(::redis_async::error::Error::Resp(
+                    ::std::format!("missing field {}", #key),
+                    ::std::option::Option::None,
+                ))
+            }
+        };
+        initialisers.push(quote! {
+            #ident: match fields.remove(#key) {
+                ::std::option::Option::Some(value) => {
+                    ::redis_async::resp::FromResp::from_resp(value)?
+                }
+                ::std::option::Option::None => #missing,
+            }
+        });
+    }
+
+    let ident = &input.ident;
+    for param in input.generics.params.iter_mut() {
+        if let GenericParam::Type(param) = param {
+            param
+                .bounds
+                .push(parse_quote!(::redis_async::resp::FromResp));
+        }
+    }
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::redis_async::resp::FromResp for #ident #ty_generics #where_clause {
+            fn from_resp_int(
+                resp: ::redis_async::resp::RespValue,
+            ) -> ::std::result::Result<Self, ::redis_async::error::Error> {
+                let mut fields = <::std::collections::HashMap<
+                    ::std::string::String,
+                    ::redis_async::resp::RespValue,
+                > as ::redis_async::resp::FromResp>::from_resp_int(resp)?;
+                ::std::result::Result::Ok(#ident {
+                    #(#initialisers,)*
+                })
+            }
+        }
+    })
+}
@@ -10,14 +10,17 @@
 
 use std::sync::Arc;
 
-use crate::error;
+use crate::{error, resp::RespVersion};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// Connection builder
 pub struct ConnectionBuilder {
     pub(crate) addr: String,
     pub(crate) username: Option<Arc<str>>,
     pub(crate) password: Option<Arc<str>>,
+    pub(crate) protocol: RespVersion,
+    pub(crate) resubscribe: bool,
+    pub(crate) database: Option<u32>,
 }
 
 impl ConnectionBuilder {
@@ -26,6 +29,9 @@ impl ConnectionBuilder {
             addr: addr.into(),
             username: None,
             password: None,
+            protocol: RespVersion::Resp2,
+            resubscribe: false,
+            database: None,
         })
     }
 
@@ -40,4 +46,29 @@ impl ConnectionBuilder {
         self.username = Some(username.into());
         self
     }
+
+    /// Set the protocol version used when connecting, the default is RESP2.  Any other version is
+    /// negotiated with `HELLO`, falling back to RESP2 if the server doesn't support it.
+    pub fn protocol(&mut self, protocol: RespVersion) -> &mut Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Paired connections only.  Select the logical database with the given index whenever the
+    /// connection is established, including after reconnecting.  Pub/Sub channels aren't scoped to
+    /// a database so this has no effect on Pub/Sub connections.
+    pub fn database(&mut self, database: u32) -> &mut Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Pub/Sub connections only.  If true, when the connection fails it is re-established
+    /// immediately, with the same handshake, and all active subscriptions are re-issued; the
+    /// streams of messages continue uninterrupted, although any messages published while the
+    /// connection was down are lost.  Otherwise, the default, the streams receive an error and
+    /// clients must subscribe again.
+    pub fn resubscribe(&mut self, resubscribe: bool) -> &mut Self {
+        self.resubscribe = resubscribe;
+        self
+    }
 }
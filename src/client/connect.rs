@@ -8,12 +8,23 @@
  * except according to those terms.
  */
 
-use futures_util::{SinkExt, StreamExt};
+use std::collections::HashMap;
 
-use tokio::net::{TcpStream, ToSocketAddrs};
+use futures_util::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpStream, ToSocketAddrs},
+};
 use tokio_util::codec::{Decoder, Framed};
 
-use crate::{error, resp};
+use crate::{
+    error::{self, RemoteErrorKind},
+    resp::{self, FromResp, RespVersion},
+};
 
 pub type RespConnection = Framed<TcpStream, resp::RespCodec>;
 
@@ -34,16 +45,121 @@ pub type RespConnection = Framed<TcpStream, resp::RespCodec>;
 /// single result, this library also implements `paired_connect`.
 pub async fn connect(addr: impl ToSocketAddrs) -> Result<RespConnection, error::Error> {
     let tcp_stream = TcpStream::connect(addr).await?;
-    Ok(resp::RespCodec.framed(tcp_stream))
+    Ok(resp::RespCodec::default().framed(tcp_stream))
 }
 
+/// The writing half of a transport returned by `split`
+pub type RespSink<T> = SplitSink<Framed<T, resp::RespCodec>, resp::RespValue>;
+
+/// The reading half of a transport returned by `split`
+pub type RespStream<T> = SplitStream<Framed<T, resp::RespCodec>>;
+
+/// Wrap any transport, e.g. a TLS stream or an in-memory pipe, with the given codec, and split it
+/// into a `Sink` for writing `RespValue`s and a `Stream` for reading them.  Both halves use
+/// `error::Error` for their errors.
+///
+/// As with `connect` the two halves are independent, and pairing replies with requests is the
+/// responsibility of the caller.
+pub fn split<T>(io: T, codec: resp::RespCodec) -> (RespSink<T>, RespStream<T>)
+where
+    T: AsyncRead + AsyncWrite,
+{
+    codec.framed(io).split()
+}
+
+/// Connect to a Redis server, authenticating with `AUTH` before anything else is sent if a
+/// password is given.  A username can also be given for servers using ACLs.
+///
+/// If authentication fails the server's reply is returned as an `error::Error::Remote`, e.g. with
+/// a `remote_kind` of `WrongPass`.
 pub async fn connect_with_auth(
     addr: impl ToSocketAddrs,
     username: Option<&str>,
     password: Option<&str>,
 ) -> Result<RespConnection, error::Error> {
     let mut connection = connect(addr).await?;
+    auth(&mut connection, username, password).await?;
+    Ok(connection)
+}
 
+/// Connect to a Redis server using the requested version of the protocol.
+///
+/// RESP2 connections are authenticated with `AUTH` in the same way as `connect_with_auth`, for
+/// any other version the protocol is negotiated with `HELLO`, see `hello` for details.  The
+/// server's information is returned if it was available.
+pub async fn connect_with_protocol(
+    addr: impl ToSocketAddrs,
+    username: Option<&str>,
+    password: Option<&str>,
+    protocol: RespVersion,
+) -> Result<(RespConnection, Option<ServerInfo>), error::Error> {
+    connect_with_options(addr, username, password, protocol, None).await
+}
+
+/// As `connect_with_protocol`, also selecting the given database once the connection is
+/// established.
+pub(crate) async fn connect_with_options(
+    addr: impl ToSocketAddrs,
+    username: Option<&str>,
+    password: Option<&str>,
+    protocol: RespVersion,
+    database: Option<u32>,
+) -> Result<(RespConnection, Option<ServerInfo>), error::Error> {
+    let mut connection = connect(addr).await?;
+    let info = handshake(&mut connection, username, password, protocol, database).await?;
+    Ok((connection, info))
+}
+
+/// Prepare a new connection for use: authenticate and negotiate the protocol, then select the
+/// database, if any.
+async fn handshake<T>(
+    connection: &mut Framed<T, resp::RespCodec>,
+    username: Option<&str>,
+    password: Option<&str>,
+    protocol: RespVersion,
+    database: Option<u32>,
+) -> Result<Option<ServerInfo>, error::Error>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let info = match protocol {
+        RespVersion::Resp2 => {
+            auth(connection, username, password).await?;
+            None
+        }
+        protocol => hello(connection, protocol, username, password).await?,
+    };
+
+    if let Some(database) = database {
+        connection
+            .send(resp_array!["SELECT", database.to_string()])
+            .await?;
+        match connection.next().await {
+            // Only `OK` confirms the database was selected, unlike `FromResp for ()`
+            Some(Ok(value)) => match resp::RespValue::from_resp(value)? {
+                resp::RespValue::SimpleString(ref string) if string == "OK" => (),
+                value => return Err(error::resp("Unexpected reply to SELECT", value)),
+            },
+            Some(Err(e)) => return Err(e),
+            None => {
+                return Err(error::internal(
+                    "Connection closed before database selected",
+                ))
+            }
+        }
+    }
+
+    Ok(info)
+}
+
+async fn auth<T>(
+    connection: &mut Framed<T, resp::RespCodec>,
+    username: Option<&str>,
+    password: Option<&str>,
+) -> Result<(), error::Error>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
     if let Some(password) = password {
         let mut auth = resp_array!["AUTH"];
 
@@ -68,7 +184,109 @@ pub async fn connect_with_auth(
         }
     }
 
-    Ok(connection)
+    Ok(())
+}
+
+/// The details of a Redis server, as returned by `HELLO`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    /// The name of the server, e.g. "redis"
+    pub server: String,
+    /// The version of the server, e.g. "7.2.4"
+    pub version: String,
+    /// The protocol version in use on the connection
+    pub protocol: RespVersion,
+    /// The ID of the client connection
+    pub id: i64,
+    /// The mode the server is running in, e.g. "standalone" or "cluster"
+    pub mode: String,
+    /// The role of the server, e.g. "master" or "replica"
+    pub role: String,
+}
+
+impl FromResp for ServerInfo {
+    fn from_resp_int(resp: resp::RespValue) -> Result<ServerInfo, error::Error> {
+        let mut fields = HashMap::<String, resp::RespValue>::from_resp_int(resp)?;
+        let mut field = |name: &str| match fields.remove(name) {
+            Some(value) => Ok(value),
+            None => Err(error::Error::Resp(
+                format!("HELLO reply has no {} field", name),
+                None,
+            )),
+        };
+
+        let protocol = match i64::from_resp_int(field("proto")?)? {
+            2 => RespVersion::Resp2,
+            3 => RespVersion::Resp3,
+            proto => {
+                return Err(error::resp(
+                    "Unknown protocol version",
+                    resp::RespValue::Integer(proto),
+                ))
+            }
+        };
+
+        Ok(ServerInfo {
+            server: String::from_resp_int(field("server")?)?,
+            version: String::from_resp_int(field("version")?)?,
+            protocol,
+            id: i64::from_resp_int(field("id")?)?,
+            mode: String::from_resp_int(field("mode")?)?,
+            role: String::from_resp_int(field("role")?)?,
+        })
+    }
+}
+
+/// Negotiate the protocol version of a connection with `HELLO`, authenticating at the same time
+/// if a password is given.
+///
+/// On success the connection's codec is switched to the negotiated protocol and the server's
+/// information is returned.  Servers older than Redis 6 do not support `HELLO`, if the server
+/// replies with an error the connection falls back to RESP2, authenticating with `AUTH` instead,
+/// and `None` is returned.  Authentication failures, i.e. `WRONGPASS` or `NOAUTH` errors, are
+/// returned as errors rather than falling back.
+pub async fn hello<T>(
+    connection: &mut Framed<T, resp::RespCodec>,
+    protocol: RespVersion,
+    username: Option<&str>,
+    password: Option<&str>,
+) -> Result<Option<ServerInfo>, error::Error>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let version = match protocol {
+        RespVersion::Resp2 => "2",
+        RespVersion::Resp3 => "3",
+    };
+    let mut hello = resp_array!["HELLO", version];
+    if let Some(password) = password {
+        hello.push("AUTH");
+        hello.push(username.unwrap_or("default"));
+        hello.push(password);
+    }
+
+    connection.send(hello).await?;
+    match connection.next().await {
+        Some(Ok(resp::RespValue::Error(e))) => {
+            let e = error::Error::Remote(e);
+            if let Some(RemoteErrorKind::WrongPass | RemoteErrorKind::NoAuth) = e.remote_kind() {
+                return Err(e);
+            }
+            log::debug!("HELLO failed, falling back to RESP2: {}", e);
+            connection.codec_mut().set_protocol(RespVersion::Resp2);
+            auth(connection, username, password).await?;
+            Ok(None)
+        }
+        Some(Ok(value)) => {
+            let info = ServerInfo::from_resp(value)?;
+            connection.codec_mut().set_protocol(info.protocol);
+            Ok(Some(info))
+        }
+        Some(Err(e)) => Err(e),
+        None => Err(error::internal(
+            "Connection closed before protocol negotiation complete",
+        )),
+    }
 }
 
 #[cfg(test)]
@@ -78,7 +296,13 @@ mod test {
         stream::{self, StreamExt},
     };
 
-    use crate::resp;
+    use tokio_util::codec::Framed;
+
+    use crate::{
+        client::mock::mock_connection,
+        error::{Error, RemoteErrorKind},
+        resp::{self, RespCodec, RespVersion},
+    };
 
     #[tokio::test]
     async fn can_connect() {
@@ -127,4 +351,255 @@ mod test {
         };
         assert_eq!(values.len(), 1000);
     }
+
+    fn hello_reply() -> resp::RespValue {
+        resp::RespValue::Map(vec![
+            ("server".into(), "redis".into()),
+            ("version".into(), "7.2.4".into()),
+            ("proto".into(), resp::RespValue::Integer(3)),
+            ("id".into(), resp::RespValue::Integer(10)),
+            ("mode".into(), "standalone".into()),
+            ("role".into(), "master".into()),
+            ("modules".into(), resp::RespValue::Array(vec![])),
+        ])
+    }
+
+    #[tokio::test]
+    async fn hello_negotiates_resp3() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut client = Framed::new(client, RespCodec::default());
+        let mut server = Framed::new(server, RespCodec::resp3());
+
+        let server_f = async move {
+            let request = server.next().await.unwrap().unwrap();
+            assert_eq!(request, resp_array!["HELLO", "3", "AUTH", "user", "secret"]);
+            server.send(hello_reply()).await.unwrap();
+        };
+        let (info, ()) = tokio::join!(
+            super::hello(
+                &mut client,
+                RespVersion::Resp3,
+                Some("user"),
+                Some("secret")
+            ),
+            server_f
+        );
+
+        let info = info.unwrap().unwrap();
+        assert_eq!(info.version, "7.2.4");
+        assert_eq!(info.protocol, RespVersion::Resp3);
+        assert_eq!(info.id, 10);
+        assert_eq!(info.role, "master");
+        assert_eq!(client.codec().protocol(), RespVersion::Resp3);
+    }
+
+    #[tokio::test]
+    async fn hello_falls_back_to_resp2() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut client = Framed::new(client, RespCodec::default());
+        let mut server = Framed::new(server, RespCodec::default());
+
+        let server_f = async move {
+            let request = server.next().await.unwrap().unwrap();
+            assert_eq!(
+                request,
+                resp_array!["HELLO", "3", "AUTH", "default", "secret"]
+            );
+            server
+                .send(resp::RespValue::Error(
+                    "ERR unknown command 'HELLO'".to_string(),
+                ))
+                .await
+                .unwrap();
+
+            let request = server.next().await.unwrap().unwrap();
+            assert_eq!(request, resp_array!["AUTH", "secret"]);
+            server
+                .send(resp::RespValue::SimpleString("OK".to_string()))
+                .await
+                .unwrap();
+        };
+        let (info, ()) = tokio::join!(
+            super::hello(&mut client, RespVersion::Resp3, None, Some("secret")),
+            server_f
+        );
+
+        assert_eq!(info.unwrap(), None);
+        assert_eq!(client.codec().protocol(), RespVersion::Resp2);
+    }
+
+    #[tokio::test]
+    async fn auth_is_sent_first() {
+        let (addr, server_f) = mock_connection(RespCodec::default(), |mut server| async move {
+            let request = server.next().await.unwrap().unwrap();
+            assert_eq!(request, resp_array!["AUTH", "user", "secret"]);
+            server
+                .send(resp::RespValue::Error(
+                    "WRONGPASS invalid username-password pair or user is disabled.".to_string(),
+                ))
+                .await
+                .unwrap();
+        })
+        .await;
+        let result = super::connect_with_options(
+            addr,
+            Some("user"),
+            Some("secret"),
+            RespVersion::Resp2,
+            Some(3),
+        )
+        .await;
+        server_f.await.unwrap();
+
+        let error = result.unwrap_err();
+        assert_eq!(error.remote_kind(), Some(RemoteErrorKind::WrongPass));
+    }
+
+    #[tokio::test]
+    async fn hello_does_not_fall_back_on_auth_failure() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut client = Framed::new(client, RespCodec::default());
+        let mut server = Framed::new(server, RespCodec::default());
+
+        let server_f = async move {
+            let request = server.next().await.unwrap().unwrap();
+            assert_eq!(
+                request,
+                resp_array!["HELLO", "3", "AUTH", "default", "secret"]
+            );
+            server
+                .send(resp::RespValue::Error(
+                    "WRONGPASS invalid username-password pair or user is disabled.".to_string(),
+                ))
+                .await
+                .unwrap();
+        };
+        let (info, ()) = tokio::join!(
+            super::hello(&mut client, RespVersion::Resp3, None, Some("secret")),
+            server_f
+        );
+
+        assert!(matches!(info, Err(Error::Remote(ref e)) if e.starts_with("WRONGPASS")));
+    }
+
+    #[tokio::test]
+    async fn handshake_selects_database_after_auth() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut client = Framed::new(client, RespCodec::default());
+        let mut server = Framed::new(server, RespCodec::default());
+
+        let server_f = async move {
+            let request = server.next().await.unwrap().unwrap();
+            assert_eq!(request, resp_array!["AUTH", "secret"]);
+            server
+                .send(resp::RespValue::SimpleString("OK".to_string()))
+                .await
+                .unwrap();
+
+            let request = server.next().await.unwrap().unwrap();
+            assert_eq!(request, resp_array!["SELECT", "3"]);
+            server
+                .send(resp::RespValue::SimpleString("OK".to_string()))
+                .await
+                .unwrap();
+        };
+        let (info, ()) = tokio::join!(
+            super::handshake(
+                &mut client,
+                None,
+                Some("secret"),
+                RespVersion::Resp2,
+                Some(3)
+            ),
+            server_f
+        );
+
+        assert_eq!(info.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn handshake_selects_database_after_hello() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut client = Framed::new(client, RespCodec::default());
+        let mut server = Framed::new(server, RespCodec::resp3());
+
+        let server_f = async move {
+            let request = server.next().await.unwrap().unwrap();
+            assert_eq!(request, resp_array!["HELLO", "3"]);
+            server.send(hello_reply()).await.unwrap();
+
+            let request = server.next().await.unwrap().unwrap();
+            assert_eq!(request, resp_array!["SELECT", "3"]);
+            server
+                .send(resp::RespValue::Error(
+                    "ERR DB index is out of range".to_string(),
+                ))
+                .await
+                .unwrap();
+        };
+        let (info, ()) = tokio::join!(
+            super::handshake(&mut client, None, None, RespVersion::Resp3, Some(3)),
+            server_f
+        );
+
+        assert!(matches!(info, Err(Error::Remote(ref e)) if e == "ERR DB index is out of range"));
+    }
+
+    #[tokio::test]
+    async fn handshake_requires_ok_from_select() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut client = Framed::new(client, RespCodec::default());
+        let mut server = Framed::new(server, RespCodec::default());
+
+        let server_f = async move {
+            let request = server.next().await.unwrap().unwrap();
+            assert_eq!(request, resp_array!["SELECT", "3"]);
+            server
+                .send(resp::RespValue::SimpleString("QUEUED".to_string()))
+                .await
+                .unwrap();
+        };
+        let (info, ()) = tokio::join!(
+            super::handshake(&mut client, None, None, RespVersion::Resp2, Some(3)),
+            server_f
+        );
+
+        assert!(matches!(
+            info,
+            Err(Error::Resp(_, Some(resp::RespValue::SimpleString(ref s)))) if s == "QUEUED"
+        ));
+    }
+
+    #[tokio::test]
+    async fn split_over_duplex() {
+        let (client, server) = tokio::io::duplex(1024);
+        let (mut sink, mut stream) = super::split(client, RespCodec::default());
+        let mut server = Framed::new(server, RespCodec::default());
+
+        sink.send(resp_array!["PING", "hello"])
+            .await
+            .expect("Cannot send");
+        let request = server.next().await.unwrap().unwrap();
+        assert_eq!(request, resp_array!["PING", "hello"]);
+
+        server.send(resp::RespValue::from("hello")).await.unwrap();
+        let reply = stream.next().await.unwrap().expect("Cannot read reply");
+        assert_eq!(reply, "hello".into());
+
+        drop(server);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn sink_errors_are_redis_errors() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut connection = Framed::new(client, RespCodec::default());
+        drop(server);
+
+        let result: Result<(), Error> = connection.send(resp_array!["PING"]).await;
+        match result {
+            Err(Error::IO(err)) => assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,100 @@
+/*
+ * Copyright 2017-2022 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Mock Redis servers for the client tests.
+
+use std::future::Future;
+use std::net::SocketAddr;
+
+use futures_util::{SinkExt, StreamExt};
+
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::JoinHandle,
+};
+use tokio_util::codec::{Decoder, Framed};
+
+use crate::resp;
+
+/// The server's side of a connection to a mock server
+pub(crate) type MockConnection = Framed<TcpStream, resp::RespCodec>;
+
+/// A server on a random port, the first connection to which is handled by `handler`
+pub(crate) async fn mock_connection<F, Fut>(
+    codec: resp::RespCodec,
+    handler: F,
+) -> (SocketAddr, JoinHandle<Fut::Output>)
+where
+    F: FnOnce(MockConnection) -> Fut + Send + 'static,
+    Fut: Future + Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_f = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        handler(codec.framed(socket)).await
+    });
+    (addr, server_f)
+}
+
+/// A server on a random port, every connection to which is handled by `handler` in a task of its
+/// own, given the number of connections accepted before it.  The tasks are sent to the receiver
+/// as they're spawned, so that a test can check how each connection was handled.
+pub(crate) async fn mock_connections<F, Fut>(
+    codec: resp::RespCodec,
+    mut handler: F,
+) -> (SocketAddr, mpsc::UnboundedReceiver<JoinHandle<Fut::Output>>)
+where
+    F: FnMut(usize, MockConnection) -> Fut + Send + 'static,
+    Fut: Future + Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        for index in 0.. {
+            let (socket, _) = listener.accept().await.unwrap();
+            // The test may not be interested in the tasks
+            let _ = tx.send(tokio::spawn(handler(index, codec.clone().framed(socket))));
+        }
+    });
+    (addr, rx)
+}
+
+/// A server which replies to each request in turn with `replies`, returning the requests
+pub(crate) async fn mock_server(
+    replies: Vec<resp::RespValue>,
+) -> (SocketAddr, JoinHandle<Vec<resp::RespValue>>) {
+    mock_connection(resp::RespCodec::default(), |mut server| async move {
+        let mut requests = Vec::new();
+        for reply in replies {
+            requests.push(server.next().await.unwrap().unwrap());
+            server.send(reply).await.unwrap();
+        }
+        // Keep the connection open until the client has read the replies
+        let _ = server.next().await;
+        requests
+    })
+    .await
+}
+
+/// Reply to each request, e.g. an `ECHO`, with its first argument until the connection is closed
+pub(crate) async fn echo(mut server: MockConnection) {
+    while let Some(Ok(request)) = server.next().await {
+        if server.send(request[1].clone()).await.is_err() {
+            break;
+        }
+    }
+}
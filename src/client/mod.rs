@@ -13,22 +13,28 @@
 //! This contains three main functions that return three specific types of client:
 //!
 //! * `connect` returns a pair of `Stream` and `Sink`, clients can write RESP messages to the
-//! `Sink` and read RESP messages from the `Stream`. Pairing requests to responses is up to the
-//! client.  This is intended to be a low-level interface from which more user-friendly interfaces
-//! can be built.
+//!   `Sink` and read RESP messages from the `Stream`. Pairing requests to responses is up to the
+//!   client.  This is intended to be a low-level interface from which more user-friendly interfaces
+//!   can be built.
 //! * `paired_connect` is used for most of the standard Redis commands, where one request results
-//! in one response.
+//!   in one response.
 //! * `pubsub_connect` is used for Redis's PUBSUB functionality.
+//!
+//! A `Pool` of paired connections is also available, to run commands concurrently.
 
 pub mod connect;
 #[macro_use]
 pub mod paired;
 mod builder;
+#[cfg(test)]
+mod mock;
+pub mod pool;
 pub mod pubsub;
 
 pub use self::{
     builder::ConnectionBuilder,
     connect::connect,
-    paired::{paired_connect, PairedConnection},
-    pubsub::{pubsub_connect, PubsubConnection},
+    paired::{paired_connect, PairedConnection, Pipeline, PushStream},
+    pool::{Pool, PoolBuilder},
+    pubsub::{pubsub_connect, KeyspaceEvent, KeyspaceEventKind, Message, PubsubConnection},
 };
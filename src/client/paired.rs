@@ -15,22 +15,29 @@ use std::mem;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use futures_channel::{mpsc, oneshot};
 use futures_sink::Sink;
-use futures_util::{future::TryFutureExt, stream::StreamExt};
+use futures_util::{
+    future::TryFutureExt,
+    stream::{Stream, StreamExt},
+};
 
-use tokio::net::ToSocketAddrs;
+use tokio::{
+    net::ToSocketAddrs,
+    time::{sleep_until, Instant, Sleep},
+};
 
 use super::{
-    connect::{connect_with_auth, RespConnection},
+    connect::{connect_with_options, RespConnection},
     ConnectionBuilder,
 };
 
 use crate::{
     error,
     reconnect::{reconnect, Reconnect},
-    resp,
+    resp::{self, FromResp},
 };
 
 /// The state of sending messages to a Redis server
@@ -57,7 +64,14 @@ enum ReceiveStatus {
 
 type CommandResult = Result<resp::RespValue, error::Error>;
 type Responder = oneshot::Sender<CommandResult>;
-type SendPayload = (resp::RespValue, Responder);
+
+/// The messages sent to the `PairedConnectionInner`
+enum SendPayload {
+    /// A single command
+    Single(resp::RespValue, Responder),
+    /// Several commands, these are written to the connection together before it's flushed
+    Batch(Vec<(resp::RespValue, Responder)>),
+}
 
 // /// The PairedConnectionInner is a spawned future that is responsible for pairing commands and
 // /// results onto a `RespConnection` that is otherwise unpaired
@@ -68,6 +82,10 @@ struct PairedConnectionInner {
     out_rx: mpsc::UnboundedReceiver<SendPayload>,
     /// The queue of waiting oneshot's for commands sent but results not yet received
     waiting: VecDeque<Responder>,
+    /// Commands from a batch that have been received but not yet sent
+    pending: VecDeque<resp::RespValue>,
+    /// Where to send RESP3 push messages, if anywhere, see `PushStream`
+    pushes: Option<mpsc::UnboundedSender<resp::RespValue>>,
 
     /// The status of the underlying connection
     send_status: SendStatus,
@@ -76,24 +94,40 @@ struct PairedConnectionInner {
 impl PairedConnectionInner {
     fn new(
         con: RespConnection,
-        out_rx: mpsc::UnboundedReceiver<(resp::RespValue, Responder)>,
+        out_rx: mpsc::UnboundedReceiver<SendPayload>,
+        pushes: Option<mpsc::UnboundedSender<resp::RespValue>>,
     ) -> Self {
         PairedConnectionInner {
             connection: con,
             out_rx,
             waiting: VecDeque::new(),
+            pending: VecDeque::new(),
+            pushes,
             send_status: SendStatus::Ok,
         }
     }
 
+    /// Out-of-band RESP3 messages aren't replies to any command, so must not be paired with one
+    fn push(&mut self, push: resp::RespValue) {
+        if let Some(pushes) = &self.pushes {
+            if pushes.unbounded_send(push).is_ok() {
+                return;
+            }
+            log::debug!("PushStream dropped, push messages will be ignored");
+            self.pushes = None;
+        } else {
+            log::warn!("Ignoring unexpected push message: {:?}", push);
+        }
+    }
+
     fn impl_start_send(
         &mut self,
         cx: &mut Context,
         msg: resp::RespValue,
     ) -> Result<bool, error::Error> {
-        match Pin::new(&mut self.connection).poll_ready(cx) {
+        match Sink::<resp::RespValue>::poll_ready(Pin::new(&mut self.connection), cx) {
             Poll::Ready(Ok(())) => (),
-            Poll::Ready(Err(e)) => return Err(e.into()),
+            Poll::Ready(Err(e)) => return Err(e),
             Poll::Pending => {
                 self.send_status = SendStatus::Full(msg);
                 return Ok(false);
@@ -115,16 +149,26 @@ impl PairedConnectionInner {
                 return Ok(false);
             }
             SendStatus::Full(msg) => msg,
-            SendStatus::Ok => match self.out_rx.poll_next_unpin(cx) {
-                Poll::Ready(Some((msg, tx))) => {
-                    self.waiting.push_back(tx);
-                    msg
-                }
-                Poll::Ready(None) => {
-                    self.send_status = SendStatus::End;
-                    return Ok(false);
-                }
-                Poll::Pending => return Ok(false),
+            SendStatus::Ok => match self.pending.pop_front() {
+                Some(msg) => msg,
+                None => match self.out_rx.poll_next_unpin(cx) {
+                    Poll::Ready(Some(SendPayload::Single(msg, tx))) => {
+                        self.waiting.push_back(tx);
+                        msg
+                    }
+                    Poll::Ready(Some(SendPayload::Batch(batch))) => {
+                        for (msg, tx) in batch {
+                            self.waiting.push_back(tx);
+                            self.pending.push_back(msg);
+                        }
+                        return Ok(true);
+                    }
+                    Poll::Ready(None) => {
+                        self.send_status = SendStatus::End;
+                        return Ok(false);
+                    }
+                    Poll::Pending => return Ok(false),
+                },
             },
         };
 
@@ -132,7 +176,7 @@ impl PairedConnectionInner {
     }
 
     fn poll_complete(&mut self, cx: &mut Context) -> Result<(), error::Error> {
-        let _ = Pin::new(&mut self.connection).poll_flush(cx)?;
+        let _ = Sink::<resp::RespValue>::poll_flush(Pin::new(&mut self.connection), cx)?;
         Ok(())
     }
 
@@ -144,10 +188,16 @@ impl PairedConnectionInner {
         }
         match self.connection.poll_next_unpin(cx) {
             Poll::Ready(None) => Err(error::unexpected("Connection to Redis closed unexpectedly")),
+            Poll::Ready(Some(Ok(push @ resp::RespValue::Push(_)))) => {
+                self.push(push);
+                Ok(ReceiveStatus::ReadyMore)
+            }
             Poll::Ready(Some(Ok(msg))) => {
                 let tx = match self.waiting.pop_front() {
                     Some(tx) => tx,
-                    None => panic!("Received unexpected message: {:?}", msg),
+                    None => {
+                        return Err(error::resp("Received a reply with no command waiting", msg));
+                    }
                 };
                 let _ = tx.send(Ok(msg));
                 Ok(ReceiveStatus::ReadyMore)
@@ -158,6 +208,9 @@ impl PairedConnectionInner {
     }
 
     fn handle_error(&mut self, e: &error::Error) {
+        // Closed first, so that `PairedConnection::is_connected` is already `false` for anyone
+        // receiving the error
+        self.out_rx.close();
         for tx in self.waiting.drain(..) {
             let _ = tx.send(Err(error::internal(format!(
                 "Failed due to underlying failure: {}",
@@ -210,28 +263,66 @@ async fn inner_conn_fn(
     addr: impl ToSocketAddrs,
     username: Option<Arc<str>>,
     password: Option<Arc<str>>,
+    protocol: resp::RespVersion,
+    database: Option<u32>,
+    pushes: Option<mpsc::UnboundedSender<resp::RespValue>>,
 ) -> Result<mpsc::UnboundedSender<SendPayload>, error::Error> {
     let username = username.as_ref().map(|u| u.as_ref());
     let password = password.as_ref().map(|p| p.as_ref());
-    let connection = connect_with_auth(&addr, username, password).await?;
+    let (connection, info) =
+        connect_with_options(&addr, username, password, protocol, database).await?;
+    if let Some(info) = info {
+        log::debug!(
+            "Connected to {} {}: {:?}",
+            info.server,
+            info.version,
+            info.protocol
+        );
+    }
     let (out_tx, out_rx) = mpsc::unbounded();
-    let paired_connection_inner = PairedConnectionInner::new(connection, out_rx);
+    let paired_connection_inner = PairedConnectionInner::new(connection, out_rx, pushes);
     tokio::spawn(paired_connection_inner);
     Ok(out_tx)
 }
 
 impl ConnectionBuilder {
     pub fn paired_connect(&self) -> impl Future<Output = Result<PairedConnection, error::Error>> {
+        self.paired_connect_inner(None)
+    }
+
+    /// Connect as with `paired_connect`, also returning a `PushStream` of the RESP3 push messages
+    /// received by the connection, e.g. the invalidation messages of client-side caching.
+    pub fn paired_connect_with_pushes(
+        &self,
+    ) -> impl Future<Output = Result<(PairedConnection, PushStream), error::Error>> {
+        let (pushes_tx, pushes_rx) = mpsc::unbounded();
+        self.paired_connect_inner(Some(pushes_tx))
+            .map_ok(|con| (con, PushStream { pushes: pushes_rx }))
+    }
+
+    fn paired_connect_inner(
+        &self,
+        pushes: Option<mpsc::UnboundedSender<resp::RespValue>>,
+    ) -> impl Future<Output = Result<PairedConnection, error::Error>> {
         let addr = self.addr.clone();
         let username = self.username.clone();
         let password = self.password.clone();
+        let protocol = self.protocol;
+        let database = self.database;
 
         let work_fn = |con: &mpsc::UnboundedSender<SendPayload>, act| {
             con.unbounded_send(act).map_err(|e| e.into())
         };
 
         let conn_fn = move || {
-            let con_f = inner_conn_fn(addr.clone(), username.clone(), password.clone());
+            let con_f = inner_conn_fn(
+                addr.clone(),
+                username.clone(),
+                password.clone(),
+                protocol,
+                database,
+                pushes.clone(),
+            );
             Box::pin(con_f) as Pin<Box<dyn Future<Output = Result<_, error::Error>> + Send + Sync>>
         };
 
@@ -274,20 +365,86 @@ impl PairedConnection {
     where
         T: resp::FromResp + Unpin,
     {
-        match &msg {
-            resp::RespValue::Array(_) => (),
-            _ => {
-                return SendFuture::new(error::internal("Command must be a RespValue::Array"));
-            }
+        if !matches!(msg, resp::RespValue::Array(_)) {
+            return SendFuture::new(error::resp("Command must be a RespValue::Array", msg));
         }
 
         let (tx, rx) = oneshot::channel();
-        match self.out_tx_c.do_work((msg, tx)) {
+        match self.out_tx_c.do_work(SendPayload::Single(msg, tx)) {
             Ok(()) => SendFuture::new(rx),
             Err(e) => SendFuture::new(e),
         }
     }
 
+    /// Sends all the commands in a `Pipeline` to Redis.
+    ///
+    /// The commands are written to the connection together, one after another, and then flushed;
+    /// so they are sent without waiting for any of the replies.  As with `send` the commands are
+    /// queued when this is called, and not when the future is realised, so ordering is maintained
+    /// with respect to any other commands sent on this connection.
+    ///
+    /// The future resolves to the replies in the same order as the commands in the pipeline.  Each
+    /// command succeeds or fails independently, e.g. one command receiving an error reply from
+    /// Redis does not stop any of the others from being run, so there is a separate `Result` for
+    /// each.  The future itself only fails if the pipeline couldn't be sent at all.
+    pub fn send_pipeline(&self, pipeline: Pipeline) -> PipelineFuture {
+        if let Some(msg) = pipeline
+            .commands
+            .iter()
+            .find(|msg| !matches!(msg, resp::RespValue::Array(_)))
+        {
+            return PipelineFuture::new(error::resp(
+                "Command must be a RespValue::Array",
+                msg.clone(),
+            ));
+        }
+
+        let mut receivers = Vec::with_capacity(pipeline.commands.len());
+        let batch = pipeline
+            .commands
+            .into_iter()
+            .map(|msg| {
+                let (tx, rx) = oneshot::channel();
+                receivers.push(rx);
+                (msg, tx)
+            })
+            .collect();
+        match self.out_tx_c.do_work(SendPayload::Batch(batch)) {
+            Ok(()) => PipelineFuture::new(receivers),
+            Err(e) => PipelineFuture::new(e),
+        }
+    }
+
+    /// Sends a command to Redis, as with `send`, but the returned future fails with
+    /// `error::Error::Timeout` if the reply hasn't been received within `timeout` of this being
+    /// called.
+    ///
+    /// A command that times out has still been sent, it may still be run by Redis, and its reply
+    /// will be read and discarded when it does arrive.  This is so that replies to any subsequent
+    /// commands on the connection are still paired correctly, the connection remains usable.
+    pub fn send_timeout<T>(&self, msg: resp::RespValue, timeout: Duration) -> SendFuture<T>
+    where
+        T: resp::FromResp + Unpin,
+    {
+        let mut send_f = self.send(msg);
+        send_f.deadline = Some(Deadline::At(Instant::now() + timeout));
+        send_f
+    }
+
+    /// Whether the connection to Redis is currently established.  A connection that has failed
+    /// isn't re-established until the next command is sent, until then this is `false`.
+    pub fn is_connected(&self) -> bool {
+        self.out_tx_c.is_connected(|out_tx| !out_tx.is_closed())
+    }
+
+    /// Start a transaction, see `Transaction`.
+    pub fn transaction(&self) -> Transaction {
+        Transaction {
+            connection: self.clone(),
+            pipeline: Pipeline::new(),
+        }
+    }
+
     pub fn send_and_forget(&self, msg: resp::RespValue) {
         let send_f = self.send::<resp::RespValue>(msg);
         let forget_f = async {
@@ -316,8 +473,190 @@ impl From<error::Error> for SendFutureType {
     }
 }
 
+/// The RESP3 push messages received by a `PairedConnection`, see
+/// `ConnectionBuilder::paired_connect_with_pushes`.
+///
+/// Push messages are not replies to commands, so are never returned by `send`; without a
+/// `PushStream` they are discarded.  Each item is a `RespValue::Push`.  The stream continues
+/// across reconnections, and ends once the connection has been dropped.
+pub struct PushStream {
+    pushes: mpsc::UnboundedReceiver<resp::RespValue>,
+}
+
+impl Stream for PushStream {
+    type Item = resp::RespValue;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.get_mut().pushes.poll_next_unpin(cx)
+    }
+}
+
+/// A sequence of commands to be sent to Redis together, see `PairedConnection::send_pipeline`.
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    commands: Vec<resp::RespValue>,
+}
+
+impl Pipeline {
+    /// An empty pipeline
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    /// Add a command to the end of the pipeline
+    pub fn add(&mut self, msg: resp::RespValue) -> &mut Self {
+        self.commands.push(msg);
+        self
+    }
+
+    /// The number of commands in the pipeline
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Whether the pipeline has no commands
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+/// A `MULTI`/`EXEC` transaction.
+///
+/// Commands are collected until `exec` is called, only then are `MULTI`, the commands, and `EXEC`
+/// sent together as a pipeline.  This ensures that no other commands sent on the same connection
+/// are run as part of the transaction.  Nothing is sent if the transaction is discarded.
+///
+/// Keys can be watched by sending `WATCH` on the connection before calling `exec`, if any of them
+/// are modified the transaction is aborted and `exec` returns `None`.  As `WATCH` applies to the
+/// whole connection this is best done on a connection that isn't shared.
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    connection: PairedConnection,
+    pipeline: Pipeline,
+}
+
+impl Transaction {
+    /// Add a command to the transaction
+    pub fn add(&mut self, msg: resp::RespValue) -> &mut Self {
+        self.pipeline.add(msg);
+        self
+    }
+
+    /// Abandon the transaction without running any of the commands
+    pub fn discard(self) {}
+
+    /// Run the transaction.
+    ///
+    /// Returns the replies to each of the commands, or `None` if the transaction was aborted,
+    /// i.e. the reply to `EXEC` was nil.  If any of the commands could not be queued, e.g. because
+    /// of a syntax error, Redis aborts the whole transaction and the error is returned.  Errors
+    /// from individual commands while the transaction runs are included in the replies, see the
+    /// `FromResp` implementation of `Result` to convert them.
+    pub fn exec(
+        self,
+    ) -> impl Future<Output = Result<Option<Vec<resp::RespValue>>, error::Error>> + Send {
+        let mut pipeline = Pipeline::new();
+        pipeline.add(resp_array!["MULTI"]);
+        pipeline.commands.extend(self.pipeline.commands);
+        pipeline.add(resp_array!["EXEC"]);
+        let pipeline_f = self.connection.send_pipeline(pipeline);
+
+        async move {
+            let mut results = pipeline_f.await?.into_iter();
+            let exec = results.next_back();
+            for queued in results {
+                <()>::from_resp(queued?)?;
+            }
+            match exec {
+                Some(Ok(resp::RespValue::Array(replies))) => Ok(Some(replies)),
+                Some(Ok(resp::RespValue::Nil)) => Ok(None),
+                Some(Ok(other)) => Err(error::resp("Unexpected reply to EXEC", other)),
+                Some(Err(e)) => Err(e),
+                None => Err(error::unexpected("No reply to EXEC")),
+            }
+        }
+    }
+}
+
+enum PipelineFutureType {
+    Wait(Vec<oneshot::Receiver<CommandResult>>, Vec<CommandResult>),
+    Error(Option<error::Error>),
+}
+
+impl From<Vec<oneshot::Receiver<CommandResult>>> for PipelineFutureType {
+    fn from(from: Vec<oneshot::Receiver<CommandResult>>) -> Self {
+        let results = Vec::with_capacity(from.len());
+        Self::Wait(from, results)
+    }
+}
+
+impl From<error::Error> for PipelineFutureType {
+    fn from(e: error::Error) -> Self {
+        Self::Error(Some(e))
+    }
+}
+
+/// The future returned by `PairedConnection::send_pipeline`
+pub struct PipelineFuture {
+    send_type: PipelineFutureType,
+}
+
+impl PipelineFuture {
+    fn new(send_type: impl Into<PipelineFutureType>) -> Self {
+        Self {
+            send_type: send_type.into(),
+        }
+    }
+}
+
+impl Future for PipelineFuture {
+    type Output = Result<Vec<CommandResult>, error::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.get_mut().send_type {
+            PipelineFutureType::Error(ref mut e) => match e.take() {
+                Some(e) => Poll::Ready(Err(e)),
+                None => panic!("Future polled several times after completion"),
+            },
+            PipelineFutureType::Wait(ref mut receivers, ref mut results) => {
+                while let Some(rx) = receivers.get_mut(results.len()) {
+                    let result = match Pin::new(rx).poll(cx) {
+                        Poll::Ready(Ok(result)) => result.and_then(resp::FromResp::from_resp),
+                        Poll::Ready(Err(_)) => Err(error::internal(
+                            "Connection closed before response received",
+                        )),
+                        Poll::Pending => return Poll::Pending,
+                    };
+                    results.push(result);
+                }
+                Poll::Ready(Ok(mem::take(results)))
+            }
+        }
+    }
+}
+
+/// The deadline of a `SendFuture`, the timer is only created when the future is first polled so
+/// that futures can be created outside of the Tokio runtime
+enum Deadline {
+    At(Instant),
+    Sleep(Pin<Box<Sleep>>),
+}
+
+impl Deadline {
+    fn poll_expired(&mut self, cx: &mut Context<'_>) -> bool {
+        if let Deadline::At(instant) = self {
+            *self = Deadline::Sleep(Box::pin(sleep_until(*instant)));
+        }
+        match self {
+            Deadline::Sleep(sleep) => sleep.as_mut().poll(cx).is_ready(),
+            Deadline::At(_) => unreachable!(),
+        }
+    }
+}
+
 pub struct SendFuture<T> {
     send_type: SendFutureType,
+    deadline: Option<Deadline>,
     _phantom: PhantomData<T>,
 }
 
@@ -325,6 +664,7 @@ impl<T> SendFuture<T> {
     fn new(send_type: impl Into<SendFutureType>) -> Self {
         Self {
             send_type: send_type.into(),
+            deadline: None,
             _phantom: Default::default(),
         }
     }
@@ -337,7 +677,8 @@ where
     type Output = Result<T, error::Error>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match self.get_mut().send_type {
+        let this = self.get_mut();
+        match this.send_type {
             SendFutureType::Error(ref mut e) => match e.take() {
                 Some(e) => Poll::Ready(Err(e)),
                 None => panic!("Future polled several times after completion"),
@@ -348,7 +689,18 @@ where
                 Poll::Ready(Err(_)) => Poll::Ready(Err(error::internal(
                     "Connection closed before response received",
                 ))),
-                Poll::Pending => Poll::Pending,
+                Poll::Pending => {
+                    let expired = match this.deadline {
+                        Some(ref mut deadline) => deadline.poll_expired(cx),
+                        None => false,
+                    };
+                    if expired {
+                        this.send_type = SendFutureType::Error(None);
+                        Poll::Ready(Err(error::Error::Timeout))
+                    } else {
+                        Poll::Pending
+                    }
+                }
             },
         }
     }
@@ -356,7 +708,20 @@ where
 
 #[cfg(test)]
 mod test {
-    use super::ConnectionBuilder;
+    use std::time::Duration;
+
+    use futures_util::{SinkExt, StreamExt};
+
+    use tokio::net::TcpStream;
+    use tokio_util::codec::Decoder;
+
+    use super::{ConnectionBuilder, PairedConnectionInner, Pipeline};
+
+    use crate::{
+        client::mock::{mock_connection, mock_server},
+        error::Error,
+        resp,
+    };
 
     #[tokio::test]
     async fn can_paired_connect() {
@@ -424,4 +789,240 @@ mod test {
         // Expecting an error as these aren't the correct username/password
         assert!(connection_result.is_err());
     }
+
+    #[tokio::test]
+    async fn pipeline_replies_in_order() {
+        let (addr, server_f) = mock_server(vec![
+            resp::RespValue::SimpleString("OK".into()),
+            resp::RespValue::Error(
+                "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
+            ),
+            resp::RespValue::from("123"),
+        ])
+        .await;
+
+        let connection = super::paired_connect(addr.to_string())
+            .await
+            .expect("Cannot establish connection");
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .add(resp_array!["SET", "X", "123"])
+            .add(resp_array!["LPUSH", "X", "1"])
+            .add(resp_array!["GET", "X"]);
+        assert_eq!(pipeline.len(), 3);
+
+        let results = connection
+            .send_pipeline(pipeline)
+            .await
+            .expect("Cannot send pipeline");
+        drop(connection);
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().as_str(), Some("OK"));
+        assert!(matches!(results[1], Err(Error::Remote(ref e)) if e.starts_with("WRONGTYPE")));
+        assert_eq!(results[2].as_ref().unwrap().as_str(), Some("123"));
+
+        let requests = server_f.await.unwrap();
+        assert_eq!(requests[0], resp_array!["SET", "X", "123"]);
+        assert_eq!(requests[2], resp_array!["GET", "X"]);
+    }
+
+    #[tokio::test]
+    async fn pipeline_rejects_invalid_commands() {
+        let (addr, _server_f) = mock_server(vec![]).await;
+
+        let connection = super::paired_connect(addr.to_string())
+            .await
+            .expect("Cannot establish connection");
+        let mut pipeline = Pipeline::new();
+        pipeline.add(resp_array!["PING"]).add("PING".into());
+        let result = connection.send_pipeline(pipeline).await;
+        assert!(matches!(result, Err(Error::Resp(_, _))));
+
+        // The same error as for a single command
+        let result: Result<String, _> = connection.send("PING".into()).await;
+        assert!(matches!(result, Err(Error::Resp(_, Some(_)))));
+    }
+
+    #[tokio::test]
+    async fn unexpected_reply_is_an_error() {
+        let (addr, _server_f) =
+            mock_connection(resp::RespCodec::default(), |mut server| async move {
+                server
+                    .send(resp::RespValue::from("surprise"))
+                    .await
+                    .unwrap();
+                let _ = server.next().await;
+            })
+            .await;
+
+        let socket = TcpStream::connect(addr).await.unwrap();
+        let (out_tx, out_rx) = futures_channel::mpsc::unbounded();
+        let inner =
+            PairedConnectionInner::new(resp::RespCodec::default().framed(socket), out_rx, None);
+        // The connection fails, rather than panicking, and isn't used for any more commands
+        tokio::time::timeout(Duration::from_secs(10), inner)
+            .await
+            .expect("Connection didn't fail");
+        assert!(out_tx.is_closed());
+    }
+
+    #[tokio::test]
+    async fn push_messages_are_not_replies() {
+        let invalidate = resp::RespValue::Push(vec!["invalidate".into(), resp_array!["key"]]);
+        let push = invalidate.clone();
+        // A push arrives between each request and its reply
+        let server = move || {
+            let push = push.clone();
+            mock_connection(resp::RespCodec::resp3(), |mut server| async move {
+                while let Some(Ok(request)) = server.next().await {
+                    server.send(push.clone()).await.unwrap();
+                    server.send(request[1].clone()).await.unwrap();
+                }
+            })
+        };
+
+        let (addr, _server_f) = server().await;
+        let (connection, mut pushes) = ConnectionBuilder::new(addr.to_string())
+            .unwrap()
+            .paired_connect_with_pushes()
+            .await
+            .expect("Cannot establish connection");
+        for value in ["a", "b"] {
+            let reply: String = connection.send(resp_array!["ECHO", value]).await.unwrap();
+            assert_eq!(reply, value);
+            assert_eq!(pushes.next().await, Some(invalidate.clone()));
+        }
+
+        // Without a push stream the pushes are discarded
+        drop(pushes);
+        let (addr, _server_f) = server().await;
+        let connection = ConnectionBuilder::new(addr.to_string())
+            .unwrap()
+            .paired_connect()
+            .await
+            .expect("Cannot establish connection");
+        for value in ["c", "d"] {
+            let reply: String = connection.send(resp_array!["ECHO", value]).await.unwrap();
+            assert_eq!(reply, value);
+        }
+    }
+
+    #[tokio::test]
+    async fn send_timeout_keeps_replies_in_order() {
+        let (addr, _server_f) =
+            mock_connection(resp::RespCodec::default(), |mut server| async move {
+                // The first command isn't replied to until after it has timed out, i.e. once the
+                // second command has been received
+                let _ = server.next().await.unwrap().unwrap();
+                let _ = server.next().await.unwrap().unwrap();
+                server.send(resp::RespValue::from("slow")).await.unwrap();
+                server.send(resp::RespValue::from("fast")).await.unwrap();
+                let _ = server.next().await;
+            })
+            .await;
+
+        let connection = super::paired_connect(addr.to_string())
+            .await
+            .expect("Cannot establish connection");
+
+        // With the clock paused, time only moves on once the runtime is idle, i.e. while waiting
+        // for the reply which won't arrive
+        tokio::time::pause();
+        let start = tokio::time::Instant::now();
+        let result: Result<String, _> = connection
+            .send_timeout(resp_array!["GET", "SLOW"], Duration::from_secs(10))
+            .await;
+        assert!(matches!(result, Err(Error::Timeout)));
+        assert!(start.elapsed() >= Duration::from_secs(10));
+
+        // The reply to the timed out command is discarded, it isn't mistaken for this one's
+        let result: String = connection
+            .send(resp_array!["GET", "FAST"])
+            .await
+            .expect("Cannot read result");
+        assert_eq!(result, "fast");
+    }
+
+    #[tokio::test]
+    async fn transaction_exec() {
+        let queued = || resp::RespValue::SimpleString("QUEUED".into());
+        let (addr, server_f) = mock_server(vec![
+            resp::RespValue::SimpleString("OK".into()),
+            queued(),
+            queued(),
+            resp_array![
+                resp::RespValue::SimpleString("OK".into()),
+                resp::RespValue::Integer(2)
+            ],
+        ])
+        .await;
+
+        let connection = super::paired_connect(addr.to_string())
+            .await
+            .expect("Cannot establish connection");
+        let mut transaction = connection.transaction();
+        transaction
+            .add(resp_array!["SET", "X", "1"])
+            .add(resp_array!["INCR", "X"]);
+        let replies = transaction
+            .exec()
+            .await
+            .expect("Cannot run transaction")
+            .expect("Transaction aborted");
+        drop(connection);
+
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0], resp::RespValue::ok());
+        assert_eq!(replies[1], resp::RespValue::Integer(2));
+
+        let requests = server_f.await.unwrap();
+        assert_eq!(
+            requests,
+            vec![
+                resp_array!["MULTI"],
+                resp_array!["SET", "X", "1"],
+                resp_array!["INCR", "X"],
+                resp_array!["EXEC"]
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn transaction_aborted() {
+        let (addr, _server_f) = mock_server(vec![
+            resp::RespValue::SimpleString("OK".into()),
+            resp::RespValue::Error("ERR unknown command 'NOTACOMMAND'".into()),
+            resp::RespValue::Error(
+                "EXECABORT Transaction discarded because of previous errors.".into(),
+            ),
+        ])
+        .await;
+
+        let connection = super::paired_connect(addr.to_string())
+            .await
+            .expect("Cannot establish connection");
+        let mut transaction = connection.transaction();
+        transaction.add(resp_array!["NOTACOMMAND"]);
+        let result = transaction.exec().await;
+        assert!(matches!(result, Err(Error::Remote(ref e)) if e.starts_with("ERR unknown")));
+    }
+
+    #[tokio::test]
+    async fn transaction_watch_aborted() {
+        let (addr, _server_f) = mock_server(vec![
+            resp::RespValue::SimpleString("OK".into()),
+            resp::RespValue::SimpleString("QUEUED".into()),
+            resp::RespValue::Nil,
+        ])
+        .await;
+
+        let connection = super::paired_connect(addr.to_string())
+            .await
+            .expect("Cannot establish connection");
+        let mut transaction = connection.transaction();
+        transaction.add(resp_array!["INCR", "X"]);
+        let result = transaction.exec().await.expect("Cannot run transaction");
+        assert_eq!(result, None);
+    }
 }
@@ -0,0 +1,390 @@
+/*
+ * Copyright 2017-2022 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! A pool of `PairedConnection`s, to spread commands across several connections.
+
+use std::ops::Deref;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use std::time::Duration;
+
+use futures_util::future;
+
+use tokio::time::timeout;
+
+use super::{ConnectionBuilder, PairedConnection};
+
+use crate::{error, resp};
+
+const DEFAULT_MIN_SIZE: usize = 1;
+const DEFAULT_MAX_SIZE: usize = 8;
+const DEFAULT_CHECKOUT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Configuration for a `Pool`
+#[derive(Debug, Clone)]
+pub struct PoolBuilder {
+    connection: ConnectionBuilder,
+    min_size: usize,
+    max_size: usize,
+    checkout_timeout: Duration,
+}
+
+impl PoolBuilder {
+    /// A pool of connections configured by the given `ConnectionBuilder`, by default the pool has
+    /// between one and eight connections.
+    pub fn new(connection: ConnectionBuilder) -> Self {
+        PoolBuilder {
+            connection,
+            min_size: DEFAULT_MIN_SIZE,
+            max_size: DEFAULT_MAX_SIZE,
+            checkout_timeout: DEFAULT_CHECKOUT_TIMEOUT,
+        }
+    }
+
+    /// The number of connections established when the pool is created
+    pub fn min_size(&mut self, min_size: usize) -> &mut Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// The maximum number of connections, this must be at least one
+    pub fn max_size(&mut self, max_size: usize) -> &mut Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// How long `Pool::get` waits for a new connection to be established, the default is ten
+    /// seconds
+    pub fn checkout_timeout(&mut self, checkout_timeout: Duration) -> &mut Self {
+        self.checkout_timeout = checkout_timeout;
+        self
+    }
+
+    /// Create the pool, the future resolves once the minimum number of connections have been
+    /// established.
+    pub async fn build(&self) -> Result<Pool, error::Error> {
+        if self.max_size == 0 || self.min_size > self.max_size {
+            return Err(error::internal(format!(
+                "Invalid pool size, minimum: {}, maximum: {}",
+                self.min_size, self.max_size
+            )));
+        }
+
+        let connections =
+            future::try_join_all((0..self.min_size).map(|_| self.connection.paired_connect()))
+                .await?;
+        let slots = connections
+            .into_iter()
+            .map(|connection| Slot {
+                connection,
+                in_flight: Arc::default(),
+            })
+            .collect();
+
+        Ok(Pool {
+            inner: Arc::new(PoolInner {
+                connection: self.connection.clone(),
+                max_size: self.max_size,
+                checkout_timeout: self.checkout_timeout,
+                state: Mutex::new(PoolState {
+                    slots,
+                    connecting: 0,
+                }),
+            }),
+        })
+    }
+}
+
+struct Slot {
+    connection: PairedConnection,
+    /// The number of `PooledConnection`s currently checked-out for this connection
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Slot {
+    fn checkout(&self) -> PooledConnection {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        PooledConnection {
+            connection: self.connection.clone(),
+            in_flight: self.in_flight.clone(),
+        }
+    }
+}
+
+struct PoolState {
+    slots: Vec<Slot>,
+    /// The number of connections currently being established
+    connecting: usize,
+}
+
+struct PoolInner {
+    connection: ConnectionBuilder,
+    max_size: usize,
+    checkout_timeout: Duration,
+    state: Mutex<PoolState>,
+}
+
+/// A pool of `PairedConnection`s.
+///
+/// Each `PairedConnection` can already be shared, with commands being pipelined, but commands on
+/// a single connection are run one at a time.  A pool allows commands to be run concurrently on
+/// several connections instead.
+///
+/// Connections are checked-out with `get`, which returns the least-busy connection, i.e. the one
+/// with the fewest check-outs; a new connection is established instead if none are idle and the
+/// pool isn't at its maximum size.  A connection is never checked-out exclusively, so `get` never
+/// waits for another user to finish.
+///
+/// Connections that fail are removed from the pool by `send`, or explicitly with `discard`, they
+/// are replaced the next time a connection is needed.
+#[derive(Clone)]
+pub struct Pool {
+    inner: Arc<PoolInner>,
+}
+
+impl Pool {
+    /// The number of connections in the pool
+    pub fn size(&self) -> usize {
+        self.inner.state.lock().expect("Poisoned lock").slots.len()
+    }
+
+    /// Check-out a connection, see the `Pool` documentation for how this is chosen.
+    ///
+    /// If a new connection is needed but can't be established, the least-busy existing connection
+    /// is returned instead.  Only if the pool is empty does this fail, with `error::Error::Timeout`
+    /// if the connection isn't established within the pool's checkout timeout.
+    pub async fn get(&self) -> Result<PooledConnection, error::Error> {
+        {
+            let mut state = self.inner.state.lock().expect("Poisoned lock");
+            let least_busy = state
+                .slots
+                .iter()
+                .min_by_key(|slot| slot.in_flight.load(Ordering::SeqCst));
+            match least_busy {
+                Some(slot) if slot.in_flight.load(Ordering::SeqCst) == 0 => {
+                    return Ok(slot.checkout())
+                }
+                Some(slot) if state.slots.len() + state.connecting >= self.inner.max_size => {
+                    return Ok(slot.checkout())
+                }
+                _ => state.connecting += 1,
+            }
+        }
+
+        let connection_r = timeout(
+            self.inner.checkout_timeout,
+            self.inner.connection.paired_connect(),
+        )
+        .await;
+
+        let mut state = self.inner.state.lock().expect("Poisoned lock");
+        state.connecting -= 1;
+        let connection = match connection_r.unwrap_or(Err(error::Error::Timeout)) {
+            Ok(connection) => connection,
+            Err(e) => {
+                let least_busy = state
+                    .slots
+                    .iter()
+                    .min_by_key(|slot| slot.in_flight.load(Ordering::SeqCst));
+                return match least_busy {
+                    Some(slot) => {
+                        log::warn!(
+                            "Cannot add a connection to the pool, sharing one instead: {}",
+                            e
+                        );
+                        Ok(slot.checkout())
+                    }
+                    None => Err(e),
+                };
+            }
+        };
+        let slot = Slot {
+            connection,
+            in_flight: Arc::default(),
+        };
+        let pooled = slot.checkout();
+        state.slots.push(slot);
+        Ok(pooled)
+    }
+
+    /// Remove a connection from the pool, e.g. because it has failed.  Any other users of the
+    /// connection are unaffected.
+    pub fn discard(&self, connection: &PooledConnection) {
+        let mut state = self.inner.state.lock().expect("Poisoned lock");
+        state
+            .slots
+            .retain(|slot| !Arc::ptr_eq(&slot.in_flight, &connection.in_flight));
+    }
+
+    /// Send a command on a connection from the pool, see `PairedConnection::send`.
+    ///
+    /// If the command fails and the connection is no longer connected, i.e. the failure was of the
+    /// connection rather than e.g. an error reply from Redis, the connection is discarded.
+    pub async fn send<T>(&self, msg: resp::RespValue) -> Result<T, error::Error>
+    where
+        T: resp::FromResp + Unpin,
+    {
+        let connection = self.get().await?;
+        let result = connection.send(msg).await;
+        if result.is_err() && !connection.is_connected() {
+            self.discard(&connection);
+        }
+        result
+    }
+}
+
+/// A connection checked-out from a `Pool`, this dereferences to the `PairedConnection`
+pub struct PooledConnection {
+    connection: PairedConnection,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Deref for PooledConnection {
+    type Target = PairedConnection;
+
+    fn deref(&self) -> &PairedConnection {
+        &self.connection
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use futures_util::{future, SinkExt, StreamExt};
+
+    use super::PoolBuilder;
+
+    use crate::{
+        client::{
+            mock::{echo, mock_connections},
+            ConnectionBuilder,
+        },
+        resp,
+    };
+
+    #[tokio::test]
+    async fn concurrent_commands_across_pool() {
+        let (addr, mut accepted) =
+            mock_connections(resp::RespCodec::default(), |_, server| echo(server)).await;
+
+        let pool = PoolBuilder::new(ConnectionBuilder::new(addr.to_string()).unwrap())
+            .min_size(1)
+            .max_size(3)
+            .build()
+            .await
+            .expect("Cannot create pool");
+        assert_eq!(pool.size(), 1);
+
+        let results: Vec<String> = future::try_join_all(
+            (0..100).map(|i| pool.send::<String>(resp_array!["ECHO", i.to_string()])),
+        )
+        .await
+        .expect("Cannot send commands");
+
+        for (i, result) in results.iter().enumerate() {
+            assert_eq!(result, &i.to_string());
+        }
+        assert!(pool.size() > 1);
+        assert!(pool.size() <= 3);
+        let mut connections = 0;
+        while accepted.try_recv().is_ok() {
+            connections += 1;
+        }
+        assert_eq!(connections, pool.size());
+    }
+
+    #[tokio::test]
+    async fn failed_connection_is_replaced() {
+        let (addr, _connections) =
+            mock_connections(resp::RespCodec::default(), |index, mut server| async move {
+                if index == 0 {
+                    // The first connection is killed while a command is in-flight
+                    let _ = server.next().await.unwrap().unwrap();
+                } else {
+                    echo(server).await;
+                }
+            })
+            .await;
+
+        let pool = PoolBuilder::new(ConnectionBuilder::new(addr.to_string()).unwrap())
+            .min_size(1)
+            .max_size(1)
+            .build()
+            .await
+            .expect("Cannot create pool");
+
+        let result = pool.send::<String>(resp_array!["ECHO", "lost"]).await;
+        assert!(result.is_err());
+        assert_eq!(pool.size(), 0);
+
+        let result: String = pool
+            .send(resp_array!["ECHO", "replaced"])
+            .await
+            .expect("Cannot send command");
+        assert_eq!(result, "replaced");
+        assert_eq!(pool.size(), 1);
+    }
+
+    #[tokio::test]
+    async fn shares_connection_if_cannot_connect() {
+        let (addr, _connections) =
+            mock_connections(resp::RespCodec::default(), |index, mut server| async move {
+                if index > 0 {
+                    // Any other connections never authenticate
+                    return std::future::pending().await;
+                }
+                let request = server.next().await.unwrap().unwrap();
+                assert_eq!(request, resp_array!["AUTH", "secret"]);
+                server.send(resp::RespValue::ok()).await.unwrap();
+                echo(server).await;
+            })
+            .await;
+
+        let mut connection = ConnectionBuilder::new(addr.to_string()).unwrap();
+        connection.password("secret");
+        let pool = PoolBuilder::new(connection)
+            .min_size(1)
+            .max_size(2)
+            .checkout_timeout(Duration::from_millis(50))
+            .build()
+            .await
+            .expect("Cannot create pool");
+
+        let first = pool.get().await.expect("Cannot get connection");
+        let second = pool.get().await.expect("Cannot get connection");
+        assert_eq!(pool.size(), 1);
+
+        let result: String = second
+            .send(resp_array!["ECHO", "shared"])
+            .await
+            .expect("Cannot send command");
+        assert_eq!(result, "shared");
+        drop(first);
+    }
+
+    #[tokio::test]
+    async fn invalid_pool_size() {
+        let result = PoolBuilder::new(ConnectionBuilder::new("127.0.0.1:6379").unwrap())
+            .min_size(2)
+            .max_size(1)
+            .build()
+            .await;
+        assert!(result.is_err());
+    }
+}
@@ -8,23 +8,25 @@
  * except according to those terms.
  */
 
-use std::collections::{btree_map::Entry, BTreeMap};
+use std::collections::{btree_map::Entry, BTreeMap, VecDeque};
 use std::future::Future;
+use std::mem;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use futures_channel::{mpsc, oneshot};
 use futures_sink::Sink;
 use futures_util::{
-    future::TryFutureExt,
+    future::{FutureExt, TryFutureExt},
     stream::{Fuse, Stream, StreamExt},
 };
 
-use tokio::net::ToSocketAddrs;
+use tokio::{net::ToSocketAddrs, time::sleep};
 
 use super::{
-    connect::{connect_with_auth, RespConnection},
+    connect::{connect_with_protocol, RespConnection},
     ConnectionBuilder,
 };
 
@@ -46,8 +48,66 @@ enum PubsubEvent {
     Punsubscribe(String),
 }
 
-type PubsubStreamInner = mpsc::UnboundedReceiver<Result<resp::RespValue, error::Error>>;
-type PubsubSink = mpsc::UnboundedSender<Result<resp::RespValue, error::Error>>;
+type PubsubStreamInner = mpsc::UnboundedReceiver<Result<Message, error::Error>>;
+type PubsubSink = mpsc::UnboundedSender<Result<Message, error::Error>>;
+
+/// A message published to a channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// The channel the message was published to
+    pub channel: String,
+    /// The pattern that matched the channel, if the subscription was made with `psubscribe`
+    pub pattern: Option<String>,
+    /// The message itself
+    pub payload: resp::RespValue,
+}
+
+impl Message {
+    /// The keyspace notification carried by this message, or `None` if it was not published to
+    /// a `__keyspace@<db>__:<key>` or `__keyevent@<db>__:<event>` channel.
+    pub fn keyspace_event(&self) -> Option<KeyspaceEvent> {
+        let (kind, rest) = if let Some(rest) = self.channel.strip_prefix("__keyspace@") {
+            (KeyspaceEventKind::Keyspace, rest)
+        } else if let Some(rest) = self.channel.strip_prefix("__keyevent@") {
+            (KeyspaceEventKind::Keyevent, rest)
+        } else {
+            return None;
+        };
+        let (db, key_or_event) = rest.split_once("__:")?;
+        if db.is_empty() || !db.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Some(KeyspaceEvent {
+            db: db.parse().ok()?,
+            kind,
+            key_or_event: key_or_event.to_string(),
+            detail: self.payload.as_str()?.to_string(),
+        })
+    }
+}
+
+/// Which of the two channels of a keyspace notification a message was published to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyspaceEventKind {
+    /// `__keyspace@<db>__:<key>`, the payload is the name of the event
+    Keyspace,
+    /// `__keyevent@<db>__:<event>`, the payload is the key
+    Keyevent,
+}
+
+/// A keyspace notification, see `Message::keyspace_event`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyspaceEvent {
+    /// The database the event happened in
+    pub db: u32,
+    pub kind: KeyspaceEventKind,
+    /// The rest of the channel, the key for a `Keyspace` notification, or the name of the event,
+    /// e.g. `set` or `expired`, for a `Keyevent` notification
+    pub key_or_event: String,
+    /// The payload, the name of the event for a `Keyspace` notification, or the key for a
+    /// `Keyevent` notification
+    pub detail: String,
+}
 
 /// A spawned future that handles a Pub/Sub connection and routes messages to streams for
 /// downstream consumption
@@ -59,15 +119,25 @@ struct PubsubConnectionInner {
     /// Current subscriptions
     subscriptions: BTreeMap<String, PubsubSink>,
     psubscriptions: BTreeMap<String, PubsubSink>,
-    /// Subscriptions that have not yet been confirmed
-    pending_subs: BTreeMap<String, (PubsubSink, oneshot::Sender<()>)>,
-    pending_psubs: BTreeMap<String, (PubsubSink, oneshot::Sender<()>)>,
+    /// Subscriptions that have not yet been confirmed, resubscriptions after reconnecting have no
+    /// signal
+    pending_subs: BTreeMap<String, (PubsubSink, Option<oneshot::Sender<()>>)>,
+    pending_psubs: BTreeMap<String, (PubsubSink, Option<oneshot::Sender<()>>)>,
+    /// Messages to be sent before any new subscription requests are read
+    queued: VecDeque<resp::RespValue>,
     /// Any incomplete messages to be sent...
     send_pending: Option<resp::RespValue>,
+    /// Whether subscriptions will be re-established after the connection fails, if so the streams
+    /// aren't notified of the failure
+    resubscribe: bool,
 }
 
 impl PubsubConnectionInner {
-    fn new(con: RespConnection, out_rx: mpsc::UnboundedReceiver<PubsubEvent>) -> Self {
+    fn new(
+        con: RespConnection,
+        out_rx: mpsc::UnboundedReceiver<PubsubEvent>,
+        resubscribe: bool,
+    ) -> Self {
         PubsubConnectionInner {
             connection: con,
             out_rx: out_rx.fuse(),
@@ -75,13 +145,96 @@ impl PubsubConnectionInner {
             psubscriptions: BTreeMap::new(),
             pending_subs: BTreeMap::new(),
             pending_psubs: BTreeMap::new(),
+            queued: VecDeque::new(),
             send_pending: None,
+            resubscribe,
         }
     }
 
+    /// Replace a failed connection, all current and pending subscriptions are re-issued on the
+    /// new connection, followed by any unsubscriptions that hadn't been sent.
+    fn reconnected(&mut self, con: RespConnection) {
+        self.connection = con;
+        for (topic, sender) in mem::take(&mut self.subscriptions) {
+            if !sender.is_closed() {
+                self.pending_subs.entry(topic).or_insert((sender, None));
+            }
+        }
+        for (topic, sender) in mem::take(&mut self.psubscriptions) {
+            if !sender.is_closed() {
+                self.pending_psubs.entry(topic).or_insert((sender, None));
+            }
+        }
+
+        // Subscriptions are re-issued from `pending_subs`/`pending_psubs` below, and Redis would
+        // reply to unsubscribing from any other topic with a notification we don't expect
+        let unsent: Vec<_> = self
+            .send_pending
+            .take()
+            .into_iter()
+            .chain(self.queued.drain(..))
+            .filter(|msg| match subscription_command(msg) {
+                Some((b"SUBSCRIBE" | b"PSUBSCRIBE", _)) => false,
+                Some((b"UNSUBSCRIBE", topic)) => self.pending_subs.contains_key(topic),
+                Some((b"PUNSUBSCRIBE", topic)) => self.pending_psubs.contains_key(topic),
+                _ => true,
+            })
+            .collect();
+
+        for topic in self.pending_subs.keys() {
+            self.queued.push_back(resp_array!["SUBSCRIBE", topic]);
+        }
+        for topic in self.pending_psubs.keys() {
+            self.queued.push_back(resp_array!["PSUBSCRIBE", topic]);
+        }
+        self.queued.extend(unsent);
+    }
+
+    /// Records the request, returning the message to be sent to Redis
+    fn handle_event(&mut self, pubsub_event: PubsubEvent) -> resp::RespValue {
+        match pubsub_event {
+            PubsubEvent::Subscribe(topic, sender, signal) => {
+                self.pending_subs
+                    .insert(topic.clone(), (sender, Some(signal)));
+                resp_array!["SUBSCRIBE", topic]
+            }
+            PubsubEvent::Psubscribe(topic, sender, signal) => {
+                self.pending_psubs
+                    .insert(topic.clone(), (sender, Some(signal)));
+                resp_array!["PSUBSCRIBE", topic]
+            }
+            PubsubEvent::Unsubscribe(topic) => resp_array!["UNSUBSCRIBE", topic],
+            PubsubEvent::Punsubscribe(topic) => resp_array!["PUNSUBSCRIBE", topic],
+        }
+    }
+
+    /// Queues any requests made while the connection is being re-established. Returns `false` if
+    /// there is nothing left to reconnect for, i.e. every handle to the connection has been
+    /// dropped and no subscription has a stream left.
+    fn handle_disconnected_events(&mut self) -> bool {
+        let closed = loop {
+            match self.out_rx.next().now_or_never() {
+                Some(Some(pubsub_event)) => {
+                    let message = self.handle_event(pubsub_event);
+                    self.queued.push_back(message);
+                }
+                Some(None) => break true,
+                None => break false,
+            }
+        };
+        !closed
+            || self
+                .subscriptions
+                .values()
+                .chain(self.psubscriptions.values())
+                .chain(self.pending_subs.values().map(|(sender, _)| sender))
+                .chain(self.pending_psubs.values().map(|(sender, _)| sender))
+                .any(|sender| !sender.is_closed())
+    }
+
     /// Returns `true` if data sent, or `false` if stream not ready...
     fn do_send(&mut self, cx: &mut Context, msg: resp::RespValue) -> Result<bool, error::Error> {
-        match Pin::new(&mut self.connection).poll_ready(cx) {
+        match Sink::<resp::RespValue>::poll_ready(Pin::new(&mut self.connection), cx) {
             Poll::Ready(_) => {
                 Pin::new(&mut self.connection).start_send(msg)?;
                 Ok(true)
@@ -94,8 +247,8 @@ impl PubsubConnectionInner {
     }
 
     fn do_flush(&mut self, cx: &mut Context) -> Result<(), error::Error> {
-        match Pin::new(&mut self.connection).poll_flush(cx) {
-            Poll::Ready(r) => r.map_err(|e| e.into()),
+        match Sink::<resp::RespValue>::poll_flush(Pin::new(&mut self.connection), cx) {
+            Poll::Ready(r) => r,
             Poll::Pending => Ok(()),
         }
     }
@@ -107,23 +260,17 @@ impl PubsubConnectionInner {
                 return Ok(());
             }
         }
+        while let Some(msg) = self.queued.pop_front() {
+            if !self.do_send(cx, msg)? {
+                return Ok(());
+            }
+        }
         loop {
             match self.out_rx.poll_next_unpin(cx) {
                 Poll::Pending => return Ok(()),
                 Poll::Ready(None) => return Ok(()),
                 Poll::Ready(Some(pubsub_event)) => {
-                    let message = match pubsub_event {
-                        PubsubEvent::Subscribe(topic, sender, signal) => {
-                            self.pending_subs.insert(topic.clone(), (sender, signal));
-                            resp_array!["SUBSCRIBE", topic]
-                        }
-                        PubsubEvent::Psubscribe(topic, sender, signal) => {
-                            self.pending_psubs.insert(topic.clone(), (sender, signal));
-                            resp_array!["PSUBSCRIBE", topic]
-                        }
-                        PubsubEvent::Unsubscribe(topic) => resp_array!["UNSUBSCRIBE", topic],
-                        PubsubEvent::Punsubscribe(topic) => resp_array!["PUNSUBSCRIBE", topic],
-                    };
+                    let message = self.handle_event(pubsub_event);
                     if !self.do_send(cx, message)? {
                         return Ok(());
                     }
@@ -133,8 +280,9 @@ impl PubsubConnectionInner {
     }
 
     fn handle_message(&mut self, msg: resp::RespValue) -> Result<bool, error::Error> {
-        let (message_type, topic, msg) = match msg {
-            resp::RespValue::Array(mut messages) => match (
+        let (message_type, topic, channel, msg) = match msg {
+            // RESP3 connections deliver messages as pushes, otherwise they're plain arrays
+            resp::RespValue::Array(mut messages) | resp::RespValue::Push(mut messages) => match (
                 messages.pop(),
                 messages.pop(),
                 messages.pop(),
@@ -142,13 +290,22 @@ impl PubsubConnectionInner {
             ) {
                 (Some(msg), Some(topic), Some(message_type), None) => {
                     match (msg, String::from_resp(topic), message_type) {
-                        (msg, Ok(topic), resp::RespValue::BulkString(bytes)) => (bytes, topic, msg),
+                        (msg, Ok(topic), resp::RespValue::BulkString(bytes)) => {
+                            (bytes, topic, None, msg)
+                        }
                         _ => return Err(error::unexpected("Incorrect format of a PUBSUB message")),
                     }
                 }
-                (Some(msg), Some(_), Some(topic), Some(message_type)) => {
-                    match (msg, String::from_resp(topic), message_type) {
-                        (msg, Ok(topic), resp::RespValue::BulkString(bytes)) => (bytes, topic, msg),
+                (Some(msg), Some(channel), Some(topic), Some(message_type)) => {
+                    match (
+                        msg,
+                        String::from_resp(topic),
+                        String::from_resp(channel),
+                        message_type,
+                    ) {
+                        (msg, Ok(topic), Ok(channel), resp::RespValue::BulkString(bytes)) => {
+                            (bytes, topic, Some(channel), msg)
+                        }
                         _ => return Err(error::unexpected("Incorrect format of a PUBSUB message")),
                     }
                 }
@@ -169,9 +326,11 @@ impl PubsubConnectionInner {
             b"subscribe" => match self.pending_subs.remove(&topic) {
                 Some((sender, signal)) => {
                     self.subscriptions.insert(topic, sender);
-                    signal
-                        .send(())
-                        .map_err(|()| error::internal("Error confirming subscription"))?
+                    if let Some(signal) = signal {
+                        signal
+                            .send(())
+                            .map_err(|()| error::internal("Error confirming subscription"))?
+                    }
                 }
                 None => {
                     return Err(error::internal(format!(
@@ -183,9 +342,11 @@ impl PubsubConnectionInner {
             b"psubscribe" => match self.pending_psubs.remove(&topic) {
                 Some((sender, signal)) => {
                     self.psubscriptions.insert(topic, sender);
-                    signal
-                        .send(())
-                        .map_err(|()| error::internal("Error confirming subscription"))?
+                    if let Some(signal) = signal {
+                        signal
+                            .send(())
+                            .map_err(|()| error::internal("Error confirming subscription"))?
+                    }
                 }
                 None => {
                     return Err(error::internal(format!(
@@ -228,7 +389,12 @@ impl PubsubConnectionInner {
             }
             b"message" => match self.subscriptions.get(&topic) {
                 Some(sender) => {
-                    if let Err(error) = sender.unbounded_send(Ok(msg)) {
+                    let message = Message {
+                        channel: topic,
+                        pattern: None,
+                        payload: msg,
+                    };
+                    if let Err(error) = sender.unbounded_send(Ok(message)) {
                         if !error.is_disconnected() {
                             return Err(error::internal(format!("Cannot send message: {}", error)));
                         }
@@ -243,7 +409,12 @@ impl PubsubConnectionInner {
             },
             b"pmessage" => match self.psubscriptions.get(&topic) {
                 Some(sender) => {
-                    if let Err(error) = sender.unbounded_send(Ok(msg)) {
+                    let message = Message {
+                        channel: channel.unwrap_or_else(|| topic.clone()),
+                        pattern: Some(topic),
+                        payload: msg,
+                    };
+                    if let Err(error) = sender.unbounded_send(Ok(message)) {
                         if !error.is_disconnected() {
                             return Err(error::internal(format!("Cannot send message: {}", error)));
                         }
@@ -273,8 +444,10 @@ impl PubsubConnectionInner {
             match self.connection.poll_next_unpin(cx) {
                 Poll::Pending => return Ok(true),
                 Poll::Ready(None) => {
-                    if self.subscriptions.is_empty() {
+                    if self.subscriptions.is_empty() && self.psubscriptions.is_empty() {
                         return Ok(false);
+                    } else if self.resubscribe {
+                        return Err(error::Error::Connection(ConnectionReason::NotConnected));
                     } else {
                         // This can only happen if the connection is closed server-side
                         for sub in self.subscriptions.values() {
@@ -298,6 +471,7 @@ impl PubsubConnectionInner {
                         return Ok(false);
                     }
                 }
+                Poll::Ready(Some(Err(e))) if self.resubscribe => return Err(e),
                 Poll::Ready(Some(Err(e))) => {
                     for sub in self.subscriptions.values() {
                         sub.unbounded_send(Err(error::unexpected(format!(
@@ -336,26 +510,81 @@ impl Future for PubsubConnectionInner {
     }
 }
 
+/// The command and topic of a request built by `PubsubConnectionInner::handle_event`
+fn subscription_command(msg: &resp::RespValue) -> Option<(&[u8], &str)> {
+    match msg {
+        resp::RespValue::Array(parts) => match parts.as_slice() {
+            [resp::RespValue::BulkString(cmd), topic] => Some((cmd, topic.as_str()?)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// A shareable reference to subscribe to PUBSUB topics
 #[derive(Debug, Clone)]
 pub struct PubsubConnection {
     out_tx_c: Arc<Reconnect<PubsubEvent, mpsc::UnboundedSender<PubsubEvent>>>,
 }
 
+/// The delay before the first attempt to re-establish a failed connection when resubscribing,
+/// this doubles after each failed attempt up to `MAX_RESUBSCRIBE_DELAY`
+const RESUBSCRIBE_DELAY: Duration = Duration::from_millis(100);
+const MAX_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(10);
+
+async fn pubsub_conn(
+    addr: &(impl ToSocketAddrs + Send + Sync),
+    username: Option<&str>,
+    password: Option<&str>,
+    protocol: resp::RespVersion,
+) -> Result<RespConnection, error::Error> {
+    let (connection, info) = connect_with_protocol(addr, username, password, protocol).await?;
+    if let Some(info) = info {
+        log::debug!(
+            "Connected to {} {}: {:?}",
+            info.server,
+            info.version,
+            info.protocol
+        );
+    }
+    Ok(connection)
+}
+
 async fn inner_conn_fn(
-    addr: impl ToSocketAddrs,
+    addr: impl ToSocketAddrs + Send + Sync + 'static,
     username: Option<Arc<str>>,
     password: Option<Arc<str>>,
+    protocol: resp::RespVersion,
+    resubscribe: bool,
 ) -> Result<mpsc::UnboundedSender<PubsubEvent>, error::Error> {
-    let username = username.as_ref().map(|u| u.as_ref());
-    let password = password.as_ref().map(|p| p.as_ref());
-
-    let connection = connect_with_auth(&addr, username, password).await?;
+    let connection = pubsub_conn(&addr, username.as_deref(), password.as_deref(), protocol).await?;
     let (out_tx, out_rx) = mpsc::unbounded();
-    tokio::spawn(async {
-        match PubsubConnectionInner::new(connection, out_rx).await {
-            Ok(_) => (),
-            Err(e) => log::error!("Pub/Sub error: {:?}", e),
+    tokio::spawn(async move {
+        let mut inner = PubsubConnectionInner::new(connection, out_rx, resubscribe);
+        loop {
+            match (&mut inner).await {
+                Ok(_) => return,
+                Err(e) if resubscribe => {
+                    log::warn!("Pub/Sub connection failed, reconnecting: {}", e)
+                }
+                Err(e) => return log::error!("Pub/Sub error: {:?}", e),
+            }
+
+            let mut delay = RESUBSCRIBE_DELAY;
+            let connection = loop {
+                sleep(delay).await;
+                if !inner.handle_disconnected_events() {
+                    return log::debug!("Pub/Sub connection no longer used, not reconnecting");
+                }
+                match pubsub_conn(&addr, username.as_deref(), password.as_deref(), protocol).await {
+                    Ok(connection) => break connection,
+                    Err(e) => {
+                        log::error!("Cannot re-establish Pub/Sub connection: {}", e);
+                        delay = (delay * 2).min(MAX_RESUBSCRIBE_DELAY);
+                    }
+                }
+            };
+            inner.reconnected(connection);
         }
     });
     Ok(out_tx)
@@ -366,13 +595,21 @@ impl ConnectionBuilder {
         let addr = self.addr.clone();
         let username = self.username.clone();
         let password = self.password.clone();
+        let protocol = self.protocol;
+        let resubscribe = self.resubscribe;
 
         let reconnecting_f = reconnect(
             |con: &mpsc::UnboundedSender<PubsubEvent>, act| {
                 con.unbounded_send(act).map_err(|e| e.into())
             },
             move || {
-                let con_f = inner_conn_fn(addr.clone(), username.clone(), password.clone());
+                let con_f = inner_conn_fn(
+                    addr.clone(),
+                    username.clone(),
+                    password.clone(),
+                    protocol,
+                    resubscribe,
+                );
                 Box::pin(con_f)
             },
         );
@@ -387,7 +624,8 @@ impl ConnectionBuilder {
 /// Returns a future that resolves to a `PubsubConnection`. The future will only resolve once the
 /// connection is established; after the intial establishment, if the connection drops for any
 /// reason (e.g. Redis server being restarted), the connection will attempt re-connect, however
-/// any subscriptions will need to be re-subscribed.
+/// any subscriptions will need to be re-subscribed.  See `ConnectionBuilder::resubscribe` to
+/// re-establish the connection and subscriptions automatically instead.
 pub async fn pubsub_connect(addr: impl Into<String>) -> Result<PubsubConnection, error::Error> {
     ConnectionBuilder::new(addr)?.pubsub_connect().await
 }
@@ -454,17 +692,43 @@ impl PubsubConnection {
     }
 }
 
+/// A stream of the payloads of the messages published to a subscription, use `into_messages` to
+/// also receive the channel each was published to.
 pub struct PubsubStream {
     topic: String,
     underlying: PubsubStreamInner,
     con: PubsubConnection,
 }
 
+impl PubsubStream {
+    /// Convert this into a stream of `Message`s, which include the channel that each message was
+    /// published to as well as the payload.
+    pub fn into_messages(self) -> MessageStream {
+        MessageStream { stream: self }
+    }
+}
+
 impl Stream for PubsubStream {
     type Item = Result<resp::RespValue, error::Error>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
-        self.get_mut().underlying.poll_next_unpin(cx)
+        self.get_mut()
+            .underlying
+            .poll_next_unpin(cx)
+            .map(|message| message.map(|message| message.map(|message| message.payload)))
+    }
+}
+
+/// A stream of the `Message`s published to a subscription, see `PubsubStream::into_messages`.
+pub struct MessageStream {
+    stream: PubsubStream,
+}
+
+impl Stream for MessageStream {
+    type Item = Result<Message, error::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.get_mut().stream.underlying.poll_next_unpin(cx)
     }
 }
 
@@ -477,9 +741,68 @@ impl Drop for PubsubStream {
 
 #[cfg(test)]
 mod test {
-    use futures::{try_join, StreamExt, TryStreamExt};
+    use futures::{try_join, SinkExt, StreamExt, TryStreamExt};
+
+    use super::{KeyspaceEvent, KeyspaceEventKind, Message};
+
+    use crate::{
+        client::{
+            self,
+            mock::{mock_connection, mock_connections},
+        },
+        resp,
+    };
 
-    use crate::{client, resp};
+    use super::super::ConnectionBuilder;
+
+    #[test]
+    fn keyspace_events() {
+        let message = |channel: &str, payload: &str| Message {
+            channel: channel.to_string(),
+            pattern: Some("__key*__:*".to_string()),
+            payload: payload.into(),
+        };
+
+        assert_eq!(
+            message("__keyspace@0__:mykey", "del").keyspace_event(),
+            Some(KeyspaceEvent {
+                db: 0,
+                kind: KeyspaceEventKind::Keyspace,
+                key_or_event: "mykey".to_string(),
+                detail: "del".to_string(),
+            })
+        );
+        // Keys may themselves contain the separator
+        assert_eq!(
+            message("__keyevent@12__:expired", "session:__:1").keyspace_event(),
+            Some(KeyspaceEvent {
+                db: 12,
+                kind: KeyspaceEventKind::Keyevent,
+                key_or_event: "expired".to_string(),
+                detail: "session:__:1".to_string(),
+            })
+        );
+
+        for channel in [
+            "news",
+            "__keyspace__:mykey",
+            "__keyspace@__:mykey",
+            "__keyspace@x__:mykey",
+            "__keyspace@+1__:mykey",
+            "__keyspace@0:mykey",
+            "__keymiss@0__:mykey",
+        ] {
+            assert_eq!(
+                message(channel, "del").keyspace_event(),
+                None,
+                "{}",
+                channel
+            );
+        }
+        let mut not_a_string = message("__keyspace@0__:mykey", "del");
+        not_a_string.payload = resp::RespValue::Integer(1);
+        assert_eq!(not_a_string.keyspace_event(), None);
+    }
 
     #[tokio::test]
     async fn subscribe_test() {
@@ -541,4 +864,174 @@ mod test {
         assert_eq!(result[1], "test-message-2".into());
         assert_eq!(result[2], "test-message-3".into());
     }
+
+    #[tokio::test]
+    async fn message_stream_test() {
+        let (addr, _server_f) =
+            mock_connection(resp::RespCodec::default(), |mut server| async move {
+                let request = server.next().await.unwrap().unwrap();
+                assert_eq!(request, resp_array!["SUBSCRIBE", "news"]);
+                server
+                    .send(
+                        resp_array!["subscribe", "news"].append(Some(resp::RespValue::Integer(1))),
+                    )
+                    .await
+                    .unwrap();
+                server
+                    .send(resp_array!["message", "news", "first"])
+                    .await
+                    .unwrap();
+
+                let request = server.next().await.unwrap().unwrap();
+                assert_eq!(request, resp_array!["PSUBSCRIBE", "news.*"]);
+                server
+                    .send(
+                        resp_array!["psubscribe", "news.*"]
+                            .append(Some(resp::RespValue::Integer(1))),
+                    )
+                    .await
+                    .unwrap();
+                server
+                    .send(resp_array!["pmessage", "news.*", "news.tech", "hello"])
+                    .await
+                    .unwrap();
+                // Keep the connection open until the client unsubscribes
+                let _ = server.next().await;
+            })
+            .await;
+
+        let pubsub = super::pubsub_connect(addr.to_string())
+            .await
+            .expect("Cannot connect");
+        let mut messages = pubsub
+            .subscribe("news")
+            .await
+            .expect("Cannot subscribe to topic")
+            .into_messages();
+        let message = messages.next().await.unwrap().unwrap();
+        assert_eq!(
+            message,
+            Message {
+                channel: "news".into(),
+                pattern: None,
+                payload: "first".into(),
+            }
+        );
+
+        let mut messages = pubsub
+            .psubscribe("news.*")
+            .await
+            .expect("Cannot subscribe to topic")
+            .into_messages();
+
+        let message = messages.next().await.unwrap().unwrap();
+        assert_eq!(
+            message,
+            Message {
+                channel: "news.tech".into(),
+                pattern: Some("news.*".into()),
+                payload: "hello".into(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn resubscribe_test() {
+        let (addr, mut connections) =
+            mock_connections(resp::RespCodec::default(), |index, mut server| async move {
+                // Only "news" is still subscribed to when reconnecting
+                let (topics, payload) = match index {
+                    0 => (&["news", "sports"][..], "before"),
+                    _ => (&["news"][..], "after"),
+                };
+                for (count, topic) in topics.iter().enumerate() {
+                    let request = server.next().await.unwrap().unwrap();
+                    assert_eq!(request, resp_array!["SUBSCRIBE", *topic]);
+                    let count = resp::RespValue::Integer(count as i64 + 1);
+                    server
+                        .send(resp_array!["subscribe", *topic].append(Some(count)))
+                        .await
+                        .unwrap();
+                }
+                server
+                    .send(resp_array!["message", "news", payload])
+                    .await
+                    .unwrap();
+                if index > 0 {
+                    // Nothing else, in particular not unsubscribing from "sports"
+                    let next = tokio::time::timeout(super::RESUBSCRIBE_DELAY, server.next());
+                    assert!(next.await.is_err(), "Unexpected request");
+                }
+                // The first connection is dropped here, killing it mid-stream
+            })
+            .await;
+
+        let pubsub = ConnectionBuilder::new(addr.to_string())
+            .unwrap()
+            .resubscribe(true)
+            .pubsub_connect()
+            .await
+            .expect("Cannot connect");
+        let mut messages = pubsub
+            .subscribe("news")
+            .await
+            .expect("Cannot subscribe to topic");
+        let dropped = pubsub
+            .subscribe("sports")
+            .await
+            .expect("Cannot subscribe to topic");
+
+        let message = messages.next().await.unwrap().unwrap();
+        assert_eq!(message, "before".into());
+        // Unsubscribe while disconnected, before the reconnection after `RESUBSCRIBE_DELAY`
+        tokio::time::sleep(super::RESUBSCRIBE_DELAY / 4).await;
+        drop(dropped);
+        let message = messages.next().await.unwrap().unwrap();
+        assert_eq!(message, "after".into());
+        for _ in 0..2 {
+            connections.recv().await.unwrap().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn no_reconnect_once_dropped() {
+        let (addr, mut connections) =
+            mock_connections(resp::RespCodec::default(), |_, mut server| async move {
+                let request = server.next().await.unwrap().unwrap();
+                assert_eq!(request, resp_array!["SUBSCRIBE", "news"]);
+                let count = resp::RespValue::Integer(1);
+                server
+                    .send(resp_array!["subscribe", "news"].append(Some(count)))
+                    .await
+                    .unwrap();
+                server
+                    .send(resp_array!["message", "news", "before"])
+                    .await
+                    .unwrap();
+            })
+            .await;
+
+        let pubsub = ConnectionBuilder::new(addr.to_string())
+            .unwrap()
+            .resubscribe(true)
+            .pubsub_connect()
+            .await
+            .expect("Cannot connect");
+        let mut messages = pubsub
+            .subscribe("news")
+            .await
+            .expect("Cannot subscribe to topic");
+        let message = messages.next().await.unwrap().unwrap();
+        assert_eq!(message, "before".into());
+        drop(messages);
+        drop(pubsub);
+
+        connections.recv().await.unwrap().await.unwrap();
+        // A reconnection would be attempted after `RESUBSCRIBE_DELAY`
+        let reconnection = tokio::time::timeout(super::RESUBSCRIBE_DELAY * 10, connections.recv());
+        assert!(
+            reconnection.await.is_err(),
+            "Reconnected after being dropped"
+        );
+    }
 }
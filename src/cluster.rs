@@ -0,0 +1,405 @@
+/*
+ * Copyright 2017-2022 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Primitives for routing commands to the nodes of a Redis Cluster
+
+use crate::{
+    error::{self, Error},
+    resp::{FromResp, RespValue},
+};
+
+/// The number of hash slots in a Redis Cluster
+pub const CLUSTER_SLOTS: u16 = 16384;
+
+/// The lookup table for the CRC16 variant used by Redis Cluster (XMODEM: polynomial `0x1021`, no
+/// reflection, an initial value of zero)
+const CRC16_TABLE: [u16; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, byte| {
+        (crc << 8) ^ CRC16_TABLE[(((crc >> 8) as u8) ^ byte) as usize]
+    })
+}
+
+/// The part of a key which is hashed.  If the key contains a `{` followed by a `}` with at least
+/// one byte between them, only the bytes between the first `{` and the next `}` are hashed, so
+/// that related keys such as `{user1000}.following` and `{user1000}.followers` are stored in the
+/// same slot.  Otherwise the whole key is hashed.
+fn hash_tag(key: &[u8]) -> &[u8] {
+    if let Some(open) = key.iter().position(|b| *b == b'{') {
+        let rest = &key[open + 1..];
+        if let Some(close) = rest.iter().position(|b| *b == b'}') {
+            if close > 0 {
+                return &rest[..close];
+            }
+        }
+    }
+    key
+}
+
+/// The hash slot of a key in a Redis Cluster, honouring hash tags.
+///
+/// ```
+/// use redis_async::cluster::slot_for_key;
+///
+/// assert_eq!(slot_for_key(b"foo"), 12182);
+/// assert_eq!(slot_for_key(b"{foo}.bar"), slot_for_key(b"foo"));
+/// ```
+pub fn slot_for_key(key: &[u8]) -> u16 {
+    crc16(hash_tag(key)) % CLUSTER_SLOTS
+}
+
+/// A node of a Redis Cluster
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    /// The IP address or hostname of the node, whichever the cluster is configured to announce
+    pub host: String,
+    pub port: u16,
+    /// The node's ID, this is not included in replies from servers older than Redis 4.0
+    pub id: Option<String>,
+}
+
+impl Node {
+    /// The address of the node, as given to `ConnectionBuilder::new`, e.g. `127.0.0.1:7000` or
+    /// `[::1]:7000`
+    pub fn addr(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Read a node, or `None` if its endpoint is unknown.  Redis replies with `?` or an empty string
+/// for an unknown endpoint, and nil for the same host as the node queried, which isn't known here.
+fn node_from_resp(resp: RespValue) -> Result<Option<Node>, Error> {
+    let mut values = match resp {
+        RespValue::Array(values) if values.len() >= 2 => values.into_iter(),
+        resp => return Err(error::resp("Cannot convert into a cluster node", resp)),
+    };
+    let host = Option::<String>::from_resp(values.next().expect("No host"))?;
+    let port = u16::from_resp(values.next().expect("No port"))?;
+    let id = values.next().map(String::from_resp).transpose()?;
+    Ok(match host {
+        Some(host) if !host.is_empty() && host != "?" => Some(Node { host, port, id }),
+        _ => None,
+    })
+}
+
+/// A node is an array of its IP address or hostname, port and, optionally, ID.  Anything after
+/// the ID, e.g. the networking metadata added in Redis 7.0, is ignored.  A node with an unknown
+/// endpoint can't be converted.
+impl FromResp for Node {
+    fn from_resp_int(resp: RespValue) -> Result<Node, Error> {
+        match node_from_resp(resp.clone())? {
+            Some(node) => Ok(node),
+            None => Err(error::resp("Cluster node has an unknown endpoint", resp)),
+        }
+    }
+}
+
+/// A range of hash slots, inclusive of both `start` and `end`, and the nodes serving them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotRange {
+    pub start: u16,
+    pub end: u16,
+    pub master: Node,
+    pub replicas: Vec<Node>,
+}
+
+impl SlotRange {
+    /// Whether the range includes `slot`
+    pub fn contains(&self, slot: u16) -> bool {
+        self.start <= slot && slot <= self.end
+    }
+}
+
+/// Read a slot range, or `None` if the endpoint of its master is unknown.  Replicas with unknown
+/// endpoints are left out.
+fn slot_range_from_resp(resp: RespValue) -> Result<Option<SlotRange>, Error> {
+    let mut values = match resp {
+        RespValue::Array(values) if values.len() >= 3 => values.into_iter(),
+        resp => return Err(error::resp("Cannot convert into a slot range", resp)),
+    };
+    let start = u16::from_resp(values.next().expect("No start"))?;
+    let end = u16::from_resp(values.next().expect("No end"))?;
+    if start > end || end >= CLUSTER_SLOTS {
+        return Err(Error::Resp(
+            format!("Invalid slot range: {}-{}", start, end),
+            None,
+        ));
+    }
+    let master = match node_from_resp(values.next().expect("No master"))? {
+        Some(master) => master,
+        None => return Ok(None),
+    };
+    let mut replicas = Vec::new();
+    for replica in values {
+        replicas.extend(node_from_resp(replica)?);
+    }
+    Ok(Some(SlotRange {
+        start,
+        end,
+        master,
+        replicas,
+    }))
+}
+
+/// Replicas with unknown endpoints are left out, a range whose master has an unknown endpoint
+/// can't be converted.
+impl FromResp for SlotRange {
+    fn from_resp_int(resp: RespValue) -> Result<SlotRange, Error> {
+        match slot_range_from_resp(resp.clone())? {
+            Some(range) => Ok(range),
+            None => Err(error::resp(
+                "Slot range master has an unknown endpoint",
+                resp,
+            )),
+        }
+    }
+}
+
+/// The reply to `CLUSTER SLOTS`, the ranges of hash slots in the cluster and the nodes serving
+/// each of them.  Ranges whose master has an unknown endpoint are left out, as the slots can't be
+/// routed to it.
+///
+/// ```
+/// use redis_async::{cluster::{slot_for_key, ClusterSlots}, resp::FromResp, resp_array};
+///
+/// let reply = resp_array![resp_array![0i64, 16383i64, resp_array!["127.0.0.1", 7000i64]]];
+/// let slots = ClusterSlots::from_resp(reply).unwrap();
+/// let range = slots.range_for_slot(slot_for_key(b"foo")).unwrap();
+/// assert_eq!(range.master.addr(), "127.0.0.1:7000");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ClusterSlots(pub Vec<SlotRange>);
+
+impl ClusterSlots {
+    /// The range which includes `slot`, if any
+    pub fn range_for_slot(&self, slot: u16) -> Option<&SlotRange> {
+        self.0.iter().find(|range| range.contains(slot))
+    }
+}
+
+impl FromResp for ClusterSlots {
+    fn from_resp_int(resp: RespValue) -> Result<ClusterSlots, Error> {
+        let mut ranges = Vec::new();
+        for range in Vec::<RespValue>::from_resp_int(resp)? {
+            ranges.extend(slot_range_from_resp(range)?);
+        }
+        Ok(ClusterSlots(ranges))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+
+    use tokio_util::codec::Decoder;
+
+    use super::{crc16, hash_tag, slot_for_key, ClusterSlots, Node, SlotRange};
+    use crate::{
+        error::Error,
+        resp::{FromResp, RespCodec, RespValue},
+    };
+
+    #[test]
+    fn crc16_test_vectors() {
+        assert_eq!(crc16(b""), 0);
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(crc16(b"A"), 0x58e5);
+    }
+
+    #[test]
+    fn slots() {
+        assert_eq!(slot_for_key(b""), 0);
+        assert_eq!(slot_for_key(b"foo"), 12182);
+        assert_eq!(slot_for_key(b"bar"), 5061);
+        assert_eq!(slot_for_key(b"123456789"), 0x31c3);
+        for key in [&b"foo"[..], b"123456789", b"\xff\x00\xfe"] {
+            assert!(slot_for_key(key) < 16384);
+        }
+    }
+
+    #[test]
+    fn hash_tags() {
+        assert_eq!(slot_for_key(b"foo{bar}"), slot_for_key(b"bar"));
+        assert_eq!(
+            slot_for_key(b"{user1000}.following"),
+            slot_for_key(b"{user1000}.followers")
+        );
+        assert_eq!(hash_tag(b"foo{bar}zap"), b"bar");
+        // Only the first `{` and the next `}` count
+        assert_eq!(hash_tag(b"foo{{bar}}zap"), b"{bar");
+        assert_eq!(hash_tag(b"foo{bar}{zap}"), b"bar");
+        // An empty or unterminated tag means the whole key is hashed
+        assert_eq!(hash_tag(b"foo{}{bar}"), b"foo{}{bar}");
+        assert_eq!(hash_tag(b"foo{bar"), b"foo{bar");
+        assert_eq!(hash_tag(b"foo}bar{"), b"foo}bar{");
+    }
+
+    fn node(host: &str, port: u16, id: Option<&str>) -> Node {
+        Node {
+            host: host.to_string(),
+            port,
+            id: id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn cluster_slots() {
+        // A Redis 7 reply, with networking metadata, for a cluster of three masters each with a
+        // replica, except for the last which is served by an older node without IDs
+        let mut bytes = BytesMut::from(
+            &b"*3\r\n\
+            *4\r\n:0\r\n:5460\r\n\
+            *4\r\n$9\r\n127.0.0.1\r\n:30001\r\n$40\r\n09dbe9720cda62f7865eabc5fd8857c5d2678366\r\n\
+            *2\r\n$8\r\nhostname\r\n$24\r\nhost-1.redis.example.com\r\n\
+            *4\r\n$9\r\n127.0.0.1\r\n:30004\r\n$40\r\n821d8ca00d7ccf931ed3ffc7e3db0599d2271abf\r\n\
+            *0\r\n\
+            *4\r\n:5461\r\n:10922\r\n\
+            *3\r\n$9\r\n127.0.0.1\r\n:30002\r\n$40\r\nc9d93d9f2c0c524ff34cc11838c2003d8c29e013\r\n\
+            *3\r\n$3\r\n::1\r\n:30005\r\n$40\r\nfaadb3eb99009de4ab72ad6b6ed87634c7ee410f\r\n\
+            *3\r\n:10923\r\n:16383\r\n\
+            *2\r\n$8\r\n10.0.0.3\r\n:6379\r\n"[..],
+        );
+        let reply = RespCodec::default().decode(&mut bytes).unwrap().unwrap();
+        assert!(bytes.is_empty());
+
+        let slots = ClusterSlots::from_resp(reply).unwrap();
+        assert_eq!(
+            slots.0,
+            vec![
+                SlotRange {
+                    start: 0,
+                    end: 5460,
+                    master: node(
+                        "127.0.0.1",
+                        30001,
+                        Some("09dbe9720cda62f7865eabc5fd8857c5d2678366")
+                    ),
+                    replicas: vec![node(
+                        "127.0.0.1",
+                        30004,
+                        Some("821d8ca00d7ccf931ed3ffc7e3db0599d2271abf")
+                    )],
+                },
+                SlotRange {
+                    start: 5461,
+                    end: 10922,
+                    master: node(
+                        "127.0.0.1",
+                        30002,
+                        Some("c9d93d9f2c0c524ff34cc11838c2003d8c29e013")
+                    ),
+                    replicas: vec![node(
+                        "::1",
+                        30005,
+                        Some("faadb3eb99009de4ab72ad6b6ed87634c7ee410f")
+                    )],
+                },
+                SlotRange {
+                    start: 10923,
+                    end: 16383,
+                    master: node("10.0.0.3", 6379, None),
+                    replicas: vec![],
+                },
+            ]
+        );
+
+        assert_eq!(slots.range_for_slot(0).unwrap().start, 0);
+        assert_eq!(slots.range_for_slot(5461).unwrap().start, 5461);
+        assert_eq!(slots.range_for_slot(16383).unwrap().start, 10923);
+        assert!(slots.range_for_slot(16384).is_none());
+        assert_eq!(slots.0[1].replicas[0].addr(), "[::1]:30005");
+    }
+
+    #[test]
+    fn cluster_slots_hostnames_and_unknown_endpoints() {
+        let reply = resp_array![
+            resp_array![
+                0i64,
+                5460i64,
+                resp_array!["redis-1.example.com", 7000i64],
+                resp_array!["?", 7003i64],
+                resp_array!["", 7004i64],
+                resp_array![RespValue::Nil, 7005i64],
+                resp_array!["redis-2.example.com", 7006i64]
+            ],
+            // The master's endpoint isn't known, so the range is left out
+            resp_array![5461i64, 16383i64, resp_array!["?", 7001i64]]
+        ];
+        let slots = ClusterSlots::from_resp(reply).unwrap();
+        assert_eq!(
+            slots.0,
+            vec![SlotRange {
+                start: 0,
+                end: 5460,
+                master: node("redis-1.example.com", 7000, None),
+                replicas: vec![node("redis-2.example.com", 7006, None)],
+            }]
+        );
+        assert_eq!(slots.0[0].master.addr(), "redis-1.example.com:7000");
+        assert!(slots.range_for_slot(5461).is_none());
+
+        assert!(Node::from_resp(resp_array!["?", 7001i64]).is_err());
+    }
+
+    #[test]
+    fn invalid_cluster_slots() {
+        for reply in [
+            resp_array![resp_array![0i64, 16383i64]],
+            resp_array![resp_array![
+                0i64,
+                16384i64,
+                resp_array!["127.0.0.1", 7000i64]
+            ]],
+            resp_array![resp_array![10i64, 5i64, resp_array!["127.0.0.1", 7000i64]]],
+            resp_array![resp_array![0i64, 16383i64, resp_array!["127.0.0.1"]]],
+            resp_array![resp_array![
+                0i64,
+                16383i64,
+                resp_array!["127.0.0.1", 70000i64]
+            ]],
+        ] {
+            assert!(
+                matches!(
+                    ClusterSlots::from_resp(reply.clone()),
+                    Err(Error::Resp(_, _))
+                ),
+                "{:?}",
+                reply
+            );
+        }
+        assert_eq!(
+            ClusterSlots::from_resp(resp_array![]).unwrap(),
+            ClusterSlots::default()
+        );
+    }
+}
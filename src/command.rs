@@ -0,0 +1,115 @@
+/*
+ * Copyright 2017-2022 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! A builder for Redis commands with optional arguments
+
+use crate::resp::RespValue;
+
+/// A builder for commands, for when the arguments aren't known up-front, e.g. optional flags.
+/// For fixed commands `resp_array!` is simpler.
+///
+/// # Examples
+///
+/// ```
+/// use redis_async::command::Command;
+/// use redis_async::resp::RespValue;
+///
+/// let expiry: Option<&str> = None;
+/// let command: RespValue = Command::new("SET")
+///     .arg("key")
+///     .arg("value")
+///     .arg_opt(expiry.map(|_| "EX"))
+///     .arg_opt(expiry)
+///     .into();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Command {
+    args: Vec<RespValue>,
+}
+
+/// The capacity reserved for most commands, enough for a name, key, value and a flag or two
+const DEFAULT_CAPACITY: usize = 5;
+
+impl Command {
+    /// Start a command with the given name, e.g. `SET`.
+    pub fn new(name: impl Into<RespValue>) -> Self {
+        Command::with_capacity(name, DEFAULT_CAPACITY)
+    }
+
+    /// Start a command, reserving space for `capacity` arguments, including the name.
+    pub fn with_capacity(name: impl Into<RespValue>, capacity: usize) -> Self {
+        let mut args = Vec::with_capacity(capacity.max(1));
+        args.push(name.into());
+        Command { args }
+    }
+
+    /// Add an argument.
+    pub fn arg(mut self, arg: impl Into<RespValue>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Add an argument if it is `Some`, `None` is skipped.
+    pub fn arg_opt<T: Into<RespValue>>(self, arg: Option<T>) -> Self {
+        match arg {
+            Some(arg) => self.arg(arg),
+            None => self,
+        }
+    }
+
+    /// Add every argument of an iterator, e.g. the keys of `DEL`.
+    pub fn args<T: Into<RespValue>>(mut self, args: impl IntoIterator<Item = T>) -> Self {
+        let args = args.into_iter();
+        self.args.reserve(args.size_hint().0);
+        self.args.extend(args.map(|arg| arg.into()));
+        self
+    }
+}
+
+impl From<Command> for RespValue {
+    fn from(command: Command) -> RespValue {
+        RespValue::Array(command.args)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Command;
+
+    use crate::resp::RespValue;
+
+    fn set(expiry: Option<u64>, nx: bool) -> RespValue {
+        Command::new("SET")
+            .arg("key")
+            .arg("value")
+            .arg_opt(expiry.map(|_| "EX"))
+            .arg_opt(expiry)
+            .arg_opt(nx.then_some("NX"))
+            .into()
+    }
+
+    #[test]
+    fn optional_arguments() {
+        assert_eq!(set(None, false), resp_array!["SET", "key", "value"]);
+        assert_eq!(
+            set(Some(10), true),
+            resp_array!["SET", "key", "value", "EX", 10u64, "NX"]
+        );
+    }
+
+    #[test]
+    fn variable_arguments() {
+        let keys = vec!["a", "b", "c"];
+        let command: RespValue = Command::with_capacity("DEL", keys.len() + 1)
+            .args(keys)
+            .into();
+        assert_eq!(command, resp_array!["DEL", "a", "b", "c"]);
+    }
+}
@@ -10,29 +10,40 @@
 
 //! Error handling
 
-use std::{error, fmt, io};
+use std::{
+    error, fmt, io,
+    net::{IpAddr, SocketAddr},
+};
 
 use futures_channel::mpsc;
 
-use crate::resp;
+use crate::{cluster::CLUSTER_SLOTS, resp};
 
 #[derive(Debug)]
 pub enum Error {
     /// A non-specific internal error that prevented an operation from completing
     Internal(String),
 
-    /// An IO error occurred
+    /// An IO error occurred, the underlying error is the `source` of this error
     IO(io::Error),
 
-    /// A RESP parsing/serialising error occurred
+    /// A RESP serialising error occurred, or a value could not be converted with `FromResp`
     Resp(String, Option<resp::RespValue>),
 
-    /// A remote error
+    /// Invalid RESP data was received, the offset is the position of the offending byte counted
+    /// from the start of the value being decoded
+    Decode(String, usize),
+
+    /// A remote error, i.e. an error reply from Redis.  By convention the first word of the
+    /// message is an error code, e.g. `WRONGTYPE`, see `remote_kind` to inspect it.
     Remote(String),
 
     /// Error creating a connection, or an error with a connection being closed unexpectedly
     Connection(ConnectionReason),
 
+    /// A response was not received within the time allowed, see `PairedConnection::send_timeout`
+    Timeout,
+
     /// An unexpected error.  In this context "unexpected" means
     /// "unexpected because we check ahead of time", it used to maintain the type signature of
     /// chains of futures; but it occurring at runtime should be considered a catastrophic
@@ -55,6 +66,174 @@ pub(crate) fn resp(msg: impl Into<String>, resp: resp::RespValue) -> Error {
     Error::Resp(msg.into(), Some(resp))
 }
 
+impl Error {
+    /// The error code of a remote error, this is the first word of the message if it is written
+    /// in capitals, e.g. `WRONGTYPE`.  `None` if this isn't a remote error or it has no code.
+    pub fn remote_code(&self) -> Option<&str> {
+        match self {
+            Error::Remote(msg) => split_code(msg).0,
+            _ => None,
+        }
+    }
+
+    /// The message of a remote error without its error code.
+    pub fn remote_message(&self) -> Option<&str> {
+        match self {
+            Error::Remote(msg) => Some(split_code(msg).1),
+            _ => None,
+        }
+    }
+
+    /// The kind of a remote error, based on its error code.  `None` if this isn't a remote error
+    /// or it has no code.
+    pub fn remote_kind(&self) -> Option<RemoteErrorKind> {
+        self.remote_code().map(RemoteErrorKind::from_code)
+    }
+
+    /// The cluster redirect of a `MOVED` or `ASK` remote error.  `None` for any other error,
+    /// including redirects which can't be parsed.
+    pub fn redirect(&self) -> Option<Redirect> {
+        match self {
+            Error::Remote(msg) => Redirect::parse(msg),
+            _ => None,
+        }
+    }
+}
+
+fn split_code(msg: &str) -> (Option<&str>, &str) {
+    let (code, rest) = msg.split_once(' ').unwrap_or((msg, ""));
+    if !code.is_empty() && code.bytes().all(|b| b.is_ascii_uppercase() || b == b'_') {
+        (Some(code), rest)
+    } else {
+        (None, msg)
+    }
+}
+
+/// The kind of an error reply from Redis, determined by its error code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteErrorKind {
+    /// `ERR`, a generic error
+    Err,
+    /// `WRONGTYPE`, an operation against a key holding the wrong kind of value
+    WrongType,
+    /// `NOSCRIPT`, no script matching the SHA1 digest given to `EVALSHA`
+    NoScript,
+    /// `NOAUTH`, authentication is required
+    NoAuth,
+    /// `WRONGPASS`, invalid username or password
+    WrongPass,
+    /// `NOPERM`, the user doesn't have permission for the command
+    NoPerm,
+    /// `LOADING`, the server is loading the dataset into memory
+    Loading,
+    /// `BUSY`, the server is busy running a script
+    Busy,
+    /// `BUSYGROUP`, the consumer group already exists
+    BusyGroup,
+    /// `NOGROUP`, the consumer group or key does not exist
+    NoGroup,
+    /// `READONLY`, a write against a read-only replica
+    ReadOnly,
+    /// `OOM`, the command is not allowed when used memory is over the limit
+    OutOfMemory,
+    /// `EXECABORT`, a transaction was discarded because of previous errors
+    ExecAbort,
+    /// `MOVED`, the key's hash slot is served by another node in the cluster
+    Moved,
+    /// `ASK`, the key's hash slot is being migrated to another node in the cluster
+    Ask,
+    /// `TRYAGAIN`, the operation can't be performed during resharding
+    TryAgain,
+    /// `CLUSTERDOWN`, the cluster is down
+    ClusterDown,
+    /// `CROSSSLOT`, the keys of a command don't hash to the same slot
+    CrossSlot,
+    /// Any other error code
+    Other(String),
+}
+
+impl RemoteErrorKind {
+    /// The kind of error for the given error code, e.g. `WRONGTYPE`
+    pub fn from_code(code: &str) -> Self {
+        match code {
+            "ERR" => RemoteErrorKind::Err,
+            "WRONGTYPE" => RemoteErrorKind::WrongType,
+            "NOSCRIPT" => RemoteErrorKind::NoScript,
+            "NOAUTH" => RemoteErrorKind::NoAuth,
+            "WRONGPASS" => RemoteErrorKind::WrongPass,
+            "NOPERM" => RemoteErrorKind::NoPerm,
+            "LOADING" => RemoteErrorKind::Loading,
+            "BUSY" => RemoteErrorKind::Busy,
+            "BUSYGROUP" => RemoteErrorKind::BusyGroup,
+            "NOGROUP" => RemoteErrorKind::NoGroup,
+            "READONLY" => RemoteErrorKind::ReadOnly,
+            "OOM" => RemoteErrorKind::OutOfMemory,
+            "EXECABORT" => RemoteErrorKind::ExecAbort,
+            "MOVED" => RemoteErrorKind::Moved,
+            "ASK" => RemoteErrorKind::Ask,
+            "TRYAGAIN" => RemoteErrorKind::TryAgain,
+            "CLUSTERDOWN" => RemoteErrorKind::ClusterDown,
+            "CROSSSLOT" => RemoteErrorKind::CrossSlot,
+            code => RemoteErrorKind::Other(code.to_string()),
+        }
+    }
+}
+
+/// The kind of a cluster redirect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectKind {
+    /// The hash slot has moved to another node, all future commands for it should be sent there
+    Moved,
+    /// The hash slot is being migrated to another node, only the next command should be sent
+    /// there, preceded by `ASKING`
+    Ask,
+}
+
+/// A redirect to another node of a Redis Cluster, e.g. `MOVED 3999 127.0.0.1:6381`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Redirect {
+    /// The hash slot of the key
+    pub slot: u16,
+    /// The address of the node serving the hash slot
+    pub addr: SocketAddr,
+    /// Whether the redirect is permanent or temporary
+    pub kind: RedirectKind,
+}
+
+impl Redirect {
+    /// Parse a redirect from the message of an error reply, `None` if the message isn't a
+    /// redirect.
+    pub fn parse(msg: &str) -> Option<Redirect> {
+        let mut parts = msg.split(' ');
+        let kind = match parts.next()? {
+            "MOVED" => RedirectKind::Moved,
+            "ASK" => RedirectKind::Ask,
+            _ => return None,
+        };
+        let slot = parts
+            .next()?
+            .parse()
+            .ok()
+            .filter(|slot| *slot < CLUSTER_SLOTS)?;
+        let addr = parse_addr(parts.next()?)?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Redirect { slot, addr, kind })
+    }
+}
+
+/// Parse an address, IPv6 addresses may or may not be in brackets, e.g. both `[::1]:6379` and
+/// `::1:6379` are accepted
+pub(crate) fn parse_addr(addr: &str) -> Option<SocketAddr> {
+    if let Ok(addr) = addr.parse() {
+        return Some(addr);
+    }
+    let (ip, port) = addr.rsplit_once(':')?;
+    let ip: IpAddr = ip.parse().ok()?;
+    Some(SocketAddr::new(ip, port.parse().ok()?))
+}
+
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
         Error::IO(err)
@@ -80,8 +259,11 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::Internal(s) => write!(f, "{}", s),
-            Error::IO(err) => write!(f, "{}", err),
-            Error::Resp(s, resp) => write!(f, "{}: {:?}", s, resp),
+            // The underlying error is available as the `source`, so isn't repeated here
+            Error::IO(_) => write!(f, "IO error"),
+            Error::Resp(s, Some(resp)) => write!(f, "{}: {:?}", s, resp),
+            Error::Resp(s, None) => write!(f, "{}", s),
+            Error::Decode(s, offset) => write!(f, "{} at byte {}", s, offset),
             Error::Remote(s) => write!(f, "{}", s),
             Error::Connection(ConnectionReason::Connected) => {
                 write!(f, "Connection already established")
@@ -93,6 +275,7 @@ impl fmt::Display for Error {
             Error::Connection(ConnectionReason::NotConnected) => {
                 write!(f, "Connection has been closed")
             }
+            Error::Timeout => write!(f, "Timed out waiting for a response"),
             Error::Unexpected(err) => write!(f, "{}", err),
         }
     }
@@ -114,3 +297,110 @@ pub enum ConnectionReason {
     /// clients should try again
     NotConnected,
 }
+
+#[cfg(test)]
+mod test {
+    use std::{error, io, net::SocketAddr};
+
+    use super::{Error, Redirect, RedirectKind, RemoteErrorKind};
+
+    #[test]
+    fn remote_error_kind() {
+        let msg = "WRONGTYPE Operation against a key holding the wrong kind of value";
+        let error = Error::Remote(msg.to_string());
+        assert_eq!(error.remote_code(), Some("WRONGTYPE"));
+        assert_eq!(error.remote_kind(), Some(RemoteErrorKind::WrongType));
+        assert_eq!(
+            error.remote_message(),
+            Some("Operation against a key holding the wrong kind of value")
+        );
+        assert_eq!(error.to_string(), msg);
+
+        let error = Error::Remote("NOTACODE".to_string());
+        assert_eq!(
+            error.remote_kind(),
+            Some(RemoteErrorKind::Other("NOTACODE".to_string()))
+        );
+        assert_eq!(error.remote_message(), Some(""));
+    }
+
+    #[test]
+    fn remote_error_without_code() {
+        let msg = "something went wrong";
+        let error = Error::Remote(msg.to_string());
+        assert_eq!(error.remote_code(), None);
+        assert_eq!(error.remote_kind(), None);
+        assert_eq!(error.remote_message(), Some(msg));
+        assert_eq!(error.to_string(), msg);
+
+        assert_eq!(Error::Internal("ERR".to_string()).remote_kind(), None);
+    }
+
+    #[test]
+    fn redirects() {
+        let error = Error::Remote("MOVED 3999 127.0.0.1:6381".to_string());
+        assert_eq!(error.remote_kind(), Some(RemoteErrorKind::Moved));
+        assert_eq!(
+            error.redirect(),
+            Some(Redirect {
+                slot: 3999,
+                addr: "127.0.0.1:6381".parse().unwrap(),
+                kind: RedirectKind::Moved,
+            })
+        );
+
+        let ipv6: SocketAddr = "[::1]:6381".parse().unwrap();
+        for msg in ["ASK 12 [::1]:6381", "ASK 12 ::1:6381"] {
+            assert_eq!(
+                Redirect::parse(msg),
+                Some(Redirect {
+                    slot: 12,
+                    addr: ipv6,
+                    kind: RedirectKind::Ask,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_redirects() {
+        for msg in [
+            "MOVED",
+            "MOVED 3999",
+            "MOVED abc 127.0.0.1:6381",
+            "MOVED 16384 127.0.0.1:6381",
+            "MOVED 3999 127.0.0.1",
+            "MOVED 3999 redis.example.com:6381",
+            "MOVED 3999 127.0.0.1:6381 extra",
+            "ERR 3999 127.0.0.1:6381",
+        ] {
+            let error = Error::Remote(msg.to_string());
+            assert_eq!(error.redirect(), None, "{}", msg);
+            assert_eq!(error.to_string(), msg);
+        }
+    }
+
+    #[test]
+    fn source_chain() {
+        let io_error = io::Error::new(io::ErrorKind::ConnectionRefused, "refused");
+        let error: Box<dyn error::Error + Send + Sync> = Box::new(Error::from(io_error));
+        assert_eq!(error.to_string(), "IO error");
+
+        let mut chain = Vec::new();
+        let mut current: Option<&(dyn error::Error + 'static)> = Some(error.as_ref());
+        while let Some(err) = current {
+            chain.push(err.to_string());
+            current = err.source();
+        }
+        assert_eq!(chain, ["IO error", "refused"]);
+
+        let source = error.source().unwrap();
+        let io_error = source.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io_error.kind(), io::ErrorKind::ConnectionRefused);
+        assert!(error.downcast_ref::<Error>().is_some());
+
+        let error = Error::Remote("ERR".to_string());
+        assert!(error::Error::source(&error).is_none());
+        assert_eq!(Error::Resp("bad".to_string(), None).to_string(), "bad");
+    }
+}
@@ -52,6 +52,22 @@
 //! [`subscribe`](client/pubsub/struct.PubsubConnection.html#method.subscribe) function that takes a topic as a parameter and
 //! returns a future which, once the subscription is confirmed, resolves to a stream that contains all messages published
 //! to that topic.
+//!
+//! ## Derive
+//!
+//! With the `derive` feature enabled `FromResp` can be derived for structs, see
+//! [`resp::FromResp`](resp/derive.FromResp.html).
+//!
+//! ## Serde
+//!
+//! With the `serde` feature enabled [`resp::RespValue`](resp/enum.RespValue.html) implements `serde::Serialize` and
+//! `serde::Deserialize`, and [`serde::from_resp_value`](serde/fn.from_resp_value.html) deserializes replies directly in
+//! to any type implementing `serde::Deserialize`.  With the `json` feature, which implies `serde`, `RespValue` can also be
+//! converted to and from a `serde_json::Value`.  The mapping between RESP and Serde's data model, and JSON, is described in the [`serde`](serde/index.html) module.
+
+// Allows the code generated by the derive macros to refer to `::redis_async` within this crate
+#[cfg(feature = "derive")]
+extern crate self as redis_async;
 
 #[macro_use]
 pub mod resp;
@@ -59,6 +75,13 @@ pub mod resp;
 #[macro_use]
 pub mod client;
 
+pub mod cluster;
+
+pub mod command;
+
 pub mod error;
 
+#[cfg(feature = "serde")]
+pub mod serde;
+
 pub(crate) mod reconnect;
@@ -154,6 +154,14 @@ where
         }
     }
 
+    /// Whether there is an established connection which `is_open` reports as still usable
+    pub(crate) fn is_connected(&self, is_open: impl FnOnce(&T) -> bool) -> bool {
+        match *self.0.state.lock().expect("Poisoned lock") {
+            Connected(ref t) => is_open(t),
+            _ => false,
+        }
+    }
+
     /// Returns a future that completes when the connection is established or failed to establish
     /// used only for timing.
     fn reconnect(
@@ -10,24 +10,66 @@
 
 //! An implementation of the RESP protocol
 
-use std::collections::HashMap;
-use std::hash::{BuildHasher, Hash};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
 use std::io;
+use std::mem;
+use std::net::{IpAddr, SocketAddr};
+use std::num::IntErrorKind;
+use std::ops::Index;
 use std::str;
 use std::sync::Arc;
+use std::time::Duration;
 
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 use tokio_util::codec::{Decoder, Encoder};
 
 use super::error::{self, Error};
 
+/// Derive `FromResp` for a struct with named fields, converting it from a `Map` or a flat array of
+/// keys and values.  Requires the `derive` feature.
+///
+/// ```
+/// use redis_async::{resp::{FromResp, RespValue}, resp_array};
+///
+/// #[derive(FromResp)]
+/// struct Entry {
+///     #[resp(rename = "entry-id")]
+///     id: i64,
+///     name: String,
+///     #[resp(default)]
+///     tags: Vec<String>,
+/// }
+///
+/// let entry = Entry::from_resp(resp_array!["entry-id", RespValue::Integer(3), "name", "first"]).unwrap();
+/// assert_eq!(entry.id, 3);
+/// assert_eq!(entry.name, "first");
+/// assert!(entry.tags.is_empty());
+/// ```
+#[cfg(feature = "derive")]
+pub use redis_async_derive::FromResp;
+
 /// A single RESP value, this owns the data that is read/to-be written to Redis.
 ///
 /// It is cloneable to allow multiple copies to be delivered in certain circumstances, e.g. multiple
 /// subscribers to the same topic.
-#[derive(Debug, Clone, Eq, PartialEq)]
+///
+/// Values are compared structurally, doubles are compared by their bit pattern so that equality
+/// is reflexive, i.e. `NaN` is equal to itself but `0.0` is not equal to `-0.0`.
+///
+/// Values are also totally ordered, so they can be used as the keys of a `BTreeMap`.  Values of
+/// different types are ordered by the position of their variant below, e.g. `Nil` comes before
+/// everything else, and values of the same type by their content.  Doubles are ordered with
+/// `f64::total_cmp`, which is consistent with comparing bit patterns.
+#[derive(Debug, Clone)]
 pub enum RespValue {
+    /// A null value, Redis uses both null bulk strings (`$-1`) and null arrays (`*-1`) to
+    /// represent this, as well as the dedicated RESP3 null (`_`), all are decoded to `Nil`.  `Nil`
+    /// is encoded as a null bulk string in RESP2 and as the RESP3 null in RESP3.
     Nil,
 
     /// Zero, one or more other `RespValue`s.
@@ -45,9 +87,260 @@ pub enum RespValue {
     Integer(i64),
 
     SimpleString(String),
+
+    /// A RESP3 double, this includes infinities and `NaN`.
+    Double(f64),
+
+    /// A RESP3 boolean
+    Boolean(bool),
+
+    /// A RESP3 big number, an integer of arbitrary size, stored as its decimal digits with an
+    /// optional leading `-`.
+    BigNumber(String),
+
+    /// A RESP3 verbatim string, `format` describes the type of the data, e.g. `txt` for plain text
+    /// or `mkd` for markdown.
+    Verbatim {
+        format: [u8; 3],
+        data: Vec<u8>,
+    },
+
+    /// A RESP3 map, an ordered sequence of key/value pairs.
+    Map(Vec<(RespValue, RespValue)>),
+
+    /// A RESP3 set, an unordered collection of distinct values.
+    Set(Vec<RespValue>),
+
+    /// A RESP3 push, out-of-band data such as Pub/Sub messages or client-side caching
+    /// invalidations, which is not the reply to any particular command.
+    Push(Vec<RespValue>),
+}
+
+impl PartialEq for RespValue {
+    fn eq(&self, other: &RespValue) -> bool {
+        match (self, other) {
+            (RespValue::Nil, RespValue::Nil) => true,
+            (RespValue::Array(a), RespValue::Array(b)) => a == b,
+            (RespValue::BulkString(a), RespValue::BulkString(b)) => a == b,
+            (RespValue::Error(a), RespValue::Error(b)) => a == b,
+            (RespValue::Integer(a), RespValue::Integer(b)) => a == b,
+            (RespValue::SimpleString(a), RespValue::SimpleString(b)) => a == b,
+            (RespValue::Double(a), RespValue::Double(b)) => a.to_bits() == b.to_bits(),
+            (RespValue::Boolean(a), RespValue::Boolean(b)) => a == b,
+            (RespValue::BigNumber(a), RespValue::BigNumber(b)) => a == b,
+            (
+                RespValue::Verbatim { format, data },
+                RespValue::Verbatim {
+                    format: other_format,
+                    data: other_data,
+                },
+            ) => format == other_format && data == other_data,
+            (RespValue::Map(a), RespValue::Map(b)) => a == b,
+            (RespValue::Set(a), RespValue::Set(b)) => a == b,
+            (RespValue::Push(a), RespValue::Push(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for RespValue {}
+
+/// Compares with the literal on the left, e.g. `"OK" == reply`, see `RespValue::eq_str`.  Only
+/// this side is implemented, so that `reply == "OK".into()` still infers a `RespValue`.
+impl PartialEq<RespValue> for str {
+    fn eq(&self, other: &RespValue) -> bool {
+        other.eq_str(self)
+    }
+}
+
+impl PartialEq<RespValue> for &str {
+    fn eq(&self, other: &RespValue) -> bool {
+        other.eq_str(self)
+    }
+}
+
+/// Compares with the number on the left, e.g. `5 == reply`, see `RespValue::eq_int`
+impl PartialEq<RespValue> for i64 {
+    fn eq(&self, other: &RespValue) -> bool {
+        other.eq_int(*self)
+    }
+}
+
+impl Hash for RespValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rank().hash(state);
+        match self {
+            RespValue::Nil => (),
+            RespValue::Array(values) | RespValue::Set(values) | RespValue::Push(values) => {
+                values.hash(state)
+            }
+            RespValue::BulkString(bytes) => bytes.hash(state),
+            RespValue::Error(string)
+            | RespValue::SimpleString(string)
+            | RespValue::BigNumber(string) => string.hash(state),
+            RespValue::Integer(int) => int.hash(state),
+            RespValue::Double(double) => double.to_bits().hash(state),
+            RespValue::Boolean(boolean) => boolean.hash(state),
+            RespValue::Verbatim { format, data } => {
+                format.hash(state);
+                data.hash(state);
+            }
+            RespValue::Map(pairs) => pairs.hash(state),
+        }
+    }
+}
+
+impl Ord for RespValue {
+    fn cmp(&self, other: &RespValue) -> Ordering {
+        match (self, other) {
+            (RespValue::Array(a), RespValue::Array(b))
+            | (RespValue::Set(a), RespValue::Set(b))
+            | (RespValue::Push(a), RespValue::Push(b)) => a.cmp(b),
+            (RespValue::BulkString(a), RespValue::BulkString(b)) => a.cmp(b),
+            (RespValue::Error(a), RespValue::Error(b))
+            | (RespValue::SimpleString(a), RespValue::SimpleString(b))
+            | (RespValue::BigNumber(a), RespValue::BigNumber(b)) => a.cmp(b),
+            (RespValue::Integer(a), RespValue::Integer(b)) => a.cmp(b),
+            (RespValue::Double(a), RespValue::Double(b)) => a.total_cmp(b),
+            (RespValue::Boolean(a), RespValue::Boolean(b)) => a.cmp(b),
+            (
+                RespValue::Verbatim { format, data },
+                RespValue::Verbatim {
+                    format: other_format,
+                    data: other_data,
+                },
+            ) => (format, data).cmp(&(other_format, other_data)),
+            (RespValue::Map(a), RespValue::Map(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for RespValue {
+    fn partial_cmp(&self, other: &RespValue) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Index into an array, set or push.  This will panic for any other value or if the index is out
+/// of bounds, see `get` for a non-panicking version.
+impl Index<usize> for RespValue {
+    type Output = RespValue;
+
+    fn index(&self, index: usize) -> &RespValue {
+        match self.as_array() {
+            Some(values) => &values[index],
+            None => panic!("Cannot index into {}", self.type_name()),
+        }
+    }
+}
+
+/// Iterates over the elements of an array, set or push, e.g. the reply to `LRANGE`.  Any other
+/// value, including maps, yields nothing.
+impl IntoIterator for RespValue {
+    type Item = RespValue;
+    type IntoIter = std::vec::IntoIter<RespValue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self {
+            RespValue::Array(values) | RespValue::Set(values) | RespValue::Push(values) => {
+                values.into_iter()
+            }
+            _ => Vec::new().into_iter(),
+        }
+    }
+}
+
+/// Iterates over references to the elements of an array, set or push, any other value yields
+/// nothing.
+impl<'a> IntoIterator for &'a RespValue {
+    type Item = &'a RespValue;
+    type IntoIter = std::slice::Iter<'a, RespValue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_array().unwrap_or_default().iter()
+    }
+}
+
+/// A compact, human-readable rendering similar to `redis-cli`, e.g. `[OK, 1, (nil)]`.  Strings
+/// are shown as text, with any bytes that aren't valid UTF-8 escaped.
+impl fmt::Display for RespValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_list<'a>(
+            f: &mut fmt::Formatter,
+            values: impl IntoIterator<Item = &'a RespValue>,
+        ) -> fmt::Result {
+            for (i, value) in values.into_iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", value)?;
+            }
+            Ok(())
+        }
+
+        fn write_bytes(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+            match str::from_utf8(bytes) {
+                Ok(string) => write!(f, "{}", string),
+                Err(_) => write!(f, "{}", bytes.escape_ascii()),
+            }
+        }
+
+        match self {
+            RespValue::Nil => write!(f, "(nil)"),
+            RespValue::Array(values) | RespValue::Push(values) => {
+                write!(f, "[")?;
+                write_list(f, values)?;
+                write!(f, "]")
+            }
+            RespValue::Set(values) => {
+                write!(f, "{{")?;
+                write_list(f, values)?;
+                write!(f, "}}")
+            }
+            RespValue::Map(pairs) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", k, v)?;
+                }
+                write!(f, "}}")
+            }
+            RespValue::BulkString(bytes) => write_bytes(f, bytes),
+            RespValue::Verbatim { data, .. } => write_bytes(f, data),
+            RespValue::Error(string) => write!(f, "(error) {}", string),
+            RespValue::Integer(int) => write!(f, "{}", int),
+            RespValue::SimpleString(string) | RespValue::BigNumber(string) => {
+                write!(f, "{}", string)
+            }
+            RespValue::Double(double) => write!(f, "{}", double),
+            RespValue::Boolean(boolean) => write!(f, "{}", boolean),
+        }
+    }
 }
 
 impl RespValue {
+    /// The position of the value's variant, values of different types are ordered by this
+    fn rank(&self) -> u8 {
+        match self {
+            RespValue::Nil => 0,
+            RespValue::Array(_) => 1,
+            RespValue::BulkString(_) => 2,
+            RespValue::Error(_) => 3,
+            RespValue::Integer(_) => 4,
+            RespValue::SimpleString(_) => 5,
+            RespValue::Double(_) => 6,
+            RespValue::Boolean(_) => 7,
+            RespValue::BigNumber(_) => 8,
+            RespValue::Verbatim { .. } => 9,
+            RespValue::Map(_) => 10,
+            RespValue::Set(_) => 11,
+            RespValue::Push(_) => 12,
+        }
+    }
+
     fn into_result(self) -> Result<RespValue, Error> {
         match self {
             RespValue::Error(string) => Err(Error::Remote(string)),
@@ -55,6 +348,31 @@ impl RespValue {
         }
     }
 
+    /// The simple string `OK`, e.g. the reply to `SET`
+    pub fn ok() -> RespValue {
+        RespValue::SimpleString("OK".to_string())
+    }
+
+    /// The simple string `PONG`, the reply to `PING`
+    pub fn pong() -> RespValue {
+        RespValue::SimpleString("PONG".to_string())
+    }
+
+    /// An error reply, by convention the message starts with an error code, e.g. `ERR`
+    pub fn error(message: impl Into<String>) -> RespValue {
+        RespValue::Error(message.into())
+    }
+
+    /// A bulk string of the given bytes
+    pub fn bulk(bytes: impl Into<Vec<u8>>) -> RespValue {
+        RespValue::BulkString(bytes.into())
+    }
+
+    /// An integer
+    pub fn integer(int: i64) -> RespValue {
+        RespValue::Integer(int)
+    }
+
     /// Convenience function for building dynamic Redis commands with variable numbers of
     /// arguments, e.g. RPUSH
     ///
@@ -72,6 +390,174 @@ impl RespValue {
         self
     }
 
+    /// The value as a string, for simple strings and bulk or verbatim strings which are valid
+    /// UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            RespValue::SimpleString(string) => Some(string),
+            RespValue::BulkString(bytes) | RespValue::Verbatim { data: bytes, .. } => {
+                str::from_utf8(bytes).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// The raw bytes of a simple, bulk or verbatim string.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            RespValue::SimpleString(string) => Some(string.as_bytes()),
+            RespValue::BulkString(bytes) | RespValue::Verbatim { data: bytes, .. } => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Take the bytes of a simple, bulk or verbatim string, or the message of an error, without
+    /// copying them.  Unlike `Vec::<u8>::from_resp` an error is converted rather than returned.
+    pub fn into_bytes(self) -> Result<Vec<u8>, Error> {
+        match self {
+            RespValue::BulkString(bytes) | RespValue::Verbatim { data: bytes, .. } => Ok(bytes),
+            RespValue::SimpleString(string) | RespValue::Error(string) => Ok(string.into_bytes()),
+            _ => Err(conversion_error::<Vec<u8>>(self)),
+        }
+    }
+
+    /// An estimate of the bytes of memory used by the value, e.g. for bounding a buffer of
+    /// replies.  This is the size of the value itself plus the capacity of its heap allocations,
+    /// including those of any nested values; the overhead of the allocator is not included.
+    pub fn memory_usage(&self) -> usize {
+        mem::size_of::<RespValue>() + self.heap_usage()
+    }
+
+    fn heap_usage(&self) -> usize {
+        fn values_usage(values: &Vec<RespValue>) -> usize {
+            values.capacity() * mem::size_of::<RespValue>()
+                + values.iter().map(RespValue::heap_usage).sum::<usize>()
+        }
+
+        match self {
+            RespValue::Nil
+            | RespValue::Integer(_)
+            | RespValue::Double(_)
+            | RespValue::Boolean(_) => 0,
+            RespValue::BulkString(bytes) | RespValue::Verbatim { data: bytes, .. } => {
+                bytes.capacity()
+            }
+            RespValue::Error(string)
+            | RespValue::SimpleString(string)
+            | RespValue::BigNumber(string) => string.capacity(),
+            RespValue::Array(values) | RespValue::Set(values) | RespValue::Push(values) => {
+                values_usage(values)
+            }
+            RespValue::Map(pairs) => {
+                pairs.capacity() * mem::size_of::<(RespValue, RespValue)>()
+                    + pairs
+                        .iter()
+                        .map(|(key, value)| key.heap_usage() + value.heap_usage())
+                        .sum::<usize>()
+            }
+        }
+    }
+
+    /// Whether this is a simple, bulk or verbatim string of the same text, e.g. for
+    /// `reply.eq_str("OK")`.
+    pub fn eq_str(&self, other: &str) -> bool {
+        self.as_bytes() == Some(other.as_bytes())
+    }
+
+    /// Whether this is an integer, or a double, with the same value.
+    pub fn eq_int(&self, other: i64) -> bool {
+        match self {
+            RespValue::Integer(int) => *int == other,
+            RespValue::Double(double) => *double == other as f64,
+            _ => false,
+        }
+    }
+
+    /// The value of an integer, unlike `i64::from_resp` strings are not parsed.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            RespValue::Integer(int) => Some(*int),
+            _ => None,
+        }
+    }
+
+    /// The elements of an array, set or push.
+    pub fn as_array(&self) -> Option<&[RespValue]> {
+        match self {
+            RespValue::Array(values) | RespValue::Set(values) | RespValue::Push(values) => {
+                Some(values)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether this is an error reply.
+    pub fn is_error(&self) -> bool {
+        matches!(self, RespValue::Error(_))
+    }
+
+    /// Whether this is a null value.
+    pub fn is_nil(&self) -> bool {
+        matches!(self, RespValue::Nil)
+    }
+
+    /// The length of the value:
+    ///
+    /// * for arrays, sets and pushes the number of elements.
+    /// * for maps the number of key/value pairs.
+    /// * for simple, bulk and verbatim strings the number of bytes, excluding the format of
+    ///   verbatim strings.
+    /// * zero for everything else, i.e. nil, errors, numbers and booleans.
+    pub fn len(&self) -> usize {
+        match self {
+            RespValue::Array(values) | RespValue::Set(values) | RespValue::Push(values) => {
+                values.len()
+            }
+            RespValue::Map(pairs) => pairs.len(),
+            RespValue::SimpleString(string) => string.len(),
+            RespValue::BulkString(bytes) | RespValue::Verbatim { data: bytes, .. } => bytes.len(),
+            _ => 0,
+        }
+    }
+
+    /// Whether `len` is zero.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The element at `index` of an array, set or push, `None` for other values or if `index` is
+    /// out of bounds.
+    pub fn get(&self, index: usize) -> Option<&RespValue> {
+        self.as_array().and_then(|values| values.get(index))
+    }
+
+    /// The name of the type of value, e.g. "bulk-string", this is used in conversion errors.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RespValue::Nil => "nil",
+            RespValue::Array(_) => "array",
+            RespValue::BulkString(_) => "bulk-string",
+            RespValue::Error(_) => "error",
+            RespValue::Integer(_) => "integer",
+            RespValue::SimpleString(_) => "simple-string",
+            RespValue::Double(_) => "double",
+            RespValue::Boolean(_) => "boolean",
+            RespValue::BigNumber(_) => "big-number",
+            RespValue::Verbatim { .. } => "verbatim-string",
+            RespValue::Map(_) => "map",
+            RespValue::Set(_) => "set",
+            RespValue::Push(_) => "push",
+        }
+    }
+
+    /// The format of a verbatim string, e.g. `txt`, or `None` if this is not a verbatim string
+    pub fn verbatim_format(&self) -> Option<&str> {
+        match self {
+            RespValue::Verbatim { format, .. } => str::from_utf8(format).ok(),
+            _ => None,
+        }
+    }
+
     /// Push item to Resp array
     ///
     /// This will panic if called for anything other than arrays
@@ -92,13 +578,40 @@ impl RespValue {
 pub trait FromResp: Sized {
     /// Return a `Result` containing either `Self` or `Error`.  Errors can occur due to either: a) the particular
     /// `RespValue` being incompatible with the required type, or b) a remote Redis error occuring.
+    ///
+    /// An error reply is returned as `Error::Remote` before the conversion for the type is tried, so
+    /// e.g. `RespValue::from_resp` never returns a `RespValue::Error`.
     fn from_resp(resp: RespValue) -> Result<Self, Error> {
         Self::from_resp_int(resp.into_result()?)
     }
 
+    /// Convert the value without first turning an error reply into `Error::Remote`, so that error
+    /// replies reach the conversion for the type, e.g. for a proxy which forwards them unchanged
+    /// `RespValue::from_resp_raw` returns a `RespValue::Error` as it is.  Types which can't be
+    /// converted from an error reply return a conversion error instead.
+    ///
+    /// Only the value itself is converted this way, the elements of aggregates such as `Vec<T>`
+    /// are converted with `from_resp`, use `Vec<Result<T, Error>>` to read errors within arrays.
+    fn from_resp_raw(resp: RespValue) -> Result<Self, Error> {
+        Self::from_resp_int(resp)
+    }
+
+    /// The conversion for the type, this is what implementations should provide, it is called by
+    /// `from_resp` once any error reply has been handled.
     fn from_resp_int(resp: RespValue) -> Result<Self, Error>;
 }
 
+/// The error for a value that cannot be converted into `T`, e.g. "cannot convert array into
+/// alloc::string::String"
+fn conversion_error<T>(resp: RespValue) -> Error {
+    let msg = format!(
+        "cannot convert {} into {}",
+        resp.type_name(),
+        std::any::type_name::<T>()
+    );
+    error::resp(msg, resp)
+}
+
 impl FromResp for RespValue {
     fn from_resp_int(resp: RespValue) -> Result<RespValue, Error> {
         Ok(resp)
@@ -109,9 +622,68 @@ impl FromResp for String {
     fn from_resp_int(resp: RespValue) -> Result<String, Error> {
         match resp {
             RespValue::BulkString(ref bytes) => Ok(String::from_utf8_lossy(bytes).into_owned()),
+            RespValue::Verbatim { ref data, .. } => Ok(String::from_utf8_lossy(data).into_owned()),
             RespValue::Integer(i) => Ok(i.to_string()),
             RespValue::SimpleString(string) => Ok(string),
-            _ => Err(error::resp("Cannot convert into a string", resp)),
+            RespValue::BigNumber(string) => Ok(string),
+            _ => Err(conversion_error::<Self>(resp)),
+        }
+    }
+}
+
+/// A `String` that must be valid UTF-8.
+///
+/// `String`'s `FromResp` implementation converts invalid UTF-8 lossily, replacing invalid bytes
+/// with the replacement character (U+FFFD), whereas converting invalid UTF-8 into a `Utf8String` is
+/// an error.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Utf8String(pub String);
+
+impl From<Utf8String> for String {
+    fn from(string: Utf8String) -> String {
+        string.0
+    }
+}
+
+impl std::ops::Deref for Utf8String {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Utf8String {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromResp for Utf8String {
+    fn from_resp_int(resp: RespValue) -> Result<Utf8String, Error> {
+        match resp {
+            RespValue::BulkString(bytes) => match String::from_utf8(bytes) {
+                Ok(string) => Ok(Utf8String(string)),
+                Err(e) => Err(error::resp(
+                    "invalid utf-8",
+                    RespValue::BulkString(e.into_bytes()),
+                )),
+            },
+            RespValue::Verbatim { format, data } => match String::from_utf8(data) {
+                Ok(string) => Ok(Utf8String(string)),
+                Err(e) => Err(error::resp(
+                    "invalid utf-8",
+                    RespValue::Verbatim {
+                        format,
+                        data: e.into_bytes(),
+                    },
+                )),
+            },
+            RespValue::Integer(i) => Ok(Utf8String(i.to_string())),
+            RespValue::SimpleString(string) | RespValue::BigNumber(string) => {
+                Ok(Utf8String(string))
+            }
+            _ => Err(conversion_error::<Self>(resp)),
         }
     }
 }
@@ -120,74 +692,140 @@ impl FromResp for Arc<str> {
     fn from_resp_int(resp: RespValue) -> Result<Arc<str>, Error> {
         match resp {
             RespValue::BulkString(ref bytes) => Ok(String::from_utf8_lossy(bytes).into()),
-            _ => Err(error::resp("Cannot convert into a Arc<str>", resp)),
+            _ => Err(conversion_error::<Self>(resp)),
         }
     }
 }
 
+/// Converted as a `String`, so is always owned.
+impl FromResp for Cow<'static, str> {
+    fn from_resp_int(resp: RespValue) -> Result<Cow<'static, str>, Error> {
+        String::from_resp_int(resp).map(Cow::Owned)
+    }
+}
+
+/// The raw bytes of a bulk string, for binary data, unlike `String` no UTF-8 conversion occurs.
 impl FromResp for Vec<u8> {
     fn from_resp_int(resp: RespValue) -> Result<Vec<u8>, Error> {
         match resp {
             RespValue::BulkString(bytes) => Ok(bytes),
-            _ => Err(error::resp("Not a bulk string", resp)),
+            RespValue::Verbatim { data, .. } => Ok(data),
+            RespValue::SimpleString(string) => Ok(string.into_bytes()),
+            _ => Err(conversion_error::<Self>(resp)),
         }
     }
 }
 
-impl FromResp for i64 {
-    fn from_resp_int(resp: RespValue) -> Result<i64, Error> {
-        match resp {
-            RespValue::Integer(i) => Ok(i),
-            _ => Err(error::resp("Cannot be converted into an i64", resp)),
-        }
+impl FromResp for Bytes {
+    fn from_resp_int(resp: RespValue) -> Result<Bytes, Error> {
+        Vec::<u8>::from_resp_int(resp).map(Bytes::from)
     }
 }
 
+/// Integers are usually returned as `RespValue::Integer`, but some commands (e.g. `HGET` of a
+/// counter) return them as strings, so those are parsed too.  Values that don't fit in the target
+/// type are an error rather than being truncated.
+///
+/// `u8` is deliberately not included, as `Vec<u8>` is used for binary bulk strings.
 macro_rules! impl_fromresp_integers {
     ($($int_ty:ident),* $(,)*) => {
         $(
-            #[allow(clippy::cast_lossless)]
             impl FromResp for $int_ty {
                 fn from_resp_int(resp: RespValue) -> Result<Self, Error> {
-                    i64::from_resp_int(resp).and_then(|x| {
-                        // $int_ty::max_value() as i64 > 0 should be optimized out. It tests if
-                        // the target integer type needs an "upper bounds" check
-                        if x < ($int_ty::min_value() as i64)
-                            || ($int_ty::max_value() as i64 > 0
-                                && x > ($int_ty::max_value() as i64))
+                    let string = match resp {
+                        RespValue::Integer(x) => {
+                            return $int_ty::try_from(x).map_err(|_| {
+                                error::resp(
+                                    concat!("value out of range for ", stringify!($int_ty)),
+                                    resp,
+                                )
+                            })
+                        }
+                        RespValue::BulkString(ref bytes) => str::from_utf8(bytes).ok(),
+                        RespValue::SimpleString(ref string) | RespValue::BigNumber(ref string) => {
+                            Some(string.as_str())
+                        }
+                        _ => None,
+                    };
+                    // Only a string of digits which doesn't fit is out of range, anything else
+                    // isn't an integer at all
+                    match string.map(str::parse::<$int_ty>) {
+                        Some(Ok(x)) => Ok(x),
+                        Some(Err(e))
+                            if matches!(
+                                e.kind(),
+                                IntErrorKind::PosOverflow | IntErrorKind::NegOverflow
+                            ) =>
                         {
                             Err(error::resp(
-                                concat!(
-                                    "i64 value cannot be represented as {}",
-                                    stringify!($int_ty),
-                                ),
-                                RespValue::Integer(x),
+                                concat!("value out of range for ", stringify!($int_ty)),
+                                resp,
                             ))
-                        } else {
-                            Ok(x as $int_ty)
                         }
-                    })
+                        _ => Err(conversion_error::<Self>(resp)),
+                    }
                 }
             }
         )*
     };
 }
 
-impl_fromresp_integers!(isize, usize, i32, u32, u64);
+impl_fromresp_integers!(i64, isize, usize, i32, u32, u64, i16, u16, i8);
 
+/// Redis returns floating point numbers as strings, including the tokens `inf`, `-inf` and `nan`,
+/// all of which are understood by Rust's float parsing.
+macro_rules! impl_fromresp_floats {
+    ($($float_ty:ident),* $(,)*) => {
+        $(
+            impl FromResp for $float_ty {
+                fn from_resp_int(resp: RespValue) -> Result<Self, Error> {
+                    let parsed = match resp {
+                        RespValue::BulkString(ref bytes) => str::from_utf8(bytes)
+                            .ok()
+                            .and_then(|string| string.trim().parse().ok()),
+                        RespValue::SimpleString(ref string) => string.trim().parse().ok(),
+                        RespValue::Integer(x) => Some(x as $float_ty),
+                        RespValue::Double(x) => Some(x as $float_ty),
+                        _ => None,
+                    };
+                    parsed.ok_or_else(|| conversion_error::<Self>(resp))
+                }
+            }
+        )*
+    };
+}
+
+impl_fromresp_floats!(f64, f32);
+
+/// Any non-zero integer, or a simple string of `OK`, is `true`.  Bulk strings aren't accepted, as
+/// there's no unambiguous way of interpreting them.
 impl FromResp for bool {
     fn from_resp_int(resp: RespValue) -> Result<bool, Error> {
-        i64::from_resp_int(resp).and_then(|x| match x {
-            0 => Ok(false),
-            1 => Ok(true),
-            _ => Err(error::resp(
-                "i64 value cannot be represented as bool",
-                RespValue::Integer(x),
-            )),
-        })
+        match resp {
+            RespValue::Integer(x) => Ok(x != 0),
+            RespValue::Boolean(b) => Ok(b),
+            RespValue::SimpleString(ref string) if string == "OK" => Ok(true),
+            _ => Err(conversion_error::<Self>(resp)),
+        }
+    }
+}
+
+/// A string of exactly one character, e.g. a single-character flag.  Bulk strings must be valid
+/// UTF-8, so may be more than one byte.
+impl FromResp for char {
+    fn from_resp_int(resp: RespValue) -> Result<char, Error> {
+        let mut chars = match resp.as_str() {
+            Some(string) => string.chars(),
+            None => return Err(conversion_error::<Self>(resp)),
+        };
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(conversion_error::<Self>(resp)),
+        }
     }
 }
 
+/// A `Nil` value, e.g. the reply to a `GET` of a key that doesn't exist, is `None`.
 impl<T: FromResp> FromResp for Option<T> {
     fn from_resp_int(resp: RespValue) -> Result<Option<T>, Error> {
         match resp {
@@ -197,125 +835,326 @@ impl<T: FromResp> FromResp for Option<T> {
     }
 }
 
+impl<T: FromResp> FromResp for Box<T> {
+    fn from_resp(resp: RespValue) -> Result<Box<T>, Error> {
+        T::from_resp(resp).map(Box::new)
+    }
+
+    fn from_resp_int(resp: RespValue) -> Result<Box<T>, Error> {
+        T::from_resp_int(resp).map(Box::new)
+    }
+}
+
+/// Captures the outcome of the conversion rather than failing, including error replies from
+/// Redis, which become `Err(Error::Remote)`.  This allows arrays with a mix of values and errors,
+/// e.g. the reply to `EXEC`, to be read in full as `Vec<Result<T, Error>>`.
+impl<T: FromResp> FromResp for Result<T, Error> {
+    fn from_resp(resp: RespValue) -> Result<Result<T, Error>, Error> {
+        Self::from_resp_int(resp)
+    }
+
+    fn from_resp_int(resp: RespValue) -> Result<Result<T, Error>, Error> {
+        match resp {
+            RespValue::Error(string) => Ok(Err(Error::Remote(string))),
+            x => Ok(T::from_resp_int(x)),
+        }
+    }
+}
+
+/// A `Nil` value, i.e. a null array, is an empty `Vec`, use `Option<Vec<T>>` to distinguish the
+/// two.
 impl<T: FromResp> FromResp for Vec<T> {
     fn from_resp_int(resp: RespValue) -> Result<Vec<T>, Error> {
         match resp {
-            RespValue::Array(ary) => {
+            RespValue::Array(ary) | RespValue::Set(ary) | RespValue::Push(ary) => {
                 let mut ar = Vec::with_capacity(ary.len());
                 for value in ary {
                     ar.push(T::from_resp(value)?);
                 }
                 Ok(ar)
             }
-            _ => Err(error::resp("Cannot be converted into a vector", resp)),
+            RespValue::Nil => Ok(Vec::new()),
+            _ => Err(conversion_error::<Self>(resp)),
         }
     }
 }
 
-impl<K: FromResp + Hash + Eq, T: FromResp, S: BuildHasher + Default> FromResp for HashMap<K, T, S> {
-    fn from_resp_int(resp: RespValue) -> Result<HashMap<K, T, S>, Error> {
+/// Sets can be read from either a RESP3 set, or an array of elements.
+impl<T: FromResp + Hash + Eq, S: BuildHasher + Default> FromResp for HashSet<T, S> {
+    fn from_resp_int(resp: RespValue) -> Result<HashSet<T, S>, Error> {
         match resp {
-            RespValue::Array(ary) => {
-                let mut map = HashMap::with_capacity_and_hasher(ary.len(), S::default());
-                let mut items = ary.into_iter();
-
-                while let Some(k) = items.next() {
-                    let key = K::from_resp(k)?;
-                    let value = T::from_resp(items.next().ok_or_else(|| {
-                        error::resp(
-                            "Cannot convert an odd number of elements into a hashmap",
-                            "".into(),
-                        )
-                    })?)?;
-
-                    map.insert(key, value);
+            RespValue::Array(ary) | RespValue::Set(ary) => {
+                let mut set = HashSet::with_capacity_and_hasher(ary.len(), S::default());
+                for value in ary {
+                    set.insert(T::from_resp(value)?);
                 }
-
-                Ok(map)
+                Ok(set)
             }
-            _ => Err(error::resp("Cannot be converted into a hashmap", resp)),
+            _ => Err(conversion_error::<Self>(resp)),
         }
     }
 }
 
-impl FromResp for () {
-    fn from_resp_int(resp: RespValue) -> Result<(), Error> {
+impl<T: FromResp + Ord> FromResp for BTreeSet<T> {
+    fn from_resp_int(resp: RespValue) -> Result<BTreeSet<T>, Error> {
         match resp {
-            RespValue::SimpleString(string) => match string.as_ref() {
-                "OK" => Ok(()),
-                _ => Err(Error::Resp(
-                    format!("Unexpected value within SimpleString: {}", string),
-                    None,
-                )),
-            },
-            _ => Err(error::resp(
-                "Unexpected value, should be encoded as a SimpleString",
-                resp,
-            )),
+            RespValue::Array(ary) | RespValue::Set(ary) => {
+                ary.into_iter().map(T::from_resp).collect()
+            }
+            _ => Err(conversion_error::<Self>(resp)),
         }
     }
 }
 
-impl<A, B> FromResp for (A, B)
-where
-    A: FromResp,
-    B: FromResp,
-{
-    fn from_resp_int(resp: RespValue) -> Result<(A, B), Error> {
-        match resp {
-            RespValue::Array(ary) => {
-                if ary.len() == 2 {
-                    let mut ary_iter = ary.into_iter();
-                    Ok((
-                        A::from_resp(ary_iter.next().expect("No value"))?,
-                        B::from_resp(ary_iter.next().expect("No value"))?,
-                    ))
-                } else {
-                    Err(Error::Resp(
-                        format!("Array needs to be 2 elements, is: {}", ary.len()),
-                        None,
-                    ))
-                }
-            }
-            _ => Err(error::resp(
-                "Unexpected value, should be encoded as an array",
-                resp,
-            )),
-        }
+/// Convert a flat array of alternating keys and values into pairs, `into` describes the type
+/// being converted into for the error if there are an odd number of elements.
+fn pairs_from_flat_array<K: FromResp, V: FromResp>(
+    ary: Vec<RespValue>,
+    into: &str,
+) -> Result<Vec<(K, V)>, Error> {
+    if ary.len() % 2 == 1 {
+        return Err(Error::Resp(
+            format!("Cannot convert an odd number of elements into {}", into),
+            Some(RespValue::Array(ary)),
+        ));
+    }
+
+    let mut pairs = Vec::with_capacity(ary.len() / 2);
+    let mut items = ary.into_iter();
+
+    while let (Some(k), Some(v)) = (items.next(), items.next()) {
+        let key = K::from_resp(k)?;
+        let value = V::from_resp(v)?;
+
+        pairs.push((key, value));
     }
+
+    Ok(pairs)
 }
 
-impl<A, B, C> FromResp for (A, B, C)
-where
-    A: FromResp,
-    B: FromResp,
-    C: FromResp,
-{
-    fn from_resp_int(resp: RespValue) -> Result<(A, B, C), Error> {
+/// Commands such as `HGETALL` return a flat array of alternating keys and values, or a map when
+/// using RESP3.
+impl<K: FromResp + Hash + Eq, T: FromResp, S: BuildHasher + Default> FromResp for HashMap<K, T, S> {
+    fn from_resp_int(resp: RespValue) -> Result<HashMap<K, T, S>, Error> {
         match resp {
             RespValue::Array(ary) => {
-                if ary.len() == 3 {
-                    let mut ary_iter = ary.into_iter();
-                    Ok((
-                        A::from_resp(ary_iter.next().expect("No value"))?,
-                        B::from_resp(ary_iter.next().expect("No value"))?,
-                        C::from_resp(ary_iter.next().expect("No value"))?,
-                    ))
-                } else {
-                    Err(Error::Resp(
-                        format!("Array needs to be 3 elements, is: {}", ary.len()),
-                        None,
-                    ))
+                let mut map = HashMap::with_capacity_and_hasher(ary.len() / 2, S::default());
+                map.extend(pairs_from_flat_array::<K, T>(ary, "a hashmap")?);
+                Ok(map)
+            }
+            RespValue::Map(pairs) => {
+                let mut map = HashMap::with_capacity_and_hasher(pairs.len(), S::default());
+                for (k, v) in pairs {
+                    map.insert(K::from_resp(k)?, T::from_resp(v)?);
+                }
+                Ok(map)
+            }
+            _ => Err(conversion_error::<Self>(resp)),
+        }
+    }
+}
+
+/// An ordered sequence of key/value pairs, e.g. the members and scores returned by
+/// `ZRANGE ... WITHSCORES`, which can be read as `Pairs<String, f64>`.
+///
+/// This can be read from a flat array of alternating keys and values, an array of two-element
+/// arrays (as RESP3 returns for `WITHSCORES`), or a map.  Unlike `HashMap` the order is preserved
+/// and keys may be repeated.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Pairs<K, V>(pub Vec<(K, V)>);
+
+impl<K, V> From<Pairs<K, V>> for Vec<(K, V)> {
+    fn from(pairs: Pairs<K, V>) -> Vec<(K, V)> {
+        pairs.0
+    }
+}
+
+impl<K, V> IntoIterator for Pairs<K, V> {
+    type Item = (K, V);
+    type IntoIter = std::vec::IntoIter<(K, V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<K: FromResp, V: FromResp> FromResp for Pairs<K, V> {
+    fn from_resp_int(resp: RespValue) -> Result<Pairs<K, V>, Error> {
+        match resp {
+            RespValue::Array(ary) => match ary.first() {
+                Some(RespValue::Array(_)) => Ok(Pairs(Vec::from_resp_int(RespValue::Array(ary))?)),
+                _ => Ok(Pairs(pairs_from_flat_array(ary, "pairs")?)),
+            },
+            RespValue::Map(pairs) => {
+                let mut converted = Vec::with_capacity(pairs.len());
+                for (k, v) in pairs {
+                    converted.push((K::from_resp(k)?, V::from_resp(v)?));
+                }
+                Ok(Pairs(converted))
+            }
+            RespValue::Nil => Ok(Pairs(Vec::new())),
+            _ => Err(conversion_error::<Self>(resp)),
+        }
+    }
+}
+
+/// The status replies `OK`, `PONG` (to `PING`), and `QUEUED` (to commands within a transaction)
+/// are accepted, anything else is an error.
+///
+/// Nil is deliberately not accepted, as for commands such as `SET ... NX` it signals that the
+/// command was not applied; use `Option<()>` where that is expected.
+impl FromResp for () {
+    fn from_resp_int(resp: RespValue) -> Result<(), Error> {
+        match resp {
+            RespValue::SimpleString(string) => match string.as_ref() {
+                "OK" | "PONG" | "QUEUED" => Ok(()),
+                _ => Err(Error::Resp(
+                    format!("Unexpected value within SimpleString: {}", string),
+                    None,
+                )),
+            },
+            _ => Err(conversion_error::<Self>(resp)),
+        }
+    }
+}
+
+/// An IP address, from a string such as `127.0.0.1` or `::1`
+impl FromResp for IpAddr {
+    fn from_resp_int(resp: RespValue) -> Result<IpAddr, Error> {
+        match resp.as_str().map(str::parse) {
+            Some(Ok(ip)) => Ok(ip),
+            _ => Err(conversion_error::<Self>(resp)),
+        }
+    }
+}
+
+/// An address, from a `host:port` string where the host is an IP address.  IPv6 addresses may or
+/// may not be in brackets, e.g. both `[::1]:6379` and `::1:6379` are accepted.
+impl FromResp for SocketAddr {
+    fn from_resp_int(resp: RespValue) -> Result<SocketAddr, Error> {
+        match resp.as_str().and_then(error::parse_addr) {
+            Some(addr) => Ok(addr),
+            None => Err(conversion_error::<Self>(resp)),
+        }
+    }
+}
+
+/// `TryFrom` for some common types, for generic code, these convert exactly as `FromResp::from_resp`
+/// does, e.g. `let len: i64 = reply.try_into()?`.
+macro_rules! impl_tryfrom_resp {
+    ($($t:ty),* $(,)*) => {
+        $(
+            impl TryFrom<RespValue> for $t {
+                type Error = Error;
+
+                fn try_from(resp: RespValue) -> Result<$t, Error> {
+                    <$t>::from_resp(resp)
                 }
             }
-            _ => Err(error::resp(
-                "Unexpected value, should be encoded as an array",
-                resp,
+        )*
+    };
+}
+
+impl_tryfrom_resp!(String, i64, Vec<u8>, bool);
+
+/// A duration in milliseconds, for commands such as `PEXPIRE` and `PTTL`, whereas a `Duration` is
+/// converted to and from whole seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Millis(pub Duration);
+
+impl From<Millis> for Duration {
+    fn from(millis: Millis) -> Duration {
+        millis.0
+    }
+}
+
+/// Read the reply to `TTL` or `PTTL`, the negative replies for a key without an expiry (`-1`) or a
+/// key that doesn't exist (`-2`) are errors.
+fn ttl_from_resp(resp: RespValue, from_int: fn(u64) -> Duration) -> Result<Duration, Error> {
+    match i64::from_resp_int(resp)? {
+        -1 => Err(error::resp("key has no expiry", RespValue::Integer(-1))),
+        -2 => Err(error::resp("key does not exist", RespValue::Integer(-2))),
+        int => match u64::try_from(int) {
+            Ok(int) => Ok(from_int(int)),
+            Err(_) => Err(error::resp("negative duration", RespValue::Integer(int))),
+        },
+    }
+}
+
+/// Whole seconds, e.g. the reply to `TTL`.  A key without an expiry, or that doesn't exist, is an
+/// error, use `i64` to read the reply if these need to be distinguished from other errors.
+impl FromResp for Duration {
+    fn from_resp_int(resp: RespValue) -> Result<Duration, Error> {
+        ttl_from_resp(resp, Duration::from_secs)
+    }
+}
+
+/// Milliseconds, e.g. the reply to `PTTL`, negative replies are errors as for `Duration`.
+impl FromResp for Millis {
+    fn from_resp_int(resp: RespValue) -> Result<Millis, Error> {
+        ttl_from_resp(resp, Duration::from_millis).map(Millis)
+    }
+}
+
+/// Arrays of a fixed length, e.g. the `[seconds, microseconds]` reply to `TIME`.
+impl<T: FromResp, const N: usize> FromResp for [T; N] {
+    fn from_resp_int(resp: RespValue) -> Result<[T; N], Error> {
+        match resp {
+            RespValue::Array(ary) if ary.len() == N => {
+                let values = ary
+                    .into_iter()
+                    .map(T::from_resp)
+                    .collect::<Result<Vec<T>, Error>>()?;
+                Ok(values
+                    .try_into()
+                    .unwrap_or_else(|_| unreachable!("Array is {} elements", N)))
+            }
+            RespValue::Array(ary) => Err(Error::Resp(
+                format!("Array needs to be {} elements, is: {}", N, ary.len()),
+                None,
             )),
+            _ => Err(conversion_error::<Self>(resp)),
         }
     }
 }
 
+macro_rules! impl_fromresp_tuples {
+    ($(($len:expr, $($name:ident),+)),* $(,)*) => {
+        $(
+            impl<$($name: FromResp),+> FromResp for ($($name,)+) {
+                fn from_resp_int(resp: RespValue) -> Result<Self, Error> {
+                    match resp {
+                        RespValue::Array(ary) => {
+                            if ary.len() == $len {
+                                let mut ary_iter = ary.into_iter();
+                                Ok(($($name::from_resp(ary_iter.next().expect("No value"))?,)+))
+                            } else {
+                                Err(Error::Resp(
+                                    format!(
+                                        "Array needs to be {} elements, is: {}",
+                                        $len,
+                                        ary.len()
+                                    ),
+                                    None,
+                                ))
+                            }
+                        }
+                        _ => Err(conversion_error::<Self>(resp)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_fromresp_tuples!(
+    (2, A, B),
+    (3, A, B, C),
+    (4, A, B, C, D),
+    (5, A, B, C, D, E),
+    (6, A, B, C, D, E, F),
+);
+
 /// Macro to create a RESP array, useful for preparing commands to send.  Elements can be any type, or a mixture
 /// of types, that satisfy `Into<RespValue>`.
 ///
@@ -345,8 +1184,23 @@ where
 ///     let command = resp_array!["RPUSH", "mykey"].append(data);
 /// }
 /// ```
+///
+/// Or equivalently, with the variable arguments following a `;`:
+///
+/// ```
+/// #[macro_use]
+/// extern crate redis_async;
+///
+/// fn main() {
+///     let keys = vec!["key1".to_string(), "key2".to_string()];
+///     let command = resp_array!["MGET"; keys];
+/// }
+/// ```
 #[macro_export]
 macro_rules! resp_array {
+    ($($e:expr),+ ; $rest:expr) => {
+        $crate::resp_array![$($e),+].append($rest)
+    };
     ($($e:expr),* $(,)?) => {
         {
             $crate::resp::RespValue::Array(vec![
@@ -386,21 +1240,21 @@ impl IntoRespString for String {
 }
 string_into_resp!(String);
 
-impl<'a> IntoRespString for &'a String {
+impl IntoRespString for &String {
     fn into_resp_string(self) -> RespValue {
         RespValue::BulkString(self.as_bytes().into())
     }
 }
 string_into_resp!(&'a String);
 
-impl<'a> IntoRespString for &'a str {
+impl IntoRespString for &str {
     fn into_resp_string(self) -> RespValue {
         RespValue::BulkString(self.as_bytes().into())
     }
 }
 string_into_resp!(&'a str);
 
-impl<'a> IntoRespString for &'a [u8] {
+impl IntoRespString for &[u8] {
     fn into_resp_string(self) -> RespValue {
         RespValue::BulkString(self.to_vec())
     }
@@ -421,6 +1275,53 @@ impl IntoRespString for Arc<str> {
 }
 string_into_resp!(Arc<str>);
 
+impl IntoRespString for Cow<'_, str> {
+    fn into_resp_string(self) -> RespValue {
+        match self {
+            Cow::Borrowed(string) => string.into_resp_string(),
+            Cow::Owned(string) => string.into_resp_string(),
+        }
+    }
+}
+string_into_resp!(Cow<'a, str>);
+
+/// A single character is a bulk string of its UTF-8 encoding
+impl IntoRespString for char {
+    fn into_resp_string(self) -> RespValue {
+        let mut bytes = [0; 4];
+        RespValue::BulkString(self.encode_utf8(&mut bytes).as_bytes().into())
+    }
+}
+string_into_resp!(char);
+
+/// Floats are sent as bulk strings of the shortest representation that round-trips, using the
+/// `inf`, `-inf` and `nan` tokens for special values.
+macro_rules! impl_intorespstring_floats {
+    ($($float_ty:ident),* $(,)*) => {
+        $(
+            impl IntoRespString for $float_ty {
+                fn into_resp_string(self) -> RespValue {
+                    let string = if self.is_nan() {
+                        "nan".to_string()
+                    } else {
+                        self.to_string()
+                    };
+                    RespValue::BulkString(string.into_bytes())
+                }
+            }
+            string_into_resp!($float_ty);
+        )*
+    };
+}
+
+impl_intorespstring_floats!(f64, f32);
+
+/// A specific trait to convert into a `RespValue::Integer`
+///
+/// Unsigned values too large for a RESP integer (which is a signed 64-bit integer) are converted
+/// into a bulk string of their decimal representation instead, which Redis accepts as an argument
+/// wherever an integer is expected.  `u8` is not included, as `Vec<u8>` and `&[u8]` are bulk
+/// strings.
 pub trait IntoRespInteger {
     fn into_resp_integer(self) -> RespValue;
 }
@@ -431,345 +1332,3657 @@ macro_rules! integer_into_resp {
     };
 }
 
-impl IntoRespInteger for usize {
+macro_rules! impl_intorespinteger {
+    ($($int_ty:ident),* $(,)*) => {
+        $(
+            impl IntoRespInteger for $int_ty {
+                fn into_resp_integer(self) -> RespValue {
+                    match i64::try_from(self) {
+                        Ok(x) => RespValue::Integer(x),
+                        Err(_) => RespValue::BulkString(self.to_string().into_bytes()),
+                    }
+                }
+            }
+            integer_into_resp!($int_ty);
+        )*
+    };
+}
+
+impl_intorespinteger!(i64, isize, usize, i32, u32, u64, i16, u16, i8);
+
+/// Booleans are converted to the integers `1` and `0`, not the strings `"true"` and `"false"`, as
+/// that is the form Redis uses for flags.
+impl IntoRespInteger for bool {
     fn into_resp_integer(self) -> RespValue {
         RespValue::Integer(self as i64)
     }
 }
-integer_into_resp!(usize);
+integer_into_resp!(bool);
 
-/// Codec to read frames
-pub struct RespCodec;
-
-fn write_rn(buf: &mut BytesMut) {
-    buf.put_u8(b'\r');
-    buf.put_u8(b'\n');
+/// Durations are converted to whole seconds, rounding down, for commands such as `EXPIRE` and
+/// `SET key value EX seconds`.  Wrap them in `Millis` for commands which take milliseconds.
+impl IntoRespInteger for Duration {
+    fn into_resp_integer(self) -> RespValue {
+        self.as_secs().into_resp_integer()
+    }
 }
+integer_into_resp!(Duration);
 
-fn check_and_reserve(buf: &mut BytesMut, amt: usize) {
-    let remaining_bytes = buf.remaining_mut();
-    if remaining_bytes < amt {
-        buf.reserve(amt);
+impl IntoRespInteger for Millis {
+    fn into_resp_integer(self) -> RespValue {
+        // Milliseconds beyond `u64::MAX` are hundreds of millions of years away
+        u64::try_from(self.0.as_millis())
+            .unwrap_or(u64::MAX)
+            .into_resp_integer()
     }
 }
+integer_into_resp!(Millis);
 
-fn write_header(symb: u8, len: i64, buf: &mut BytesMut) {
-    let len_as_string = len.to_string();
-    let len_as_bytes = len_as_string.as_bytes();
-    let header_bytes = 1 + len_as_bytes.len() + 2;
-    check_and_reserve(buf, header_bytes);
-    buf.put_u8(symb);
-    buf.extend(len_as_bytes);
-    write_rn(buf);
+/// `Some(value)` is converted as `value` would be, `None` is converted into `RespValue::Nil`, which
+/// is encoded as a null bulk string (`$-1\r\n`) in RESP2.
+///
+/// Note that Redis does not accept nulls as command arguments, so `None` is mostly useful for
+/// constructing replies, e.g. in a mock server.
+impl<T: Into<RespValue>> From<Option<T>> for RespValue {
+    fn from(from: Option<T>) -> RespValue {
+        match from {
+            Some(value) => value.into(),
+            None => RespValue::Nil,
+        }
+    }
 }
 
-fn write_simple_string(symb: u8, string: &str, buf: &mut BytesMut) {
-    let bytes = string.as_bytes();
-    let size = 1 + bytes.len() + 2;
-    check_and_reserve(buf, size);
-    buf.put_u8(symb);
-    buf.extend(bytes);
-    write_rn(buf);
+/// Vectors and slices of convertible values are converted into a `RespValue::Array`, with the
+/// exception of `Vec<u8>` and `&[u8]` which are bulk strings.
+///
+/// Redis commands must be a flat array, so to add the contents of a collection to a command use
+/// `append` instead; this is for nested structures such as replies in a mock server.
+impl<T: Into<RespValue>> From<Vec<T>> for RespValue {
+    fn from(from: Vec<T>) -> RespValue {
+        RespValue::Array(from.into_iter().map(|t| t.into()).collect())
+    }
 }
 
-impl Encoder<RespValue> for RespCodec {
-    type Error = io::Error;
-
-    fn encode(&mut self, msg: RespValue, buf: &mut BytesMut) -> Result<(), Self::Error> {
-        match msg {
-            RespValue::Nil => {
-                write_header(b'$', -1, buf);
-            }
-            RespValue::Array(ary) => {
-                write_header(b'*', ary.len() as i64, buf);
-                for v in ary {
-                    self.encode(v, buf)?;
-                }
-            }
-            RespValue::BulkString(bstr) => {
-                let len = bstr.len();
-                write_header(b'$', len as i64, buf);
-                check_and_reserve(buf, len + 2);
-                buf.extend(bstr);
-                write_rn(buf);
-            }
-            RespValue::Error(ref string) => {
-                write_simple_string(b'-', string, buf);
-            }
-            RespValue::Integer(val) => {
-                // Simple integer are just the header
-                write_header(b':', val, buf);
-            }
-            RespValue::SimpleString(ref string) => {
-                write_simple_string(b'+', string, buf);
-            }
-        }
-        Ok(())
+impl<'a, T: Into<RespValue> + Clone> From<&'a [T]> for RespValue {
+    fn from(from: &'a [T]) -> RespValue {
+        RespValue::Array(from.iter().cloned().map(|t| t.into()).collect())
     }
 }
 
-#[inline]
-fn parse_error(message: String) -> Error {
-    Error::Resp(message, None)
+/// Maps are converted into a flat `RespValue::Array` of alternating keys and values, as used by
+/// `HSET` and `MSET`.  The iteration order of a `HashMap` is unspecified, where a deterministic
+/// order is required use a `Vec<(K, V)>` instead.
+impl<K: Into<RespValue>, V: Into<RespValue>, S> From<HashMap<K, V, S>> for RespValue {
+    fn from(from: HashMap<K, V, S>) -> RespValue {
+        RespValue::Array(flatten_pairs(from.len(), from))
+    }
 }
 
-/// Many RESP types have their length (which is either bytes or "number of elements", depending on context)
-/// encoded as a string, terminated by "\r\n", this looks for them.
-///
-/// Only return the string if the whole sequence is complete, including the terminator bytes (but those final
-/// two bytes will not be returned)
-///
-/// TODO - rename this function potentially, it's used for simple integers too
-fn scan_integer(buf: &mut BytesMut, idx: usize) -> Result<Option<(usize, &[u8])>, Error> {
-    let length = buf.len();
-    let mut at_end = false;
-    let mut pos = idx;
-    loop {
-        if length <= pos {
-            return Ok(None);
-        }
-        match (at_end, buf[pos]) {
-            (true, b'\n') => return Ok(Some((pos + 1, &buf[idx..pos - 1]))),
-            (false, b'\r') => at_end = true,
-            (false, b'0'..=b'9') => (),
-            (false, b'-') => (),
-            (_, val) => {
-                return Err(parse_error(format!(
-                    "Unexpected byte in size_string: {}",
-                    val
-                )));
-            }
-        }
-        pos += 1;
+impl<K: Into<RespValue>, V: Into<RespValue>> From<Vec<(K, V)>> for RespValue {
+    fn from(from: Vec<(K, V)>) -> RespValue {
+        RespValue::Array(flatten_pairs(from.len(), from))
     }
 }
 
-fn scan_string(buf: &mut BytesMut, idx: usize) -> Option<(usize, String)> {
-    let length = buf.len();
-    let mut at_end = false;
-    let mut pos = idx;
-    loop {
-        if length <= pos {
-            return None;
-        }
-        match (at_end, buf[pos]) {
-            (true, b'\n') => {
-                let value = String::from_utf8_lossy(&buf[idx..pos - 1]).into_owned();
-                return Some((pos + 1, value));
-            }
-            (true, _) => at_end = false,
-            (false, b'\r') => at_end = true,
-            (false, _) => (),
-        }
-        pos += 1;
+fn flatten_pairs<K, V>(len: usize, pairs: impl IntoIterator<Item = (K, V)>) -> Vec<RespValue>
+where
+    K: Into<RespValue>,
+    V: Into<RespValue>,
+{
+    let mut values = Vec::with_capacity(len * 2);
+    for (k, v) in pairs {
+        values.push(k.into());
+        values.push(v.into());
     }
+    values
 }
 
-fn decode_raw_integer(buf: &mut BytesMut, idx: usize) -> Result<Option<(usize, i64)>, Error> {
-    match scan_integer(buf, idx) {
-        Ok(None) => Ok(None),
-        Ok(Some((pos, int_str))) => {
-            // Redis integers are transmitted as strings, so we first convert the raw bytes into a string...
-            match str::from_utf8(int_str) {
-                Ok(string) => {
-                    // ...and then parse the string.
-                    match string.parse() {
-                        Ok(int) => Ok(Some((pos, int))),
-                        Err(_) => Err(parse_error(format!("Not an integer: {}", string))),
-                    }
-                }
-                Err(_) => Err(parse_error(format!("Not a valid string: {:?}", int_str))),
-            }
-        }
-        Err(e) => Err(e),
-    }
+/// The version of the RESP protocol spoken on a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RespVersion {
+    /// The original protocol, supported by all versions of Redis.
+    #[default]
+    Resp2,
+    /// The protocol introduced in Redis 6, which adds a number of new types, e.g. maps, doubles
+    /// and booleans.
+    Resp3,
 }
 
-type DecodeResult = Result<Option<(usize, RespValue)>, Error>;
+/// Codec to read frames, create one with `RespCodec::default()`, `RespCodec::resp2()` or
+/// `RespCodec::resp3()`
+///
+/// Decoding accepts both RESP2 and RESP3 regardless of the protocol; encoding uses the protocol
+/// to decide how to serialize values which differ between versions.  When encoding RESP2, the
+/// RESP3-only types are converted to their nearest RESP2 equivalent: booleans become integers,
+/// doubles and big numbers become bulk strings, maps become flat arrays, etc.
+///
+/// Values nested more deeply than the maximum depth, by default 512, are rejected when decoding.
+/// This stops a malicious or buggy server from causing a stack overflow when the values are used,
+/// e.g. when they are dropped.  Similarly bulk strings longer than the maximum length, by default
+/// 512MB, are rejected rather than buffered.
+#[derive(Debug, Clone)]
+pub struct RespCodec {
+    protocol: RespVersion,
+    max_depth: usize,
+    max_bulk_len: usize,
+    max_array_len: usize,
+    max_frame_bytes: usize,
+    max_buffer_bytes: usize,
+    inline: bool,
+    resync: bool,
+    /// Whether the next value is still to be found after an error, see `with_resync`
+    resyncing: bool,
+    /// The number of bytes of the current value that have been decoded
+    frame_bytes: usize,
+    /// Aggregates that have been partially decoded, innermost last
+    partial: Vec<PartialAggregate>,
+    /// A streamed string that has been partially decoded, this is always innermost
+    streamed_string: Option<Vec<u8>>,
+}
 
-fn decode_bulk_string(buf: &mut BytesMut, idx: usize) -> DecodeResult {
-    match decode_raw_integer(buf, idx) {
-        Ok(None) => Ok(None),
-        Ok(Some((pos, -1))) => Ok(Some((pos, RespValue::Nil))),
-        Ok(Some((pos, size))) if size >= 0 => {
-            let size = size as usize;
-            let remaining = buf.len() - pos;
-            let required_bytes = size + 2;
+/// The default maximum nesting depth of decoded values
+const DEFAULT_MAX_DEPTH: usize = 512;
 
-            if remaining < required_bytes {
-                return Ok(None);
-            }
+/// The default maximum length of decoded bulk strings, this is the same as Redis's own default
+/// `proto-max-bulk-len`
+const DEFAULT_MAX_BULK_LEN: usize = 512 * 1024 * 1024;
 
-            let bulk_string = RespValue::BulkString(buf[pos..(pos + size)].to_vec());
-            Ok(Some((pos + required_bytes, bulk_string)))
+impl Default for RespCodec {
+    fn default() -> Self {
+        RespCodec {
+            protocol: RespVersion::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_bulk_len: DEFAULT_MAX_BULK_LEN,
+            max_array_len: usize::MAX,
+            max_frame_bytes: usize::MAX,
+            max_buffer_bytes: usize::MAX,
+            inline: false,
+            resync: false,
+            resyncing: false,
+            frame_bytes: 0,
+            partial: Vec::new(),
+            streamed_string: None,
         }
-        Ok(Some((_, size))) => Err(parse_error(format!("Invalid string size: {}", size))),
-        Err(e) => Err(e),
     }
 }
 
-fn decode_array(buf: &mut BytesMut, idx: usize) -> DecodeResult {
-    match decode_raw_integer(buf, idx) {
-        Ok(None) => Ok(None),
-        Ok(Some((pos, -1))) => Ok(Some((pos, RespValue::Nil))),
-        Ok(Some((pos, size))) if size >= 0 => {
-            let size = size as usize;
-            let mut pos = pos;
-            let mut values = Vec::with_capacity(size);
-            for _ in 0..size {
-                match decode(buf, pos) {
-                    Ok(None) => return Ok(None),
-                    Ok(Some((new_pos, value))) => {
-                        values.push(value);
-                        pos = new_pos;
-                    }
-                    Err(e) => return Err(e),
-                }
-            }
-            Ok(Some((pos, RespValue::Array(values))))
+impl RespCodec {
+    /// A codec which encodes RESP2, this is the default.
+    pub fn resp2() -> Self {
+        RespCodec {
+            protocol: RespVersion::Resp2,
+            ..Default::default()
         }
-        Ok(Some((_, size))) => Err(parse_error(format!("Invalid array size: {}", size))),
-        Err(e) => Err(e),
     }
-}
 
-fn decode_integer(buf: &mut BytesMut, idx: usize) -> DecodeResult {
-    match decode_raw_integer(buf, idx) {
-        Ok(None) => Ok(None),
-        Ok(Some((pos, int))) => Ok(Some((pos, RespValue::Integer(int)))),
-        Err(e) => Err(e),
+    /// A codec which encodes RESP3.
+    pub fn resp3() -> Self {
+        RespCodec {
+            protocol: RespVersion::Resp3,
+            ..Default::default()
+        }
     }
-}
 
-/// A simple string is any series of bytes that ends with `\r\n`
-#[allow(clippy::unnecessary_wraps)]
-fn decode_simple_string(buf: &mut BytesMut, idx: usize) -> DecodeResult {
-    match scan_string(buf, idx) {
-        None => Ok(None),
-        Some((pos, string)) => Ok(Some((pos, RespValue::SimpleString(string)))),
+    /// The protocol currently used when encoding.
+    pub fn protocol(&self) -> RespVersion {
+        self.protocol
     }
-}
 
-#[allow(clippy::unnecessary_wraps)]
-fn decode_error(buf: &mut BytesMut, idx: usize) -> DecodeResult {
-    match scan_string(buf, idx) {
-        None => Ok(None),
-        Some((pos, string)) => Ok(Some((pos, RespValue::Error(string)))),
+    /// Change the protocol used when encoding, e.g. after negotiating a connection's protocol.
+    pub fn set_protocol(&mut self, protocol: RespVersion) {
+        self.protocol = protocol;
     }
-}
 
-fn decode(buf: &mut BytesMut, idx: usize) -> DecodeResult {
+    /// Set the maximum depth that decoded values can be nested, a top-level array has a depth of
+    /// one.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Set the maximum length, in bytes, of decoded bulk strings, any longer strings are rejected
+    /// as soon as their length is known.  This applies to verbatim and streamed strings too.
+    pub fn with_max_bulk_len(mut self, max_bulk_len: usize) -> Self {
+        self.max_bulk_len = max_bulk_len;
+        self
+    }
+
+    /// Set the maximum number of elements of decoded aggregates, for maps this is the number of
+    /// key/value pairs.  By default there is no limit.
+    pub fn with_max_array_len(mut self, max_array_len: usize) -> Self {
+        self.max_array_len = max_array_len;
+        self
+    }
+
+    /// Set the maximum number of bytes a single top-level value can be encoded in, including all
+    /// nested values.  By default there is no limit.
+    pub fn with_max_frame_bytes(mut self, max_frame_bytes: usize) -> Self {
+        self.max_frame_bytes = max_frame_bytes;
+        self
+    }
+
+    /// Set the maximum number of bytes of an incomplete value that will be buffered while waiting
+    /// for the rest of it to arrive.  As each element of an aggregate is removed from the buffer
+    /// once decoded, this bounds the memory used by the buffer even when a value can never be
+    /// completed, e.g. a simple string which is never terminated.  By default there is no limit.
+    pub fn with_max_buffer_bytes(mut self, max_buffer_bytes: usize) -> Self {
+        self.max_buffer_bytes = max_buffer_bytes;
+        self
+    }
+
+    /// Set whether to decode inline commands, i.e. lines of space-separated arguments which don't
+    /// start with a RESP type byte, as sent by a user typing commands into telnet.  Each line is
+    /// decoded into an array of bulk strings, and empty lines are skipped.  Unlike Redis, quotes
+    /// are not interpreted, they are part of the arguments, so arguments cannot contain spaces.
+    /// Lines longer than 64KB, the same limit as Redis, or the maximum bulk string length or
+    /// frame size if smaller, are rejected.
+    ///
+    /// This is disabled by default, in which case such lines are an error.
+    pub fn with_inline_commands(mut self, inline: bool) -> Self {
+        self.inline = inline;
+        self
+    }
+
+    /// Set whether to resynchronise with the stream after invalid data, rather than failing on
+    /// every later call to `decode`.  After an error the buffer is skipped forward to the next
+    /// plausible start of a value, a RESP type byte following a CRLF, and decoding resumes from
+    /// there; any partially decoded value is discarded.  The error is still returned, once.
+    ///
+    /// This is disabled by default, as the next value found may not be a real value, e.g. it may
+    /// be part of a bulk string.  It is intended for tools such as diagnostic proxies which must
+    /// survive a desynchronised peer.  A `Framed` stream ends at the first error regardless, so
+    /// use the codec directly, or `RespReader`, to resume after errors.
+    pub fn with_resync(mut self, resync: bool) -> Self {
+        self.resync = resync;
+        self
+    }
+}
+
+fn write_rn(buf: &mut BytesMut) {
+    buf.put_u8(b'\r');
+    buf.put_u8(b'\n');
+}
+
+fn check_and_reserve(buf: &mut BytesMut, amt: usize) {
+    let remaining_bytes = buf.capacity() - buf.len();
+    if remaining_bytes < amt {
+        buf.reserve(amt);
+    }
+}
+
+/// The number of bytes in the decimal representation of `val`
+fn decimal_len(val: i64) -> usize {
+    let digits = val.unsigned_abs().checked_ilog10().unwrap_or(0) as usize + 1;
+    if val < 0 {
+        digits + 1
+    } else {
+        digits
+    }
+}
+
+/// The size of a header, e.g. `*10\r\n`, or of an integer which is encoded the same way
+fn header_size(len: i64) -> usize {
+    1 + decimal_len(len) + 2
+}
+
+fn bulk_string_size(len: usize) -> usize {
+    header_size(len as i64) + len + 2
+}
+
+/// The number of bytes in the string representation of `val`, without formatting it into a
+/// `String`
+fn double_len(val: f64) -> usize {
+    struct Len(usize);
+
+    impl fmt::Write for Len {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0 += s.len();
+            Ok(())
+        }
+    }
+
+    if val.is_nan() {
+        return 3;
+    }
+    let mut len = Len(0);
+    fmt::write(&mut len, format_args!("{}", val)).expect("Counting cannot fail");
+    len.0
+}
+
+/// Doubles are a simple string in RESP3, and a bulk string in RESP2.  Redis spells NaN as `nan`,
+/// whereas Rust formats it as `NaN`.
+fn write_double(val: f64, resp3: bool, buf: &mut BytesMut) {
+    let len = double_len(val);
+    if resp3 {
+        check_and_reserve(buf, 1 + len + 2);
+        buf.put_u8(b',');
+    } else {
+        write_header(b'$', len as i64, buf);
+        check_and_reserve(buf, len + 2);
+    }
+    if val.is_nan() {
+        buf.extend_from_slice(b"nan");
+    } else {
+        fmt::write(buf, format_args!("{}", val)).expect("Writing to a buffer cannot fail");
+    }
+    write_rn(buf);
+}
+
+/// The longest decimal representation of an `i64` or `u64`, e.g. `-9223372036854775808`
+const MAX_DECIMAL_LEN: usize = 20;
+
+/// The decimal representations of 0 to 999, padded to three digits with leading zeros, as these
+/// are by far the most common lengths and integers in commands
+const SMALL_DECIMALS: [[u8; 3]; 1000] = {
+    let mut table = [[0; 3]; 1000];
+    let mut i = 0;
+    while i < 1000 {
+        table[i] = [
+            b'0' + (i / 100) as u8,
+            b'0' + (i / 10 % 10) as u8,
+            b'0' + (i % 10) as u8,
+        ];
+        i += 1;
+    }
+    table
+};
+
+/// Format `val` as decimal into the end of `digits`, returning the bytes used.  This avoids
+/// allocating a `String` for every header.
+fn format_decimal(val: u64, digits: &mut [u8; MAX_DECIMAL_LEN]) -> &[u8] {
+    match val {
+        0..=9 => &SMALL_DECIMALS[val as usize][2..],
+        10..=99 => &SMALL_DECIMALS[val as usize][1..],
+        100..=999 => &SMALL_DECIMALS[val as usize],
+        _ => format_digits(val, digits),
+    }
+}
+
+/// The general case of `format_decimal`, one digit at a time
+fn format_digits(mut val: u64, digits: &mut [u8; MAX_DECIMAL_LEN]) -> &[u8] {
+    let mut pos = digits.len();
+    loop {
+        pos -= 1;
+        digits[pos] = b'0' + (val % 10) as u8;
+        val /= 10;
+        if val == 0 {
+            return &digits[pos..];
+        }
+    }
+}
+
+fn write_header(symb: u8, len: i64, buf: &mut BytesMut) {
+    let mut digits = [0; MAX_DECIMAL_LEN];
+    let len_as_bytes = format_decimal(len.unsigned_abs(), &mut digits);
+    let sign_bytes = if len < 0 { 1 } else { 0 };
+    let header_bytes = 1 + sign_bytes + len_as_bytes.len() + 2;
+    check_and_reserve(buf, header_bytes);
+    buf.put_u8(symb);
+    if len < 0 {
+        buf.put_u8(b'-');
+    }
+    buf.extend_from_slice(len_as_bytes);
+    write_rn(buf);
+}
+
+fn write_bulk_string(bytes: &[u8], buf: &mut BytesMut) {
+    let len = bytes.len();
+    write_header(b'$', len as i64, buf);
+    check_and_reserve(buf, len + 2);
+    buf.extend(bytes);
+    write_rn(buf);
+}
+
+fn write_simple_string(symb: u8, string: &str, buf: &mut BytesMut) {
+    let bytes = string.as_bytes();
+    let size = 1 + bytes.len() + 2;
+    check_and_reserve(buf, size);
+    buf.put_u8(symb);
+    buf.extend(bytes);
+    write_rn(buf);
+}
+
+impl Encoder<RespValue> for RespCodec {
+    type Error = Error;
+
+    fn encode(&mut self, msg: RespValue, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&msg, buf)
+    }
+}
+
+/// Values can be encoded by reference, this avoids cloning a value to send it when it is needed
+/// afterwards
+impl Encoder<&RespValue> for RespCodec {
+    type Error = Error;
+
+    fn encode(&mut self, msg: &RespValue, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        // Reserve the space for the whole value up-front, rather than growing the buffer as each
+        // part is written
+        buf.reserve(self.encoded_size(msg));
+        self.encode_value(msg, buf);
+        Ok(())
+    }
+}
+
+impl RespCodec {
+    /// The number of bytes `msg` is encoded in, with the codec's current protocol.
+    pub fn encoded_size(&self, msg: &RespValue) -> usize {
+        let resp3 = self.protocol == RespVersion::Resp3;
+        match *msg {
+            RespValue::Nil => {
+                if resp3 {
+                    3
+                } else {
+                    5
+                }
+            }
+            RespValue::Array(ref ary) | RespValue::Push(ref ary) | RespValue::Set(ref ary) => {
+                header_size(ary.len() as i64)
+                    + ary.iter().map(|v| self.encoded_size(v)).sum::<usize>()
+            }
+            RespValue::BulkString(ref bstr) => bulk_string_size(bstr.len()),
+            RespValue::Error(ref string) | RespValue::SimpleString(ref string) => string.len() + 3,
+            RespValue::Integer(val) => header_size(val),
+            RespValue::Double(val) => {
+                let len = double_len(val);
+                if resp3 {
+                    len + 3
+                } else {
+                    bulk_string_size(len)
+                }
+            }
+            // Either `#t\r\n` or `:1\r\n`
+            RespValue::Boolean(_) => 4,
+            RespValue::BigNumber(ref string) => {
+                if resp3 {
+                    string.len() + 3
+                } else {
+                    bulk_string_size(string.len())
+                }
+            }
+            RespValue::Map(ref pairs) => {
+                let len = if resp3 { pairs.len() } else { pairs.len() * 2 };
+                header_size(len as i64)
+                    + pairs
+                        .iter()
+                        .map(|(k, v)| self.encoded_size(k) + self.encoded_size(v))
+                        .sum::<usize>()
+            }
+            RespValue::Verbatim { ref data, .. } => {
+                if resp3 {
+                    bulk_string_size(4 + data.len())
+                } else {
+                    bulk_string_size(data.len())
+                }
+            }
+        }
+    }
+
+    fn encode_value(&self, msg: &RespValue, buf: &mut BytesMut) {
+        let resp3 = self.protocol == RespVersion::Resp3;
+        match *msg {
+            RespValue::Nil => {
+                if resp3 {
+                    write_simple_string(b'_', "", buf);
+                } else {
+                    write_header(b'$', -1, buf);
+                }
+            }
+            RespValue::Array(ref ary) => {
+                write_header(b'*', ary.len() as i64, buf);
+                for v in ary {
+                    self.encode_value(v, buf);
+                }
+            }
+            RespValue::BulkString(ref bstr) => {
+                write_bulk_string(bstr, buf);
+            }
+            RespValue::Error(ref string) => {
+                write_simple_string(b'-', string, buf);
+            }
+            RespValue::Integer(val) => {
+                // Simple integer are just the header
+                write_header(b':', val, buf);
+            }
+            RespValue::SimpleString(ref string) => {
+                write_simple_string(b'+', string, buf);
+            }
+            RespValue::Double(val) => write_double(val, resp3, buf),
+            RespValue::Boolean(val) => {
+                if resp3 {
+                    write_simple_string(b'#', if val { "t" } else { "f" }, buf);
+                } else {
+                    write_header(b':', val as i64, buf);
+                }
+            }
+            RespValue::BigNumber(ref string) => {
+                if resp3 {
+                    write_simple_string(b'(', string, buf);
+                } else {
+                    write_bulk_string(string.as_bytes(), buf);
+                }
+            }
+            RespValue::Push(ref ary) => {
+                write_header(if resp3 { b'>' } else { b'*' }, ary.len() as i64, buf);
+                for v in ary {
+                    self.encode_value(v, buf);
+                }
+            }
+            RespValue::Set(ref ary) => {
+                write_header(if resp3 { b'~' } else { b'*' }, ary.len() as i64, buf);
+                for v in ary {
+                    self.encode_value(v, buf);
+                }
+            }
+            RespValue::Map(ref pairs) => {
+                if resp3 {
+                    write_header(b'%', pairs.len() as i64, buf);
+                } else {
+                    write_header(b'*', (pairs.len() * 2) as i64, buf);
+                }
+                for (k, v) in pairs {
+                    self.encode_value(k, buf);
+                    self.encode_value(v, buf);
+                }
+            }
+            RespValue::Verbatim {
+                ref format,
+                ref data,
+            } => {
+                if resp3 {
+                    let len = format.len() + 1 + data.len();
+                    write_header(b'=', len as i64, buf);
+                    check_and_reserve(buf, len + 2);
+                    buf.extend(format);
+                    buf.put_u8(b':');
+                    buf.extend(data);
+                    write_rn(buf);
+                } else {
+                    write_bulk_string(data, buf);
+                }
+            }
+        }
+    }
+
+    /// Encode a command as an array of `len` bulk strings taken from `args`, writing each
+    /// argument straight into `buf` rather than building a `RespValue` for it first.  Arguments
+    /// of different types can be mixed as `&dyn EncodeArg`, see `EncodeArg`.
+    ///
+    /// For strings the output is identical to encoding the command built with `resp_array!`. If
+    /// `args` does not yield exactly `len` arguments an `Error::Resp` is returned and `buf` is
+    /// left as it was.
+    ///
+    /// ```
+    /// use bytes::BytesMut;
+    /// use redis_async::resp::{EncodeArg, RespCodec};
+    ///
+    /// let mut buf = BytesMut::new();
+    /// let args: [&dyn EncodeArg; 5] = [&"SET", &"key", &10, &"EX", &5];
+    /// RespCodec::default().encode_command(args.len(), args, &mut buf).unwrap();
+    /// ```
+    pub fn encode_command<I>(&self, len: usize, args: I, buf: &mut BytesMut) -> Result<(), Error>
+    where
+        I: IntoIterator,
+        I::Item: EncodeArg,
+    {
+        let start = buf.len();
+        write_header(b'*', len as i64, buf);
+        let mut count = 0;
+        for arg in args {
+            count += 1;
+            if count > len {
+                break;
+            }
+            arg.encode_arg(buf);
+        }
+        if count != len {
+            buf.truncate(start);
+            return Err(Error::Resp(
+                format!("Expected {} command arguments, got {}", len, count),
+                None,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// An argument for `RespCodec::encode_command`, which is written into the buffer as a single bulk
+/// string.
+///
+/// Integers are written as their decimal digits, and floats as for `IntoRespString`, as Redis
+/// expects every argument of a command to be a bulk string.  As with `IntoRespInteger`, `u8` is
+/// not included.
+pub trait EncodeArg {
+    fn encode_arg(&self, buf: &mut BytesMut);
+}
+
+impl<T: EncodeArg + ?Sized> EncodeArg for &T {
+    fn encode_arg(&self, buf: &mut BytesMut) {
+        (**self).encode_arg(buf)
+    }
+}
+
+macro_rules! impl_encodearg_strings {
+    ($as_bytes:ident: $($t:ty),* $(,)*) => {
+        $(
+            impl EncodeArg for $t {
+                fn encode_arg(&self, buf: &mut BytesMut) {
+                    write_bulk_string(self.$as_bytes(), buf);
+                }
+            }
+        )*
+    };
+}
+
+impl_encodearg_strings!(as_bytes: str, String, Arc<str>, Cow<'_, str>);
+impl_encodearg_strings!(as_ref: [u8], Vec<u8>, Bytes);
+
+impl<const N: usize> EncodeArg for [u8; N] {
+    fn encode_arg(&self, buf: &mut BytesMut) {
+        write_bulk_string(self, buf);
+    }
+}
+
+impl EncodeArg for f64 {
+    fn encode_arg(&self, buf: &mut BytesMut) {
+        write_double(*self, false, buf);
+    }
+}
+
+macro_rules! impl_encodearg_integers {
+    ($($int_ty:ident),* $(,)*) => {
+        $(
+            impl EncodeArg for $int_ty {
+                fn encode_arg(&self, buf: &mut BytesMut) {
+                    write_bulk_integer(*self < 0, self.unsigned_abs() as u64, buf);
+                }
+            }
+        )*
+    };
+}
+
+impl_encodearg_integers!(i64, isize, i32, i16, i8);
+
+macro_rules! impl_encodearg_unsigned {
+    ($($int_ty:ident),* $(,)*) => {
+        $(
+            impl EncodeArg for $int_ty {
+                fn encode_arg(&self, buf: &mut BytesMut) {
+                    write_bulk_integer(false, *self as u64, buf);
+                }
+            }
+        )*
+    };
+}
+
+impl_encodearg_unsigned!(u64, usize, u32, u16);
+
+/// Write an integer as a bulk string of its decimal digits
+fn write_bulk_integer(negative: bool, abs: u64, buf: &mut BytesMut) {
+    let mut digits = [0; MAX_DECIMAL_LEN];
+    let digits = format_decimal(abs, &mut digits);
+    let len = usize::from(negative) + digits.len();
+    write_header(b'$', len as i64, buf);
+    check_and_reserve(buf, len + 2);
+    if negative {
+        buf.put_u8(b'-');
+    }
+    buf.extend_from_slice(digits);
+    write_rn(buf);
+}
+
+/// A decoding error, `offset` is the position in the buffer of the offending byte
+#[inline]
+fn parse_error(message: String, offset: usize) -> Error {
+    Error::Decode(message, offset)
+}
+
+/// Move the offset of a decoding error along by `by` bytes, for errors found after the start of a
+/// value has already been removed from the buffer
+fn offset_error(err: Error, by: usize) -> Error {
+    match err {
+        Error::Decode(message, offset) => Error::Decode(message, offset + by),
+        err => err,
+    }
+}
+
+/// Many RESP types have their length (which is either bytes or "number of elements", depending on context)
+/// encoded as a string, terminated by "\r\n", this looks for them.
+///
+/// Only return the string if the whole sequence is complete, including the terminator bytes (but those final
+/// two bytes will not be returned)
+///
+/// TODO - rename this function potentially, it's used for simple integers too
+fn scan_integer(buf: &mut BytesMut, idx: usize) -> Result<Option<(usize, &[u8])>, Error> {
+    let end = memchr::memchr(b'\r', &buf[idx..]).map(|end| idx + end);
+    // The digits are checked even if the terminator hasn't arrived yet, so a bad header is
+    // rejected as soon as it is seen
+    let digits = &buf[idx..end.unwrap_or(buf.len())];
+    // A sign is only valid as the first byte
+    let invalid = digits
+        .iter()
+        .enumerate()
+        .position(|(i, b)| !(b.is_ascii_digit() || (i == 0 && *b == b'-')));
+    if let Some(invalid) = invalid {
+        return Err(parse_error(
+            format!("Unexpected byte in size_string: {}", digits[invalid]),
+            idx + invalid,
+        ));
+    }
+    let end = match end {
+        None => return Ok(None),
+        Some(end) if end == idx => {
+            return Err(parse_error("empty length header".to_string(), end));
+        }
+        Some(end) => end,
+    };
+    match buf.get(end + 1) {
+        None => Ok(None),
+        Some(b'\n') => Ok(Some((end + 2, &buf[idx..end]))),
+        Some(val) => Err(parse_error(
+            format!("Unexpected byte in size_string: {}", val),
+            end + 1,
+        )),
+    }
+}
+
+/// A string is terminated by the first `\r\n`, a `\r` or `\n` on its own is part of the string
+fn scan_string(buf: &mut BytesMut, idx: usize) -> Option<(usize, String)> {
+    let mut from = idx;
+    loop {
+        let end = from + memchr::memchr(b'\n', &buf[from..])?;
+        if end > idx && buf[end - 1] == b'\r' {
+            let value = String::from_utf8_lossy(&buf[idx..end - 1]).into_owned();
+            return Some((end + 1, value));
+        }
+        from = end + 1;
+    }
+}
+
+/// Decode the integer at `idx`, `name` describes the context, e.g. "length", and is used in error
+/// messages
+fn decode_raw_integer(
+    buf: &mut BytesMut,
+    idx: usize,
+    name: &str,
+) -> Result<Option<(usize, i64)>, Error> {
+    match scan_integer(buf, idx) {
+        Ok(None) => Ok(None),
+        Ok(Some((pos, int_str))) => {
+            // Redis integers are transmitted as strings, so we first convert the raw bytes into a string...
+            match str::from_utf8(int_str) {
+                Ok(string) => {
+                    // ...and then parse the string.
+                    match string.parse() {
+                        Ok(int) => Ok(Some((pos, int))),
+                        Err(e) => match e.kind() {
+                            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => Err(
+                                parse_error(format!("{} out of range: {}", name, string), idx),
+                            ),
+                            _ => Err(parse_error(format!("Not an integer: {}", string), idx)),
+                        },
+                    }
+                }
+                Err(_) => Err(parse_error(
+                    format!("Not a valid string: {:?}", int_str),
+                    idx,
+                )),
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+type DecodeResult = Result<Option<(usize, RespValue)>, Error>;
+
+/// The smallest number of bytes any single RESP value can be encoded in, e.g. `+\r\n`
+const MIN_ENCODED_LEN: usize = 3;
+
+/// RESP3 allows strings and aggregates to be streamed, in which case the length is `?` and the
+/// end is marked by a terminator instead.  Returns `None` if there isn't enough data to tell.
+fn is_streamed(buf: &BytesMut, idx: usize) -> Result<Option<bool>, Error> {
+    match buf.get(idx) {
+        None => Ok(None),
+        Some(b'?') if buf.len() < idx + 3 => Ok(None),
+        Some(b'?') if &buf[idx + 1..idx + 3] == b"\r\n" => Ok(Some(true)),
+        Some(b'?') => Err(parse_error(
+            "Streamed length is not terminated by CRLF".to_string(),
+            idx + 1,
+        )),
+        Some(_) => Ok(Some(false)),
+    }
+}
+
+/// A streamed string is a series of chunks, each prefixed with `;` and its length, terminated by
+/// a chunk of length zero.  `max_len` is the number of bytes the string may still grow by.
+fn decode_chunk(buf: &mut BytesMut, idx: usize, max_len: usize) -> TokenResult {
+    match buf.get(idx) {
+        None => return Ok(None),
+        Some(b';') => (),
+        Some(val) => {
+            return Err(parse_error(
+                format!("Unexpected byte in streamed string: {}", val),
+                idx,
+            ))
+        }
+    }
+    let (pos, size) = match decode_raw_integer(buf, idx + 1, "length")? {
+        None => return Ok(None),
+        Some((pos, size)) => (pos, size),
+    };
+    if size == 0 {
+        return Ok(Some((pos, Token::Chunk(Vec::new()))));
+    }
+    let size = usize::try_from(size)
+        .map_err(|_| parse_error(format!("Invalid chunk size: {}", size), idx + 1))?;
+    if size > max_len {
+        return Err(parse_error(
+            format!("bulk string too large: chunk of {}", size),
+            idx + 1,
+        ));
+    }
+    if buf.len() < pos + size + 2 {
+        return Ok(None);
+    }
+    if &buf[(pos + size)..(pos + size + 2)] != b"\r\n" {
+        return Err(parse_error(
+            format!("Chunk of size {} is not terminated by CRLF", size),
+            pos + size,
+        ));
+    }
+    let chunk = buf[pos..(pos + size)].to_vec();
+    Ok(Some((pos + size + 2, Token::Chunk(chunk))))
+}
+
+/// Decode a bulk string, the declared length is checked against `max_len` before waiting for the
+/// data to arrive.
+fn decode_bulk_string(buf: &mut BytesMut, idx: usize, max_len: usize) -> DecodeResult {
+    match decode_raw_integer(buf, idx, "length") {
+        Ok(None) => Ok(None),
+        Ok(Some((pos, -1))) => Ok(Some((pos, RespValue::Nil))),
+        Ok(Some((pos, size))) if size >= 0 => {
+            let size = usize::try_from(size)
+                .map_err(|_| parse_error(format!("length out of range: {}", size), idx))?;
+            if size > max_len {
+                return Err(parse_error(format!("bulk string too large: {}", size), idx));
+            }
+            let remaining = buf.len() - pos;
+            let required_bytes = size + 2;
+
+            if remaining < required_bytes {
+                return Ok(None);
+            }
+            if &buf[(pos + size)..(pos + required_bytes)] != b"\r\n" {
+                return Err(parse_error(
+                    format!("Bulk string of size {} is not terminated by CRLF", size),
+                    pos + size,
+                ));
+            }
+
+            let bulk_string = RespValue::BulkString(buf[pos..(pos + size)].to_vec());
+            Ok(Some((pos + required_bytes, bulk_string)))
+        }
+        Ok(Some((_, size))) => Err(parse_error(format!("Invalid string size: {}", size), idx)),
+        Err(e) => Err(e),
+    }
+}
+
+/// A verbatim string is encoded the same as a bulk string, but with the first four bytes being the
+/// format and a `:`, e.g. `txt:`
+fn decode_verbatim(buf: &mut BytesMut, idx: usize, max_len: usize) -> DecodeResult {
+    match decode_bulk_string(buf, idx, max_len)? {
+        None => Ok(None),
+        Some((pos, RespValue::BulkString(mut data))) => {
+            if data.len() < 4 || data[3] != b':' {
+                return Err(parse_error(
+                    format!(
+                        "Verbatim string has no format: {}",
+                        String::from_utf8_lossy(&data)
+                    ),
+                    pos - data.len() - 2,
+                ));
+            }
+            let format = [data[0], data[1], data[2]];
+            data.drain(..4);
+            Ok(Some((pos, RespValue::Verbatim { format, data })))
+        }
+        Some(_) => Err(parse_error(
+            "Verbatim strings cannot be null".to_string(),
+            idx,
+        )),
+    }
+}
+
+/// The types of value which contain other values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AggregateKind {
+    Array,
+    Set,
+    Push,
+    Map,
+    Attribute,
+}
+
+impl AggregateKind {
+    fn name(self) -> &'static str {
+        match self {
+            AggregateKind::Array => "Arrays",
+            AggregateKind::Set => "Sets",
+            AggregateKind::Push => "Pushes",
+            AggregateKind::Map => "Maps",
+            AggregateKind::Attribute => "Attributes",
+        }
+    }
+
+    /// The number of values in each element, maps and attributes are made of key/value pairs
+    fn per_element(self) -> usize {
+        match self {
+            AggregateKind::Map | AggregateKind::Attribute => 2,
+            _ => 1,
+        }
+    }
+
+    /// Build the value from its decoded elements.
+    ///
+    /// Attributes are metadata about the reply that follows them, e.g. the popularity of keys,
+    /// which is not needed to interpret the reply itself.  They are discarded, so `None` is
+    /// returned for them, with the following value taking their place.
+    fn finish(self, values: Vec<RespValue>) -> Option<RespValue> {
+        match self {
+            AggregateKind::Array => Some(RespValue::Array(values)),
+            AggregateKind::Set => Some(RespValue::Set(values)),
+            AggregateKind::Push => Some(RespValue::Push(values)),
+            AggregateKind::Map => {
+                let mut pairs = Vec::with_capacity(values.len() / 2);
+                let mut values = values.into_iter();
+                while let (Some(k), Some(v)) = (values.next(), values.next()) {
+                    pairs.push((k, v));
+                }
+                Some(RespValue::Map(pairs))
+            }
+            AggregateKind::Attribute => None,
+        }
+    }
+}
+
+/// An aggregate whose header has been decoded, but not all of its elements
+#[derive(Debug, Clone)]
+struct PartialAggregate {
+    kind: AggregateKind,
+    /// The number of values still to be decoded, or `None` if the aggregate is streamed, in which
+    /// case it ends with a terminator instead
+    remaining: Option<usize>,
+    values: Vec<RespValue>,
+}
+
+/// A single token of RESP, either a complete value or the start or end of a value made up of
+/// several tokens
+enum Token {
+    Value(RespValue),
+    Aggregate(AggregateKind, Option<usize>),
+    StreamedString,
+    Chunk(Vec<u8>),
+    End,
+    /// Something which isn't part of any value, e.g. an empty inline command
+    Skip,
+}
+
+type TokenResult = Result<Option<(usize, Token)>, Error>;
+
+fn value_token(result: DecodeResult) -> TokenResult {
+    result.map(|value| value.map(|(pos, value)| (pos, Token::Value(value))))
+}
+
+/// Decode the header of an aggregate, the number of values declared is multiplied by the number
+/// of values in each element, e.g. two for maps.  The number of elements is checked against
+/// `max_len`.
+fn decode_aggregate(
+    buf: &mut BytesMut,
+    idx: usize,
+    kind: AggregateKind,
+    max_len: usize,
+) -> TokenResult {
+    match is_streamed(buf, idx)? {
+        None => return Ok(None),
+        Some(true) => return Ok(Some((idx + 3, Token::Aggregate(kind, None)))),
+        Some(false) => (),
+    }
+    match decode_raw_integer(buf, idx, "length")? {
+        None => Ok(None),
+        Some((pos, -1)) if kind == AggregateKind::Array => {
+            Ok(Some((pos, Token::Value(RespValue::Nil))))
+        }
+        Some((_, -1)) => Err(parse_error(format!("{} cannot be null", kind.name()), idx)),
+        Some((pos, size)) if size >= 0 => {
+            let size = usize::try_from(size)
+                .ok()
+                .and_then(|size| size.checked_mul(kind.per_element()))
+                .ok_or_else(|| parse_error(format!("length out of range: {}", size), idx))?;
+            if size / kind.per_element() > max_len {
+                return Err(parse_error(
+                    format!("array too large: {}", size / kind.per_element()),
+                    idx,
+                ));
+            }
+            Ok(Some((pos, Token::Aggregate(kind, Some(size)))))
+        }
+        Some((_, size)) => Err(parse_error(format!("Invalid array size: {}", size), idx)),
+    }
+}
+
+/// The terminator of a streamed aggregate, `.\r\n`
+fn decode_end(buf: &mut BytesMut, idx: usize) -> TokenResult {
+    if buf.len() < idx + 2 {
+        return Ok(None);
+    }
+    if &buf[idx..(idx + 2)] != b"\r\n" {
+        return Err(parse_error(
+            "Streamed aggregate terminator is not followed by CRLF".to_string(),
+            idx,
+        ));
+    }
+    Ok(Some((idx + 2, Token::End)))
+}
+
+fn decode_integer(buf: &mut BytesMut, idx: usize) -> DecodeResult {
+    match decode_raw_integer(buf, idx, "integer") {
+        Ok(None) => Ok(None),
+        Ok(Some((pos, int))) => Ok(Some((pos, RespValue::Integer(int)))),
+        Err(e) => Err(e),
+    }
+}
+
+/// A simple string is any series of bytes that ends with `\r\n`
+#[allow(clippy::unnecessary_wraps)]
+fn decode_simple_string(buf: &mut BytesMut, idx: usize) -> DecodeResult {
+    match scan_string(buf, idx) {
+        None => Ok(None),
+        Some((pos, string)) => Ok(Some((pos, RespValue::SimpleString(string)))),
+    }
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn decode_error(buf: &mut BytesMut, idx: usize) -> DecodeResult {
+    match scan_string(buf, idx) {
+        None => Ok(None),
+        Some((pos, string)) => Ok(Some((pos, RespValue::Error(string)))),
+    }
+}
+
+fn decode_double(buf: &mut BytesMut, idx: usize) -> DecodeResult {
+    match scan_string(buf, idx) {
+        None => Ok(None),
+        Some((pos, string)) => match string.parse() {
+            Ok(double) => Ok(Some((pos, RespValue::Double(double)))),
+            Err(_) => Err(parse_error(format!("Not a double: {}", string), idx)),
+        },
+    }
+}
+
+fn decode_boolean(buf: &mut BytesMut, idx: usize) -> DecodeResult {
+    if buf.len() < idx + 3 {
+        return Ok(None);
+    }
+    let value = match buf[idx] {
+        b't' => true,
+        b'f' => false,
+        val => {
+            return Err(parse_error(
+                format!("Unexpected byte in boolean: {}", val),
+                idx,
+            ))
+        }
+    };
+    if &buf[(idx + 1)..(idx + 3)] != b"\r\n" {
+        return Err(parse_error(
+            "Boolean is not terminated by CRLF".to_string(),
+            idx + 1,
+        ));
+    }
+    Ok(Some((idx + 3, RespValue::Boolean(value))))
+}
+
+fn decode_null(buf: &mut BytesMut, idx: usize) -> DecodeResult {
+    if buf.len() < idx + 2 {
+        return Ok(None);
+    }
+    if &buf[idx..(idx + 2)] != b"\r\n" {
+        return Err(parse_error(
+            "Null is not terminated by CRLF".to_string(),
+            idx,
+        ));
+    }
+    Ok(Some((idx + 2, RespValue::Nil)))
+}
+
+fn decode_big_number(buf: &mut BytesMut, idx: usize) -> DecodeResult {
+    match scan_string(buf, idx) {
+        None => Ok(None),
+        Some((pos, string)) => {
+            let digits = string.strip_prefix('-').unwrap_or(&string);
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(parse_error(format!("Not a big number: {}", string), idx));
+            }
+            Ok(Some((pos, RespValue::BigNumber(string))))
+        }
+    }
+}
+
+/// The type bytes which can start a top-level value, used to find the next value after an error
+const FRAME_TYPE_BYTES: &[u8] = b"$*:+-,#_(=%~>|";
+
+/// The maximum length of an inline command, this is the same as Redis's own limit
+const MAX_INLINE_LEN: usize = 64 * 1024;
+
+/// An inline command is a line of space-separated arguments, e.g. `SET foo bar\r\n`, these are
+/// decoded into an array of bulk strings as if they had been sent in the usual form.  Lines are
+/// limited to `max_len` bytes, which is checked before the end of the line arrives.
+fn decode_inline(buf: &mut BytesMut, idx: usize, max_len: usize) -> TokenResult {
+    let max_len = max_len.min(MAX_INLINE_LEN);
+    let end = match memchr::memchr(b'\n', &buf[idx..]) {
+        Some(end) if end <= max_len => idx + end,
+        None if buf.len() - idx <= max_len => return Ok(None),
+        _ => {
+            return Err(parse_error(
+                format!("inline command too long: more than {} bytes", max_len),
+                idx,
+            ))
+        }
+    };
+    let line = buf[idx..end].strip_suffix(b"\r").unwrap_or(&buf[idx..end]);
+    let args: Vec<_> = line
+        .split(|b| *b == b' ' || *b == b'\t')
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| RespValue::BulkString(arg.to_vec()))
+        .collect();
+    if args.is_empty() {
+        return Ok(Some((end + 1, Token::Skip)));
+    }
+    Ok(Some((end + 1, Token::Value(RespValue::Array(args)))))
+}
+
+/// Decode the next token, `inline` is whether a line without a known type byte should be decoded
+/// as an inline command
+fn decode_token(
+    buf: &mut BytesMut,
+    idx: usize,
+    max_bulk_len: usize,
+    max_array_len: usize,
+    inline: bool,
+) -> TokenResult {
     let length = buf.len();
     if length <= idx {
         return Ok(None);
     }
 
-    let first_byte = buf[idx];
-    match first_byte {
-        b'$' => decode_bulk_string(buf, idx + 1),
-        b'*' => decode_array(buf, idx + 1),
-        b':' => decode_integer(buf, idx + 1),
-        b'+' => decode_simple_string(buf, idx + 1),
-        b'-' => decode_error(buf, idx + 1),
-        _ => Err(parse_error(format!("Unexpected byte: {}", first_byte))),
+    let first_byte = buf[idx];
+    match first_byte {
+        b'$' => match is_streamed(buf, idx + 1)? {
+            None => Ok(None),
+            Some(true) => Ok(Some((idx + 4, Token::StreamedString))),
+            Some(false) => value_token(decode_bulk_string(buf, idx + 1, max_bulk_len)),
+        },
+        b'*' => decode_aggregate(buf, idx + 1, AggregateKind::Array, max_array_len),
+        b':' => value_token(decode_integer(buf, idx + 1)),
+        b'+' => value_token(decode_simple_string(buf, idx + 1)),
+        b'-' => value_token(decode_error(buf, idx + 1)),
+        b',' => value_token(decode_double(buf, idx + 1)),
+        b'#' => value_token(decode_boolean(buf, idx + 1)),
+        b'_' => value_token(decode_null(buf, idx + 1)),
+        b'(' => value_token(decode_big_number(buf, idx + 1)),
+        b'=' => value_token(decode_verbatim(buf, idx + 1, max_bulk_len)),
+        b'%' => decode_aggregate(buf, idx + 1, AggregateKind::Map, max_array_len),
+        b'~' => decode_aggregate(buf, idx + 1, AggregateKind::Set, max_array_len),
+        b'>' => decode_aggregate(buf, idx + 1, AggregateKind::Push, max_array_len),
+        b'|' => decode_aggregate(buf, idx + 1, AggregateKind::Attribute, max_array_len),
+        b'.' => decode_end(buf, idx + 1),
+        _ if inline => decode_inline(buf, idx, max_bulk_len),
+        _ => Err(parse_error(format!("Unexpected byte: {}", first_byte), idx)),
+    }
+}
+
+/// The error returned by `RespCodec::decode_all`, this keeps the values decoded before the
+/// invalid data.
+#[derive(Debug)]
+pub struct DecodeAllError {
+    values: Vec<RespValue>,
+    error: Error,
+}
+
+impl DecodeAllError {
+    /// The values decoded before the invalid data
+    pub fn values(&self) -> &[RespValue] {
+        &self.values
+    }
+
+    /// The error decoding the invalid data
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    /// The values decoded before the invalid data, and the error
+    pub fn into_parts(self) -> (Vec<RespValue>, Error) {
+        (self.values, self.error)
+    }
+}
+
+impl From<DecodeAllError> for Error {
+    fn from(err: DecodeAllError) -> Error {
+        err.error
+    }
+}
+
+impl std::error::Error for DecodeAllError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl fmt::Display for DecodeAllError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The underlying error is available as the `source`, so isn't repeated here
+        write!(f, "Invalid data after {} decoded values", self.values.len())
+    }
+}
+
+impl RespCodec {
+    /// Decode every complete value in the buffer, e.g. the replies to a pipeline which arrived in
+    /// a single read.  Only the bytes of a trailing incomplete value are left in the buffer, and
+    /// are kept by the codec as for `decode`.
+    ///
+    /// If the buffer contains invalid data the values decoded before it are available from the
+    /// `DecodeAllError`, which converts into the underlying `Error`.
+    pub fn decode_all(&mut self, buf: &mut BytesMut) -> Result<Vec<RespValue>, DecodeAllError> {
+        let mut values = Vec::new();
+        loop {
+            match self.decode(buf) {
+                Ok(Some(value)) => values.push(value),
+                Ok(None) => return Ok(values),
+                Err(error) => return Err(DecodeAllError { values, error }),
+            }
+        }
+    }
+
+    /// Decode as much of the buffer as possible, each token is removed from the buffer once
+    /// decoded, with any aggregates still waiting for elements being kept in the codec.  This
+    /// means a value which arrives over many reads is only decoded once.
+    fn decode_partial(&mut self, buf: &mut BytesMut) -> Result<Option<RespValue>, Error> {
+        loop {
+            let budget = self.max_frame_bytes.saturating_sub(self.frame_bytes);
+            let decoded = match self.streamed_string {
+                Some(ref data) => {
+                    let max_len = self.max_bulk_len.saturating_sub(data.len()).min(budget);
+                    decode_chunk(buf, 0, max_len)
+                }
+                None => {
+                    // Inline commands can only be top-level values
+                    let inline = self.inline && self.partial.is_empty();
+                    let max_bulk_len = self.max_bulk_len.min(budget);
+                    decode_token(buf, 0, max_bulk_len, self.max_array_len, inline)
+                }
+            };
+            // The earlier tokens of this frame have already been removed from the buffer, so
+            // count them when reporting where an error is
+            let decoded = decoded.map_err(|e| offset_error(e, self.frame_bytes))?;
+            let (pos, token) = match decoded {
+                None if buf.len() > self.max_buffer_bytes => {
+                    return Err(parse_error(
+                        format!(
+                            "reply buffer exceeded limit: more than {} bytes",
+                            self.max_buffer_bytes
+                        ),
+                        self.frame_bytes,
+                    ))
+                }
+                None => return Ok(None),
+                Some(token) => token,
+            };
+            let start = self.frame_bytes;
+            if pos > budget {
+                return Err(parse_error(
+                    format!("frame too large: more than {} bytes", self.max_frame_bytes),
+                    start,
+                ));
+            }
+            buf.advance(pos);
+            self.frame_bytes += pos;
+            if let Token::Aggregate(..) = token {
+                if self.partial.len() >= self.max_depth {
+                    return Err(parse_error(
+                        format!("Maximum nesting depth of {} exceeded", self.max_depth),
+                        start,
+                    ));
+                }
+            }
+            let value = match token {
+                Token::Value(value) => value,
+                Token::StreamedString => {
+                    self.streamed_string = Some(Vec::new());
+                    continue;
+                }
+                // Only top-level tokens are skipped, so the next frame starts afterwards
+                Token::Skip => {
+                    self.frame_bytes = 0;
+                    continue;
+                }
+                Token::Chunk(chunk) => match self.streamed_string {
+                    // A chunk of length zero ends the string
+                    Some(ref mut data) if !chunk.is_empty() => {
+                        data.extend_from_slice(&chunk);
+                        continue;
+                    }
+                    _ => RespValue::BulkString(self.streamed_string.take().unwrap_or_default()),
+                },
+                Token::Aggregate(kind, Some(0)) => match kind.finish(Vec::new()) {
+                    Some(value) => value,
+                    None => continue,
+                },
+                Token::Aggregate(kind, remaining) => {
+                    // The declared size comes from the peer, so don't trust it for pre-allocation
+                    // beyond the number of elements that could possibly fit in the bytes already
+                    // received.
+                    let capacity = remaining.unwrap_or(0).min(buf.len() / MIN_ENCODED_LEN);
+                    self.partial.push(PartialAggregate {
+                        kind,
+                        remaining,
+                        values: Vec::with_capacity(capacity),
+                    });
+                    continue;
+                }
+                Token::End => match self.partial.pop() {
+                    Some(PartialAggregate {
+                        kind,
+                        remaining: None,
+                        values,
+                    }) => {
+                        if values.len() % kind.per_element() != 0 {
+                            return Err(parse_error(
+                                format!(
+                                    "Streamed aggregate has an incomplete element: {} values",
+                                    values.len()
+                                ),
+                                start,
+                            ));
+                        }
+                        match kind.finish(values) {
+                            Some(value) => value,
+                            None => continue,
+                        }
+                    }
+                    _ => {
+                        return Err(parse_error(
+                            "Unexpected end of streamed aggregate".to_string(),
+                            start,
+                        ))
+                    }
+                },
+            };
+            if let Some(value) = self.complete(value, start)? {
+                self.frame_bytes = 0;
+                return Ok(Some(value));
+            }
+        }
+    }
+
+    /// Skip the buffer forward to the next type byte following a CRLF, returning whether one was
+    /// found.  If not, a trailing CR or CRLF is kept in case the type byte hasn't arrived yet.
+    fn resync(buf: &mut BytesMut) -> bool {
+        let start = buf
+            .windows(3)
+            .position(|window| &window[..2] == b"\r\n" && FRAME_TYPE_BYTES.contains(&window[2]));
+        let (start, found) = match start {
+            Some(pos) => (pos + 2, true),
+            None if buf.ends_with(b"\r\n") => (buf.len() - 2, false),
+            None if buf.ends_with(b"\r") => (buf.len() - 1, false),
+            None => (buf.len(), false),
+        };
+        buf.advance(start);
+        found
+    }
+
+    /// The stream cannot be resumed after an error, so don't keep any partial values
+    fn reset(&mut self) {
+        self.partial.clear();
+        self.streamed_string = None;
+        self.frame_bytes = 0;
+    }
+
+    /// Add a decoded value, which started at `offset` in the frame, to the aggregate it belongs
+    /// to, finishing any aggregates that are now complete.  Returns the value if it completes a
+    /// top-level value.
+    fn complete(
+        &mut self,
+        mut value: RespValue,
+        offset: usize,
+    ) -> Result<Option<RespValue>, Error> {
+        loop {
+            let aggregate = match self.partial.last_mut() {
+                None => return Ok(Some(value)),
+                Some(aggregate) => aggregate,
+            };
+            aggregate.values.push(value);
+            match aggregate.remaining {
+                Some(1) => (),
+                Some(ref mut remaining) => {
+                    *remaining -= 1;
+                    return Ok(None);
+                }
+                None => {
+                    // Streamed aggregates don't declare their length, so check it as it grows
+                    let len = aggregate.values.len() / aggregate.kind.per_element();
+                    if len > self.max_array_len {
+                        return Err(parse_error(format!("array too large: {}", len), offset));
+                    }
+                    return Ok(None);
+                }
+            }
+            let aggregate = match self.partial.pop() {
+                Some(aggregate) => aggregate,
+                None => return Ok(None),
+            };
+            value = match aggregate.kind.finish(aggregate.values) {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+        }
+    }
+}
+
+impl Decoder for RespCodec {
+    type Item = RespValue;
+    type Error = Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if self.resyncing {
+            if !RespCodec::resync(buf) {
+                return Ok(None);
+            }
+            self.resyncing = false;
+        }
+        let result = self.decode_partial(buf);
+        if result.is_err() {
+            self.reset();
+            if self.resync {
+                self.resyncing = !RespCodec::resync(buf);
+            }
+        }
+        result
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(buf)? {
+            Some(value) => Ok(Some(value)),
+            // Anything left while resynchronising is the tail of the invalid data
+            None if self.resyncing => Ok(None),
+            None if buf.is_empty() && self.partial.is_empty() && self.streamed_string.is_none() => {
+                Ok(None)
+            }
+            None => {
+                let offset = self.frame_bytes + buf.len();
+                self.reset();
+                Err(parse_error(
+                    "Stream ended in the middle of a value".to_string(),
+                    offset,
+                ))
+            }
+        }
+    }
+}
+
+/// Decodes `RespValue`s from a blocking `std::io::Read`, for use outside of Tokio.
+///
+/// This uses the same decoder as `RespCodec`, with the same limits, reading more data from the
+/// reader only when the data already read doesn't contain a complete value.
+///
+/// ```
+/// use std::io::Cursor;
+///
+/// use redis_async::resp::{RespReader, RespValue};
+///
+/// let mut reader = RespReader::new(Cursor::new(&b"+OK\r\n:42\r\n"[..]));
+/// assert_eq!(reader.read_value().unwrap(), Some(RespValue::SimpleString("OK".into())));
+/// assert_eq!(reader.read_value().unwrap(), Some(RespValue::Integer(42)));
+/// assert_eq!(reader.read_value().unwrap(), None);
+/// ```
+#[derive(Debug)]
+pub struct RespReader<R> {
+    reader: R,
+    codec: RespCodec,
+    buf: BytesMut,
+    /// Each read is into this, so that `buf` doesn't have to be zero-filled before every read
+    chunk: Box<[u8]>,
+    /// Whether iteration has ended because of an error
+    failed: bool,
+}
+
+/// The number of bytes `RespReader` attempts to read at a time
+const READ_SIZE: usize = 8 * 1024;
+
+impl<R: std::io::Read> RespReader<R> {
+    pub fn new(reader: R) -> Self {
+        RespReader::with_codec(reader, RespCodec::default())
+    }
+
+    /// Decode with the given codec, e.g. to decode RESP3 or to change the limits
+    pub fn with_codec(reader: R, codec: RespCodec) -> Self {
+        RespReader {
+            reader,
+            codec,
+            buf: BytesMut::new(),
+            chunk: vec![0; READ_SIZE].into_boxed_slice(),
+            failed: false,
+        }
+    }
+
+    /// Read the next value, blocking until it has been completely read.  Returns `None` if the
+    /// reader ends cleanly between values, and an error if it ends in the middle of one.
+    pub fn read_value(&mut self) -> Result<Option<RespValue>, Error> {
+        loop {
+            if let Some(value) = self.codec.decode(&mut self.buf)? {
+                return Ok(Some(value));
+            }
+
+            let read = loop {
+                match self.reader.read(&mut self.chunk) {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    result => break result?,
+                }
+            };
+            if read == 0 {
+                return self.codec.decode_eof(&mut self.buf);
+            }
+            self.buf.extend_from_slice(&self.chunk[..read]);
+        }
+    }
+
+    /// The underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Iteration ends after the first error, as the same data would fail to decode again, unless the
+/// codec resynchronises after invalid data, see `RespCodec::with_resync`.
+impl<R: std::io::Read> Iterator for RespReader<R> {
+    type Item = Result<RespValue, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.read_value().transpose();
+        self.failed = matches!(result, Some(Err(_))) && !self.codec.resync;
+        result
+    }
+}
+
+/// A frame decoded by `ChunkedRespCodec`
+#[derive(Debug, Clone, PartialEq)]
+pub enum RespFrame {
+    /// A complete value
+    Value(RespValue),
+    /// The start of a bulk string that will be delivered in chunks, with its declared length
+    BulkStart(usize),
+    /// The next part of the bulk string
+    BulkChunk(Bytes),
+    /// The bulk string is complete
+    BulkEnd,
+}
+
+/// A codec that delivers large bulk strings in chunks, as the data arrives, rather than as a single
+/// value.
+///
+/// A top-level bulk string with a length of at least `threshold` bytes is decoded as `BulkStart`,
+/// followed by any number of `BulkChunk`s, and then `BulkEnd`; so large values can be processed or
+/// forwarded incrementally, without being held in memory all at once.  All other values,
+/// including large bulk strings nested within aggregates, are decoded by the wrapped `RespCodec`
+/// as `Value`s.  Encoding is also delegated to the wrapped codec.
+#[derive(Debug, Clone)]
+pub struct ChunkedRespCodec {
+    codec: RespCodec,
+    threshold: usize,
+    /// The number of bytes of the current chunked bulk string still to be received, if one is
+    /// being received
+    remaining: Option<usize>,
+    /// The number of bytes of the current chunked bulk string received so far, including its
+    /// header, for reporting the offset of errors
+    received: usize,
+}
+
+impl ChunkedRespCodec {
+    pub fn new(codec: RespCodec, threshold: usize) -> Self {
+        ChunkedRespCodec {
+            codec,
+            threshold,
+            remaining: None,
+            received: 0,
+        }
+    }
+
+    /// The wrapped codec
+    pub fn codec(&self) -> &RespCodec {
+        &self.codec
+    }
+
+    pub fn codec_mut(&mut self) -> &mut RespCodec {
+        &mut self.codec
+    }
+
+    fn decode_frame(&mut self, buf: &mut BytesMut) -> Result<Option<RespFrame>, Error> {
+        match self.remaining {
+            Some(0) => {
+                if buf.len() < 2 {
+                    return Ok(None);
+                }
+                if &buf[..2] != b"\r\n" {
+                    return Err(parse_error(
+                        "Chunked bulk string is not terminated by CRLF".to_string(),
+                        self.received,
+                    ));
+                }
+                buf.advance(2);
+                self.remaining = None;
+                return Ok(Some(RespFrame::BulkEnd));
+            }
+            Some(remaining) => {
+                if buf.is_empty() {
+                    return Ok(None);
+                }
+                let chunk = buf.split_to(remaining.min(buf.len())).freeze();
+                self.remaining = Some(remaining - chunk.len());
+                self.received += chunk.len();
+                return Ok(Some(RespFrame::BulkChunk(chunk)));
+            }
+            None => (),
+        }
+
+        let top_level = self.codec.partial.is_empty() && self.codec.streamed_string.is_none();
+        if top_level && buf.first() == Some(&b'$') {
+            // Streamed strings have no declared length, so are left to the wrapped codec
+            match is_streamed(buf, 1)? {
+                None => return Ok(None),
+                Some(true) => return Ok(self.codec.decode_partial(buf)?.map(RespFrame::Value)),
+                Some(false) => (),
+            }
+            match decode_raw_integer(buf, 1, "length")? {
+                None => return Ok(None),
+                Some((pos, size)) if usize::try_from(size).is_ok_and(|s| s >= self.threshold) => {
+                    let size = size as usize;
+                    if size > self.codec.max_bulk_len {
+                        return Err(parse_error(format!("bulk string too large: {}", size), 1));
+                    }
+                    buf.advance(pos);
+                    self.remaining = Some(size);
+                    self.received = pos;
+                    return Ok(Some(RespFrame::BulkStart(size)));
+                }
+                Some(_) => (),
+            }
+        }
+
+        Ok(self.codec.decode_partial(buf)?.map(RespFrame::Value))
+    }
+}
+
+impl Decoder for ChunkedRespCodec {
+    type Item = RespFrame;
+    type Error = Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let result = self.decode_frame(buf);
+        if result.is_err() {
+            self.remaining = None;
+            self.codec.reset();
+        }
+        result
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(buf)? {
+            Some(frame) => Ok(Some(frame)),
+            None if self.remaining.is_none() => {
+                Ok(self.codec.decode_eof(buf)?.map(RespFrame::Value))
+            }
+            None => {
+                self.remaining = None;
+                Err(parse_error(
+                    "Stream ended in the middle of a value".to_string(),
+                    self.received + buf.len(),
+                ))
+            }
+        }
+    }
+}
+
+impl Encoder<RespValue> for ChunkedRespCodec {
+    type Error = Error;
+
+    fn encode(&mut self, msg: RespValue, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        self.codec.encode(&msg, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::borrow::Cow;
+    use std::cell::Cell;
+    use std::collections::{BTreeSet, HashMap, HashSet};
+    use std::net::{IpAddr, SocketAddr};
+    use std::str;
+    use std::time::Duration;
+
+    use bytes::BytesMut;
+
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::{
+        format_decimal, format_digits, write_header, ChunkedRespCodec, EncodeArg, Error, FromResp,
+        Millis, Pairs, RespCodec, RespFrame, RespReader, RespValue, RespVersion, Utf8String,
+        MAX_DECIMAL_LEN, SMALL_DECIMALS,
+    };
+
+    /// Counts the allocations made by each thread, so that tests can check how often the codec
+    /// allocates
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn count_allocation() {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count_allocation();
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            count_allocation();
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// The result of `f`, and the number of allocations and reallocations it made
+    fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        let before = ALLOCATIONS.with(Cell::get);
+        let result = f();
+        (result, ALLOCATIONS.with(Cell::get) - before)
+    }
+
+    fn obj_to_bytes(obj: RespValue) -> Vec<u8> {
+        let mut bytes = BytesMut::new();
+        let mut codec = RespCodec::default();
+        codec.encode(obj, &mut bytes).unwrap();
+        bytes.to_vec()
+    }
+
+    #[test]
+    fn test_resp_array_macro() {
+        let resp_object = resp_array!["SET", "x"];
+        let bytes = obj_to_bytes(resp_object);
+        assert_eq!(b"*2\r\n$3\r\nSET\r\n$1\r\nx\r\n", bytes.as_slice());
+
+        let resp_object = resp_array!["RPUSH", "wyz"].append(vec!["a", "b"]);
+        let bytes = obj_to_bytes(resp_object);
+        assert_eq!(
+            &b"*4\r\n$5\r\nRPUSH\r\n$3\r\nwyz\r\n$1\r\na\r\n$1\r\nb\r\n"[..],
+            bytes.as_slice()
+        );
+
+        let vals = vec![String::from("a"), String::from("b")];
+        let resp_object = resp_array!["RPUSH", "xyz"].append(&vals);
+        let bytes = obj_to_bytes(resp_object);
+        assert_eq!(
+            &b"*4\r\n$5\r\nRPUSH\r\n$3\r\nxyz\r\n$1\r\na\r\n$1\r\nb\r\n"[..],
+            bytes.as_slice()
+        );
+    }
+
+    #[test]
+    fn test_resp_array_macro_spread() {
+        let keys = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(
+            resp_array!["MGET"; keys.clone()],
+            resp_array!["MGET", "a", "b"]
+        );
+        assert_eq!(
+            resp_array!["HMGET", "hash"; keys.iter()],
+            resp_array!["HMGET", "hash", "a", "b"]
+        );
+        assert_eq!(resp_array!["DEL"; Vec::<String>::new()], resp_array!["DEL"]);
+        assert_eq!(
+            resp_array!["SADD", "set"; 1..3i64],
+            resp_array!["SADD", "set", 1i64, 2i64]
+        );
+    }
+
+    #[test]
+    fn test_integer_arguments() {
+        let bytes = obj_to_bytes(resp_array!["SET", "k", -5i64]);
+        assert_eq!(
+            &b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n:-5\r\n"[..],
+            bytes.as_slice()
+        );
+
+        assert_eq!(RespValue::from(7u32), RespValue::Integer(7));
+        assert_eq!(RespValue::from(-7i8), RespValue::Integer(-7));
+        assert_eq!(
+            RespValue::from(u64::MAX),
+            RespValue::BulkString(b"18446744073709551615".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_string_arguments() {
+        let owned = "owned".to_string();
+        assert_eq!(
+            RespValue::from(&owned),
+            RespValue::BulkString(b"owned".to_vec())
+        );
+        assert_eq!(
+            RespValue::from(Cow::Borrowed("borrowed")),
+            RespValue::BulkString(b"borrowed".to_vec())
+        );
+        assert_eq!(
+            RespValue::from(Cow::<str>::Owned(owned)),
+            RespValue::BulkString(b"owned".to_vec())
+        );
+        assert_eq!(RespValue::from('x'), RespValue::BulkString(b"x".to_vec()));
+        assert_eq!(
+            RespValue::from('é'),
+            RespValue::BulkString("é".as_bytes().to_vec())
+        );
+        assert_eq!(
+            resp_array!["SET", 'k', Cow::Borrowed("v")],
+            resp_array!["SET", "k", "v"]
+        );
+    }
+
+    #[test]
+    fn test_float_arguments() {
+        assert_eq!(
+            RespValue::from(2.5f64),
+            RespValue::BulkString(b"2.5".to_vec())
+        );
+        assert_eq!(
+            RespValue::from(0.1f32),
+            RespValue::BulkString(b"0.1".to_vec())
+        );
+        assert_eq!(
+            RespValue::from(-3f64),
+            RespValue::BulkString(b"-3".to_vec())
+        );
+        assert_eq!(
+            RespValue::from(f64::NEG_INFINITY),
+            RespValue::BulkString(b"-inf".to_vec())
+        );
+        assert_eq!(
+            RespValue::from(f64::NAN),
+            RespValue::BulkString(b"nan".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_bool_arguments() {
+        assert_eq!(RespValue::from(true), RespValue::Integer(1));
+        assert_eq!(RespValue::from(false), RespValue::Integer(0));
+    }
+
+    #[test]
+    fn test_option_arguments() {
+        assert_eq!(
+            RespValue::from(Some("a")),
+            RespValue::BulkString(b"a".to_vec())
+        );
+        assert_eq!(RespValue::from(None::<i64>), RespValue::Nil);
+
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::new();
+        codec
+            .encode(RespValue::from(Some(5i64)), &mut bytes)
+            .unwrap();
+        codec
+            .encode(RespValue::from(None::<String>), &mut bytes)
+            .unwrap();
+        assert_eq!(
+            Option::<i64>::from_resp(codec.decode(&mut bytes).unwrap().unwrap()).unwrap(),
+            Some(5)
+        );
+        assert_eq!(
+            Option::<String>::from_resp(codec.decode(&mut bytes).unwrap().unwrap()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_nested_arrays() {
+        let pairs = vec!["a", "1"];
+        let bytes = obj_to_bytes(resp_array!["X", pairs]);
+        assert_eq!(
+            &b"*2\r\n$1\r\nX\r\n*2\r\n$1\r\na\r\n$1\r\n1\r\n"[..],
+            bytes.as_slice()
+        );
+
+        let slice: &[i64] = &[1, 2];
+        assert_eq!(
+            RespValue::from(slice),
+            RespValue::Array(vec![RespValue::Integer(1), RespValue::Integer(2)])
+        );
+
+        // Bytes are still bulk strings
+        assert_eq!(
+            RespValue::from(vec![1u8, 2u8]),
+            RespValue::BulkString(vec![1, 2])
+        );
+    }
+
+    #[test]
+    fn test_pair_arguments() {
+        let mut map = HashMap::new();
+        map.insert("a", 1i64);
+        map.insert("b", 2i64);
+        match RespValue::from(map) {
+            RespValue::Array(values) => assert_eq!(values.len(), 4),
+            x => panic!("Expected an array, got: {:?}", x),
+        }
+
+        let pairs = vec![("a", "1"), ("b", "2")];
+        assert_eq!(RespValue::from(pairs), resp_array!["a", "1", "b", "2"]);
+    }
+
+    #[test]
+    fn test_ordering() {
+        let values = [
+            RespValue::Nil,
+            resp_array![],
+            resp_array!["a"],
+            resp_array!["a", 1i64],
+            resp_array!["b"],
+            RespValue::BulkString(b"a".to_vec()),
+            RespValue::BulkString(b"ab".to_vec()),
+            RespValue::Error("ERR".to_string()),
+            RespValue::Integer(i64::MIN),
+            RespValue::Integer(-1),
+            RespValue::Integer(2),
+            RespValue::SimpleString("OK".to_string()),
+            RespValue::Double(f64::NEG_INFINITY),
+            RespValue::Double(-0.0),
+            RespValue::Double(0.0),
+            RespValue::Double(f64::NAN),
+            RespValue::Boolean(false),
+            RespValue::Boolean(true),
+            RespValue::BigNumber("1".to_string()),
+            RespValue::Verbatim {
+                format: *b"mkd",
+                data: b"b".to_vec(),
+            },
+            RespValue::Verbatim {
+                format: *b"txt",
+                data: b"a".to_vec(),
+            },
+            RespValue::Map(vec![("a".into(), RespValue::Nil)]),
+            RespValue::Set(vec![RespValue::Nil]),
+            RespValue::Push(vec!["message".into()]),
+        ];
+        for (i, a) in values.iter().enumerate() {
+            for (j, b) in values.iter().enumerate() {
+                assert_eq!(a.cmp(b), i.cmp(&j), "{:?} and {:?}", a, b);
+                assert_eq!(a == b, i == j);
+            }
+        }
+
+        // Inserting in any order gives the same set
+        let forwards: BTreeSet<_> = values.iter().cloned().collect();
+        let backwards: BTreeSet<_> = values.iter().rev().cloned().chain(values.clone()).collect();
+        assert_eq!(forwards.len(), values.len());
+        assert_eq!(forwards, backwards);
+        assert!(forwards.into_iter().eq(values.iter().cloned()));
+    }
+
+    #[test]
+    fn test_hash() {
+        let set: HashSet<_> = [
+            RespValue::BulkString(b"1".to_vec()),
+            RespValue::SimpleString("1".to_string()),
+            RespValue::Integer(1),
+            RespValue::Double(1.0),
+            RespValue::Double(f64::NAN),
+            RespValue::Double(f64::NAN),
+            RespValue::Integer(1),
+            RespValue::Set(vec![RespValue::Integer(1)]),
+            resp_array![1i64],
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(set.len(), 7);
+        assert!(set.contains(&RespValue::Double(f64::NAN)));
+        assert!(set.contains(&resp_array![1i64]));
+        assert!(!set.contains(&RespValue::Double(2.0)));
+    }
+
+    #[test]
+    fn test_constructors() {
+        assert_eq!(RespValue::ok(), RespValue::SimpleString("OK".to_string()));
+        assert_eq!(
+            RespValue::pong(),
+            RespValue::SimpleString("PONG".to_string())
+        );
+        assert_eq!(
+            RespValue::error("ERR bad"),
+            RespValue::Error("ERR bad".to_string())
+        );
+        assert_eq!(
+            RespValue::error(String::from("WRONGTYPE")),
+            RespValue::Error("WRONGTYPE".to_string())
+        );
+        assert_eq!(
+            RespValue::bulk("abc"),
+            RespValue::BulkString(b"abc".to_vec())
+        );
+        assert_eq!(
+            RespValue::bulk(&b"\xff"[..]),
+            RespValue::BulkString(vec![0xff])
+        );
+        assert_eq!(
+            RespValue::bulk(vec![1, 2]),
+            RespValue::BulkString(vec![1, 2])
+        );
+        assert_eq!(RespValue::integer(-3), RespValue::Integer(-3));
+
+        let mut bytes = BytesMut::new();
+        RespCodec::default()
+            .encode(RespValue::ok(), &mut bytes)
+            .unwrap();
+        assert_eq!(&bytes[..], b"+OK\r\n");
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let size = std::mem::size_of::<RespValue>();
+        assert_eq!(RespValue::Nil.memory_usage(), size);
+        assert_eq!(RespValue::Integer(1).memory_usage(), size);
+
+        let mut bulk = Vec::with_capacity(64);
+        bulk.extend_from_slice(b"value");
+        assert_eq!(RespValue::BulkString(bulk).memory_usage(), size + 64);
+
+        let nested = RespValue::Array(vec![
+            RespValue::SimpleString("OK".to_string()),
+            RespValue::Map(vec![(
+                RespValue::BulkString(b"key".to_vec()),
+                RespValue::Array(vec![RespValue::Integer(1), RespValue::Double(1.5)]),
+            )]),
+        ]);
+        let expected = size
+            // The outer array's two elements, and the simple string
+            + 2 * size + 2
+            // The map's pair, and the key
+            + 2 * size + 3
+            // The inner array's two elements
+            + 2 * size;
+        assert_eq!(nested.memory_usage(), expected);
+    }
+
+    #[test]
+    fn test_into_bytes() {
+        let bulk = vec![0xff, 0x00, b'a'];
+        let ptr = bulk.as_ptr();
+        let bytes = RespValue::BulkString(bulk).into_bytes().unwrap();
+        assert_eq!(bytes, [0xff, 0x00, b'a']);
+        // The bytes are moved out rather than copied
+        assert_eq!(bytes.as_ptr(), ptr);
+
+        for (value, expected) in [
+            (RespValue::SimpleString("OK".to_string()), &b"OK"[..]),
+            (RespValue::Error("ERR bad".to_string()), b"ERR bad"),
+            (
+                RespValue::Verbatim {
+                    format: *b"txt",
+                    data: b"text".to_vec(),
+                },
+                b"text",
+            ),
+        ] {
+            assert_eq!(value.into_bytes().unwrap(), expected);
+        }
+
+        for value in [RespValue::Nil, RespValue::Integer(1), resp_array!["a"]] {
+            match value.clone().into_bytes() {
+                Err(Error::Resp(_, Some(resp))) => assert_eq!(resp, value),
+                x => panic!("Expected an error, got: {:?}", x),
+            }
+        }
+    }
+
+    #[test]
+    fn test_accessors() {
+        let simple = RespValue::SimpleString("OK".to_string());
+        assert_eq!(simple.as_str(), Some("OK"));
+        assert_eq!(simple.as_bytes(), Some(&b"OK"[..]));
+        assert_eq!(simple.as_i64(), None);
+        assert_eq!(simple.as_array(), None);
+
+        let bulk = RespValue::BulkString(b"abc".to_vec());
+        assert_eq!(bulk.as_str(), Some("abc"));
+        assert_eq!(bulk.as_bytes(), Some(&b"abc"[..]));
+
+        let binary = RespValue::BulkString(vec![0xff, 0xfe]);
+        assert_eq!(binary.as_str(), None);
+        assert_eq!(binary.as_bytes(), Some(&[0xff, 0xfe][..]));
+
+        let verbatim = RespValue::Verbatim {
+            format: *b"txt",
+            data: b"text".to_vec(),
+        };
+        assert_eq!(verbatim.as_str(), Some("text"));
+
+        let int = RespValue::Integer(42);
+        assert_eq!(int.as_i64(), Some(42));
+        assert_eq!(int.as_str(), None);
+        assert_eq!(int.as_bytes(), None);
+        assert_eq!(RespValue::BulkString(b"42".to_vec()).as_i64(), None);
+
+        let array = resp_array!["a", 1i64];
+        assert_eq!(
+            array.as_array(),
+            Some(&[RespValue::BulkString(b"a".to_vec()), RespValue::Integer(1)][..])
+        );
+        assert_eq!(array.as_str(), None);
+        assert_eq!(
+            RespValue::Set(vec![RespValue::Nil]).as_array(),
+            Some(&[RespValue::Nil][..])
+        );
+
+        for value in [
+            RespValue::Nil,
+            RespValue::Error("ERR".to_string()),
+            RespValue::Double(1.0),
+            RespValue::Boolean(true),
+            RespValue::Map(vec![]),
+        ] {
+            assert_eq!(value.as_str(), None);
+            assert_eq!(value.as_bytes(), None);
+            assert_eq!(value.as_i64(), None);
+            assert_eq!(value.as_array(), None);
+        }
+    }
+
+    #[test]
+    fn test_compare_to_literals() {
+        let ok = RespValue::SimpleString("OK".to_string());
+        assert!(ok.eq_str("OK"));
+        assert!(!ok.eq_str("ok"));
+        assert_eq!("OK", ok);
+        assert_eq!(*"OK", ok);
+        assert!(RespValue::BulkString(b"value".to_vec()).eq_str("value"));
+        assert!(!RespValue::Error("OK".to_string()).eq_str("OK"));
+        assert!(!RespValue::Nil.eq_str(""));
+
+        assert!(RespValue::Integer(5).eq_int(5));
+        assert!(RespValue::Double(5.0).eq_int(5));
+        assert!(!RespValue::Double(5.5).eq_int(5));
+        assert!(!RespValue::BulkString(b"5".to_vec()).eq_int(5));
+        assert_eq!(5, RespValue::Integer(5));
+        assert_ne!(6, RespValue::Integer(5));
+
+        // Only the literal side is implemented, so `.into()` still infers a `RespValue`
+        assert_eq!(RespValue::BulkString(b"OK".to_vec()), "OK".into());
+    }
+
+    #[test]
+    fn test_len() {
+        for (value, len) in [
+            (resp_array!["a", "b"], 2),
+            (RespValue::Set(vec![RespValue::Nil]), 1),
+            (RespValue::Push(vec![]), 0),
+            (
+                RespValue::Map(vec![("a".into(), 1i64.into()), ("b".into(), 2i64.into())]),
+                2,
+            ),
+            (RespValue::BulkString(b"hello".to_vec()), 5),
+            (RespValue::SimpleString("OK".to_string()), 2),
+            (
+                RespValue::Verbatim {
+                    format: *b"txt",
+                    data: b"abc".to_vec(),
+                },
+                3,
+            ),
+            (RespValue::Nil, 0),
+            (RespValue::Integer(100), 0),
+            (RespValue::Error("ERR".to_string()), 0),
+            (RespValue::Boolean(true), 0),
+        ] {
+            assert_eq!(value.len(), len, "{:?}", value);
+            assert_eq!(value.is_empty(), len == 0);
+        }
+    }
+
+    #[test]
+    fn test_index_and_iterate() {
+        let array = resp_array!["a", "b", "c"];
+        assert_eq!(array[1], "b".into());
+        assert_eq!(array.get(2), Some(&"c".into()));
+        assert_eq!(array.get(3), None);
+        assert_eq!(RespValue::Integer(1).get(0), None);
+
+        let borrowed: Vec<&RespValue> = (&array).into_iter().collect();
+        assert_eq!(borrowed.len(), 3);
+
+        let mut owned = Vec::new();
+        for item in array {
+            owned.push(String::from_resp(item).unwrap());
+        }
+        assert_eq!(owned, ["a", "b", "c"]);
+
+        assert_eq!(RespValue::Nil.into_iter().count(), 0);
+        assert_eq!((&RespValue::Integer(1)).into_iter().count(), 0);
+        assert_eq!(RespValue::Set(vec![RespValue::Nil]).into_iter().count(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_index_out_of_bounds() {
+        let _ = &resp_array!["a"][1];
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_index_non_array() {
+        let _ = &RespValue::Integer(1)[0];
+    }
+
+    #[test]
+    fn test_predicates() {
+        for (value, type_name) in [
+            (RespValue::Nil, "nil"),
+            (resp_array!["a"], "array"),
+            (RespValue::BulkString(vec![]), "bulk-string"),
+            (RespValue::Error("ERR".to_string()), "error"),
+            (RespValue::Integer(1), "integer"),
+            (RespValue::SimpleString("OK".to_string()), "simple-string"),
+            (RespValue::Double(1.0), "double"),
+            (RespValue::Boolean(false), "boolean"),
+            (RespValue::BigNumber("1".to_string()), "big-number"),
+            (
+                RespValue::Verbatim {
+                    format: *b"txt",
+                    data: vec![],
+                },
+                "verbatim-string",
+            ),
+            (RespValue::Map(vec![]), "map"),
+            (RespValue::Set(vec![]), "set"),
+            (RespValue::Push(vec![]), "push"),
+        ] {
+            assert_eq!(value.type_name(), type_name);
+            assert_eq!(value.is_error(), type_name == "error");
+            assert_eq!(value.is_nil(), type_name == "nil");
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        for (value, rendered) in [
+            (RespValue::Nil, "(nil)"),
+            (RespValue::BulkString(b"hello".to_vec()), "hello"),
+            (RespValue::BulkString(vec![b'a', 0xff, b'\n']), "a\\xff\\n"),
+            (RespValue::Error("ERR bad".to_string()), "(error) ERR bad"),
+            (RespValue::Integer(-5), "-5"),
+            (RespValue::SimpleString("OK".to_string()), "OK"),
+            (RespValue::Double(2.5), "2.5"),
+            (RespValue::Boolean(true), "true"),
+            (RespValue::BigNumber("1234".to_string()), "1234"),
+            (
+                RespValue::Verbatim {
+                    format: *b"txt",
+                    data: b"text".to_vec(),
+                },
+                "text",
+            ),
+            (
+                resp_array!["a", 1i64, resp_array![RespValue::Nil]],
+                "[a, 1, [(nil)]]",
+            ),
+            (RespValue::Array(vec![]), "[]"),
+            (
+                RespValue::Map(vec![("a".into(), 1i64.into()), ("b".into(), 2i64.into())]),
+                "{a: 1, b: 2}",
+            ),
+            (RespValue::Set(vec!["a".into(), "b".into()]), "{a, b}"),
+            (RespValue::Push(vec!["message".into()]), "[message]"),
+        ] {
+            assert_eq!(value.to_string(), rendered);
+        }
+    }
+
+    #[test]
+    fn test_encode_by_reference() {
+        let resp_object = resp_array![
+            "SET",
+            resp_array!["nested", 1i64],
+            RespValue::Map(vec![("a".into(), RespValue::Nil)])
+        ];
+        let mut codec = RespCodec::resp3();
+        let mut borrowed = BytesMut::new();
+        codec.encode(&resp_object, &mut borrowed).unwrap();
+        let mut owned = BytesMut::new();
+        codec.encode(resp_object.clone(), &mut owned).unwrap();
+        assert_eq!(borrowed, owned);
+
+        assert_eq!(codec.decode(&mut borrowed).unwrap(), Some(resp_object));
+    }
+
+    #[test]
+    fn test_write_header() {
+        for val in [
+            0,
+            1,
+            9,
+            10,
+            99,
+            100,
+            999,
+            1000,
+            12345,
+            usize::MAX as u64,
+            u64::MAX,
+        ] {
+            let mut digits = [0; MAX_DECIMAL_LEN];
+            assert_eq!(format_decimal(val, &mut digits), val.to_string().as_bytes());
+            let mut digits = [0; MAX_DECIMAL_LEN];
+            assert_eq!(format_digits(val, &mut digits), val.to_string().as_bytes());
+        }
+        for val in 0..SMALL_DECIMALS.len() as u64 {
+            let mut digits = [0; MAX_DECIMAL_LEN];
+            assert_eq!(format_decimal(val, &mut digits), val.to_string().as_bytes());
+        }
+
+        for val in [
+            0,
+            -1,
+            7,
+            9,
+            10,
+            99,
+            100,
+            999,
+            1000,
+            1024,
+            -1024,
+            i64::MAX,
+            i64::MIN,
+        ] {
+            let mut bytes = BytesMut::new();
+            write_header(b'*', val, &mut bytes);
+            assert_eq!(&bytes[..], format!("*{}\r\n", val).as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_encoded_size() {
+        let values = [
+            RespValue::Nil,
+            RespValue::Integer(0),
+            RespValue::Integer(-1234),
+            RespValue::Integer(i64::MIN),
+            RespValue::Integer(i64::MAX),
+            RespValue::BulkString(vec![b'x'; 1000]),
+            RespValue::SimpleString("OK".to_string()),
+            RespValue::Error("ERR".to_string()),
+            RespValue::Double(-2.5),
+            RespValue::Double(f64::NAN),
+            RespValue::Boolean(false),
+            RespValue::BigNumber("123456789012345678901234567890".to_string()),
+            RespValue::Verbatim {
+                format: *b"txt",
+                data: b"Some string".to_vec(),
+            },
+            RespValue::Map(vec![("a".into(), resp_array![1i64, "b"])]),
+            RespValue::Set(vec![RespValue::Nil; 12]),
+            RespValue::Push(vec!["message".into()]),
+            resp_array![resp_array![], (0..100i64).collect::<Vec<_>>()],
+        ];
+        for mut codec in [RespCodec::resp2(), RespCodec::resp3()] {
+            for value in &values {
+                let mut bytes = BytesMut::new();
+                codec.encode(value, &mut bytes).unwrap();
+                assert_eq!(codec.encoded_size(value), bytes.len(), "{:?}", value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_reserves_once() {
+        let value = RespValue::from((0..10_000i64).collect::<Vec<_>>());
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::new();
+        let (result, allocations) = count_allocations(|| codec.encode(&value, &mut bytes));
+        result.unwrap();
+        assert_eq!(allocations, 1);
+        assert_eq!(bytes.len(), codec.encoded_size(&value));
+
+        // Measuring doubles doesn't allocate either
+        let value = RespValue::Double(-2.5);
+        let (size, allocations) = count_allocations(|| codec.encoded_size(&value));
+        assert_eq!(size, 10);
+        assert_eq!(allocations, 0);
+    }
+
+    #[test]
+    fn test_nested_encode_does_not_reallocate() {
+        let value = RespValue::Array(
+            (0..10_000i64)
+                .map(|i| match i % 4 {
+                    0 => RespValue::BulkString(vec![b'x'; i as usize % 100]),
+                    1 => resp_array![i, "nested"],
+                    2 => RespValue::Map(vec![(RespValue::Integer(i), RespValue::Double(0.5))]),
+                    _ => RespValue::Nil,
+                })
+                .collect(),
+        );
+        for mut codec in [RespCodec::resp2(), RespCodec::resp3()] {
+            // The capacity for the whole value is reserved up-front, so none of the elements
+            // should need to reserve any more
+            let mut bytes = BytesMut::new();
+            let ((), allocations) = count_allocations(|| codec.encode(&value, &mut bytes).unwrap());
+            assert!(allocations <= 1, "{} allocations", allocations);
+            assert_eq!(bytes.len(), codec.encoded_size(&value));
+        }
+    }
+
+    #[test]
+    fn test_encode_command() {
+        for codec in [RespCodec::resp2(), RespCodec::resp3()] {
+            let args = ["SET", "key", "value"];
+            let mut streamed = BytesMut::new();
+            codec
+                .encode_command(args.len(), args, &mut streamed)
+                .unwrap();
+
+            let mut collected = BytesMut::new();
+            codec
+                .clone()
+                .encode(resp_array!["SET", "key", "value"], &mut collected)
+                .unwrap();
+            assert_eq!(streamed, collected);
+
+            // Nothing is allocated for the arguments
+            let mut bytes = BytesMut::with_capacity(64);
+            let key = String::from("key");
+            let ((), allocations) = count_allocations(|| {
+                codec
+                    .encode_command(2, [&b"INCR"[..], key.as_bytes()], &mut bytes)
+                    .unwrap()
+            });
+            assert_eq!(allocations, 0);
+            assert_eq!(&bytes[..], b"*2\r\n$4\r\nINCR\r\n$3\r\nkey\r\n");
+        }
+    }
+
+    #[test]
+    fn test_encode_command_numbers() {
+        let codec = RespCodec::default();
+        let mut bytes = BytesMut::with_capacity(64);
+        let args: [&dyn EncodeArg; 5] = [&"SET", &"k", &10, &"EX", &5u64];
+        let ((), allocations) =
+            count_allocations(|| codec.encode_command(args.len(), args, &mut bytes).unwrap());
+        assert_eq!(allocations, 0);
+        assert_eq!(
+            &bytes[..],
+            b"*5\r\n$3\r\nSET\r\n$1\r\nk\r\n$2\r\n10\r\n$2\r\nEX\r\n$1\r\n5\r\n"
+        );
+
+        for (arg, expected) in [
+            (&-1234i64 as &dyn EncodeArg, &b"$5\r\n-1234\r\n"[..]),
+            (&i64::MIN, b"$20\r\n-9223372036854775808\r\n"),
+            (&u64::MAX, b"$20\r\n18446744073709551615\r\n"),
+            (&1.5f64, b"$3\r\n1.5\r\n"),
+            (&f64::NAN, b"$3\r\nnan\r\n"),
+            (&b"raw", b"$3\r\nraw\r\n"),
+        ] {
+            let mut bytes = BytesMut::new();
+            codec.encode_command(1, [arg], &mut bytes).unwrap();
+            assert_eq!(&bytes[4..], expected);
+        }
+    }
+
+    #[test]
+    fn test_encode_command_wrong_length() {
+        let codec = RespCodec::default();
+        let mut bytes = BytesMut::from(&b"prefix"[..]);
+        let err = codec
+            .encode_command(3, ["GET", "key"], &mut bytes)
+            .unwrap_err();
+        assert!(matches!(err, Error::Resp(_, None)), "{:?}", err);
+        assert_eq!(&bytes[..], b"prefix");
+
+        let err = codec
+            .encode_command(1, ["GET", "key"], &mut bytes)
+            .unwrap_err();
+        assert!(matches!(err, Error::Resp(_, None)), "{:?}", err);
+        assert_eq!(&bytes[..], b"prefix");
+    }
+
+    #[test]
+    fn test_decode_all() {
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::from(&b"$3\r\nfoo\r\n$3\r\nbar\r\n*2\r\n:1"[..]);
+        assert_eq!(
+            codec.decode_all(&mut bytes).unwrap(),
+            vec![RespValue::from("foo"), RespValue::from("bar")]
+        );
+        assert!(codec.decode_all(&mut bytes).unwrap().is_empty());
+
+        bytes.extend_from_slice(b"\r\n:2\r\n+OK\r\n");
+        assert_eq!(
+            codec.decode_all(&mut bytes).unwrap(),
+            vec![
+                resp_array![1i64, 2i64],
+                RespValue::SimpleString("OK".to_string())
+            ]
+        );
+        assert!(bytes.is_empty());
+
+        let mut bytes = BytesMut::from(&b"+OK\r\n:1\r\n!"[..]);
+        let err = codec.decode_all(&mut bytes).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid data after 2 decoded values");
+        assert!(std::error::Error::source(&err).is_some());
+        assert_eq!(
+            err.values(),
+            [
+                RespValue::SimpleString("OK".to_string()),
+                RespValue::Integer(1)
+            ]
+        );
+        let (values, err) = err.into_parts();
+        assert_eq!(values.len(), 2);
+        assert!(matches!(err, Error::Decode(_, 0)), "{:?}", err);
+
+        let mut bytes = BytesMut::from(&b"!"[..]);
+        let err: Error = codec.decode_all(&mut bytes).unwrap_err().into();
+        assert!(matches!(err, Error::Decode(_, 0)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_bulk_string() {
+        let resp_object = RespValue::BulkString(b"THISISATEST".to_vec());
+        let mut bytes = BytesMut::new();
+        let mut codec = RespCodec::default();
+        codec.encode(resp_object.clone(), &mut bytes).unwrap();
+        assert_eq!(b"$11\r\nTHISISATEST\r\n".to_vec(), bytes.to_vec());
+
+        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(deserialized, resp_object);
+    }
+
+    #[test]
+    fn test_array() {
+        let resp_object = RespValue::Array(vec!["TEST1".into(), "TEST2".into()]);
+        let mut bytes = BytesMut::new();
+        let mut codec = RespCodec::default();
+        codec.encode(resp_object.clone(), &mut bytes).unwrap();
+        assert_eq!(
+            b"*2\r\n$5\r\nTEST1\r\n$5\r\nTEST2\r\n".to_vec(),
+            bytes.to_vec()
+        );
+
+        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(deserialized, resp_object);
+    }
+
+    #[test]
+    fn test_double() {
+        let mut codec = RespCodec::resp3();
+        for (resp_object, encoded) in [
+            (RespValue::Double(2.5), &b",2.5\r\n"[..]),
+            (RespValue::Double(-10.0), &b",-10\r\n"[..]),
+            (RespValue::Double(f64::INFINITY), &b",inf\r\n"[..]),
+            (RespValue::Double(f64::NEG_INFINITY), &b",-inf\r\n"[..]),
+            (RespValue::Double(f64::NAN), &b",nan\r\n"[..]),
+        ] {
+            let mut bytes = BytesMut::new();
+            codec.encode(resp_object.clone(), &mut bytes).unwrap();
+            assert_eq!(encoded, &bytes[..]);
+
+            let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+            assert_eq!(deserialized, resp_object);
+        }
+
+        let mut bytes = BytesMut::from(&b",1.5e3\r\n"[..]);
+        let value = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(f64::from_resp(value).unwrap(), 1500.0);
+
+        let mut bytes = BytesMut::from(&b",abc\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn test_boolean() {
+        let mut codec = RespCodec::resp3();
+        for (resp_object, encoded) in [
+            (RespValue::Boolean(true), &b"#t\r\n"[..]),
+            (RespValue::Boolean(false), &b"#f\r\n"[..]),
+        ] {
+            let mut bytes = BytesMut::new();
+            codec.encode(resp_object.clone(), &mut bytes).unwrap();
+            assert_eq!(encoded, &bytes[..]);
+
+            let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+            assert_eq!(deserialized, resp_object);
+        }
+        assert!(bool::from_resp(RespValue::Boolean(true)).unwrap());
+
+        let mut bytes = BytesMut::from(&b"#x\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn test_big_number() {
+        let big = "3492890328409238509324850943850943825024385";
+        let resp_object = RespValue::BigNumber(big.to_string());
+        let mut bytes = BytesMut::new();
+        let mut codec = RespCodec::resp3();
+        codec.encode(resp_object.clone(), &mut bytes).unwrap();
+        assert_eq!(format!("({}\r\n", big).as_bytes(), &bytes[..]);
+
+        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(deserialized, resp_object);
+        assert!(i64::from_resp(deserialized.clone()).is_err());
+        assert_eq!(String::from_resp(deserialized).unwrap(), big);
+
+        let value = RespValue::BigNumber("-12".to_string());
+        assert_eq!(i64::from_resp(value).unwrap(), -12);
+
+        for frame in [&b"(12a\r\n"[..], &b"(-\r\n"[..]] {
+            let mut bytes = BytesMut::from(frame);
+            assert!(codec.decode(&mut bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn test_verbatim_string() {
+        let mut codec = RespCodec::resp3();
+        for (format, encoded) in [
+            (b"txt", &b"=15\r\ntxt:Some string\r\n"[..]),
+            (b"mkd", &b"=15\r\nmkd:Some string\r\n"[..]),
+        ] {
+            let resp_object = RespValue::Verbatim {
+                format: *format,
+                data: b"Some string".to_vec(),
+            };
+            let mut bytes = BytesMut::new();
+            codec.encode(resp_object.clone(), &mut bytes).unwrap();
+            assert_eq!(encoded, &bytes[..]);
+
+            let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+            assert_eq!(deserialized, resp_object);
+            assert_eq!(
+                deserialized.verbatim_format(),
+                Some(str::from_utf8(format).unwrap())
+            );
+            assert_eq!(String::from_resp(deserialized).unwrap(), "Some string");
+        }
+
+        let mut bytes = BytesMut::from(&b"=3\r\ntxt\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn test_map() {
+        let resp_object = RespValue::Map(vec![
+            ("first".into(), RespValue::Integer(1)),
+            ("second".into(), RespValue::Integer(2)),
+        ]);
+        let mut bytes = BytesMut::new();
+        let mut codec = RespCodec::resp3();
+        codec.encode(resp_object.clone(), &mut bytes).unwrap();
+        assert_eq!(
+            &b"%2\r\n$5\r\nfirst\r\n:1\r\n$6\r\nsecond\r\n:2\r\n"[..],
+            &bytes[..]
+        );
+
+        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(deserialized, resp_object);
+
+        let map = HashMap::<String, i64>::from_resp(deserialized).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["first"], 1);
+        assert_eq!(map["second"], 2);
+    }
+
+    #[test]
+    fn test_set() {
+        let resp_object = RespValue::Set(vec!["a".into(), "b".into(), "c".into()]);
+        let mut bytes = BytesMut::new();
+        let mut codec = RespCodec::resp3();
+        codec.encode(resp_object.clone(), &mut bytes).unwrap();
+        assert_eq!(&b"~3\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n"[..], &bytes[..]);
+
+        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(deserialized, resp_object);
+
+        let expected = ["a", "b", "c"].iter().map(|s| s.to_string());
+        assert_eq!(
+            HashSet::<String>::from_resp(deserialized.clone()).unwrap(),
+            expected.clone().collect()
+        );
+        assert_eq!(
+            BTreeSet::<String>::from_resp(deserialized.clone()).unwrap(),
+            expected.clone().collect()
+        );
+        assert_eq!(
+            Vec::<String>::from_resp(deserialized).unwrap(),
+            expected.collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_push() {
+        let mut bytes =
+            BytesMut::from(&b">3\r\n$7\r\nmessage\r\n$7\r\nchannel\r\n$5\r\nhello\r\n"[..]);
+        let mut codec = RespCodec::resp3();
+        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+        let resp_object = RespValue::Push(vec!["message".into(), "channel".into(), "hello".into()]);
+        assert_eq!(deserialized, resp_object);
+
+        codec.encode(resp_object, &mut bytes).unwrap();
+        assert_eq!(codec.decode(&mut bytes).unwrap().unwrap(), deserialized);
+    }
+
+    #[test]
+    fn test_attributes_are_skipped() {
+        let attribute = &b"|1\r\n+key-popularity\r\n%1\r\n$1\r\na\r\n,0.19\r\n"[..];
+        let mut codec = RespCodec::default();
+
+        let mut bytes = BytesMut::from(attribute);
+        bytes.extend_from_slice(b"+OK\r\n");
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespValue::SimpleString("OK".to_string()))
+        );
+        assert!(bytes.is_empty());
+
+        let mut bytes = BytesMut::from(attribute);
+        bytes.extend_from_slice(b"*2\r\n:1\r\n:2\r\n+next\r\n");
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespValue::Array(vec![
+                RespValue::Integer(1),
+                RespValue::Integer(2)
+            ]))
+        );
+        assert_eq!(&bytes[..], b"+next\r\n");
+
+        let mut bytes = BytesMut::from(attribute);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+    }
+
+    #[test]
+    fn test_streamed_string() {
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::from(&b"$?\r\n;4\r\nHell\r\n;5\r\no wor\r\n"[..]);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+
+        bytes.extend_from_slice(b";1\r\nd\r\n;0\r\n");
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespValue::BulkString(b"Hello word".to_vec()))
+        );
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn test_streamed_aggregates() {
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::from(&b"*?\r\n:1\r\n:2\r\n.\r\n"[..]);
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespValue::Array(vec![
+                RespValue::Integer(1),
+                RespValue::Integer(2)
+            ]))
+        );
+
+        let mut bytes = BytesMut::from(&b"%?\r\n+a\r\n:1\r\n.\r\n"[..]);
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespValue::Map(vec![(
+                RespValue::SimpleString("a".to_string()),
+                RespValue::Integer(1)
+            )]))
+        );
+
+        let mut bytes = BytesMut::from(&b"%?\r\n+a\r\n.\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+
+        let mut bytes = BytesMut::from(&b"*?\r\n:1\r\n"[..]);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+    }
+
+    #[test]
+    fn test_streamed_header_not_terminated() {
+        for frame in [
+            &b"$?xy;1\r\na\r\n;0\r\n"[..],
+            b"*?\r:1\r\n.\r\n",
+            b"~?\n\r:1\r\n",
+        ] {
+            let mut bytes = BytesMut::from(frame);
+            match RespCodec::default().decode(&mut bytes) {
+                Err(Error::Decode(msg, 2)) => {
+                    assert_eq!(msg, "Streamed length is not terminated by CRLF")
+                }
+                x => panic!("Expected an error, got: {:?}", x),
+            }
+        }
+    }
+
+    #[test]
+    fn test_incremental_decode() {
+        let count = 100_000;
+        let mut input = format!("*{}\r\n", count).into_bytes();
+        for i in 0..count {
+            input.extend_from_slice(format!(":{}\r\n", i).as_bytes());
+        }
+
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::new();
+        let (last, rest) = input.split_last().unwrap();
+        for byte in rest {
+            bytes.extend_from_slice(&[*byte]);
+            assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        }
+        bytes.extend_from_slice(&[*last]);
+        let value = codec.decode(&mut bytes).unwrap().unwrap();
+
+        assert!(bytes.is_empty());
+        let values = Vec::<i64>::from_resp(value).unwrap();
+        assert_eq!(values, (0..count).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_incremental_decode_nested() {
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::from(&b"*2\r\n*2\r\n:1\r\n"[..]);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+
+        bytes.extend_from_slice(b":2\r\n$3\r\nabc\r\n+next\r\n");
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(resp_array![resp_array![1i64, 2i64], "abc"])
+        );
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespValue::SimpleString("next".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested_array() {
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::from("*1\r\n".repeat(100_000).as_bytes());
+        bytes.extend_from_slice(b":1\r\n");
+        assert!(codec.decode(&mut bytes).is_err());
+
+        let mut codec = RespCodec::default().with_max_depth(3);
+        let mut bytes = BytesMut::from(&b"*1\r\n*2\r\n*0\r\n:1\r\n"[..]);
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(resp_array![resp_array![RespValue::Array(vec![]), 1i64]])
+        );
+
+        let mut bytes = BytesMut::from(&b"*1\r\n%1\r\n+a\r\n*1\r\n*0\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn test_max_bulk_len() {
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::from(&b"$4294967295\r\n"[..]);
+        match codec.decode(&mut bytes) {
+            Err(Error::Decode(msg, 1)) => assert!(msg.starts_with("bulk string too large")),
+            _ => panic!("Should be an error"),
+        }
+
+        let mut codec = RespCodec::default().with_max_bulk_len(3);
+        let mut bytes = BytesMut::from(&b"$3\r\nabc\r\n$4\r\n"[..]);
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespValue::BulkString(b"abc".to_vec()))
+        );
+        assert!(codec.decode(&mut bytes).is_err());
+
+        let mut bytes = BytesMut::from(&b"$?\r\n;2\r\nab\r\n;2\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn test_max_array_len() {
+        let mut codec = RespCodec::default().with_max_array_len(2);
+        let mut bytes = BytesMut::from(&b"*2\r\n:1\r\n:2\r\n*3\r\n"[..]);
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(resp_array![1i64, 2i64])
+        );
+        assert!(codec.decode(&mut bytes).is_err());
+
+        let mut bytes = BytesMut::from(&b"%3\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+
+        let mut bytes = BytesMut::from(&b"*?\r\n:1\r\n:2\r\n:3\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn test_max_frame_bytes() {
+        // `*3\r\n` and three `:1\r\n` add up to 16 bytes
+        let mut codec = RespCodec::default().with_max_frame_bytes(16);
+        let mut bytes = BytesMut::from(&b"*3\r\n:1\r\n:2\r\n:3\r\n"[..]);
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(resp_array![1i64, 2i64, 3i64])
+        );
+
+        let mut bytes = BytesMut::from(&b"*1\r\n*3\r\n:1\r\n:2\r\n:3\r\n"[..]);
+        match codec.decode(&mut bytes) {
+            Err(Error::Decode(msg, 16)) => assert!(msg.starts_with("frame too large")),
+            _ => panic!("Should be an error"),
+        }
+
+        let mut bytes = BytesMut::from(&b"*1\r\n$100\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn test_max_buffer_bytes() {
+        let mut codec = RespCodec::default().with_max_buffer_bytes(64);
+        // Complete values can be decoded from a larger buffer
+        let mut bytes = BytesMut::new();
+        for _ in 0..10 {
+            bytes.extend_from_slice(b"+0123456789\r\n");
+        }
+        assert_eq!(codec.decode_all(&mut bytes).unwrap().len(), 10);
+
+        // A simple string which is never terminated fails once it's larger than the limit, the
+        // elements of the array before it have already been removed from the buffer
+        let mut bytes = BytesMut::from(&b"*2\r\n:1\r\n+"[..]);
+        for _ in 0..6 {
+            assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+            bytes.extend_from_slice(b"0123456789");
+        }
+        assert_eq!(bytes.len(), 61);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        bytes.extend_from_slice(b"0123456789");
+        match codec.decode(&mut bytes) {
+            Err(Error::Decode(msg, 8)) => {
+                assert_eq!(msg, "reply buffer exceeded limit: more than 64 bytes")
+            }
+            x => panic!("Expected an error, got: {:?}", x),
+        }
+
+        let mut bytes = BytesMut::from(&b"$100\r\n"[..]);
+        bytes.extend_from_slice(&[b'a'; 60]);
+        assert!(codec.decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn test_resync() {
+        // By default the invalid data is left in the buffer, so every decode fails
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::from(&b"?garbage\r\n:1\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+        assert!(codec.decode(&mut bytes).is_err());
+
+        let mut codec = RespCodec::default().with_resync(true);
+        let mut bytes = BytesMut::from(&b"?garbage\r\n:1\r\n"[..]);
+        match codec.decode(&mut bytes) {
+            Err(Error::Decode(msg, 0)) => assert_eq!(msg, "Unexpected byte: 63"),
+            x => panic!("Expected an error, got: {:?}", x),
+        }
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespValue::Integer(1))
+        );
+
+        // Garbage in the middle of an array discards the array
+        let mut bytes = BytesMut::from(&b"*3\r\n:1\r\n:x\r\n+OK\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+        assert_eq!(codec.decode(&mut bytes).unwrap(), Some(RespValue::ok()));
+        assert!(bytes.is_empty());
+
+        // The next value may arrive in a later read
+        let mut bytes = BytesMut::from(&b"?garbage\r"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+        assert_eq!(&bytes[..], b"\r");
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        bytes.extend_from_slice(b"\n$3\r\nfoo\r\n");
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespValue::BulkString(b"foo".to_vec()))
+        );
+
+        let mut bytes = BytesMut::from(&b"?garbage"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+        assert!(bytes.is_empty());
+        bytes.extend_from_slice(b"more garbage\r\n");
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        assert_eq!(codec.decode_eof(&mut bytes).unwrap(), None);
+    }
+
+    #[test]
+    fn test_inline_commands() {
+        let mut bytes = BytesMut::from(&b"PING\r\n"[..]);
+        assert!(RespCodec::default().decode(&mut bytes).is_err());
+
+        let mut codec = RespCodec::default().with_inline_commands(true);
+        let mut bytes = BytesMut::from(&b"PING\r\nSET  foo bar\r\n*1\r\n$4\r\nPING\r\n"[..]);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), Some(resp_array!["PING"]));
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(resp_array!["SET", "foo", "bar"])
+        );
+        assert_eq!(codec.decode(&mut bytes).unwrap(), Some(resp_array!["PING"]));
+
+        let mut bytes = BytesMut::from(&b"GET fo"[..]);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        bytes.extend_from_slice(b"o\n");
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(resp_array!["GET", "foo"])
+        );
+
+        let mut bytes = BytesMut::from(&b"*1\r\nPING\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+
+        // Empty lines are skipped
+        let mut bytes = BytesMut::from(&b"\r\n  \t\r\n\nPING\r\n\r\n"[..]);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), Some(resp_array!["PING"]));
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        assert!(bytes.is_empty());
+        assert_eq!(codec.decode_eof(&mut bytes).unwrap(), None);
+
+        // Quotes aren't interpreted
+        let mut bytes = BytesMut::from(&b"SET foo \"a b\"\r\n"[..]);
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(resp_array!["SET", "foo", "\"a", "b\""])
+        );
+    }
+
+    #[test]
+    fn test_inline_command_too_long() {
+        let mut codec = RespCodec::default().with_inline_commands(true);
+        let mut bytes = BytesMut::from(&b"SET foo "[..]);
+        bytes.extend_from_slice(&[b'x'; 64 * 1024]);
+        match codec.decode(&mut bytes) {
+            Err(Error::Decode(msg, 0)) => {
+                assert_eq!(msg, "inline command too long: more than 65536 bytes")
+            }
+            x => panic!("Expected an error, got: {:?}", x),
+        }
+
+        // A lower frame limit applies too, even before the line is complete
+        let mut codec = RespCodec::default()
+            .with_inline_commands(true)
+            .with_max_frame_bytes(16);
+        let mut bytes = BytesMut::from(&b"SET foo bar\r\n"[..]);
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(resp_array!["SET", "foo", "bar"])
+        );
+        let mut bytes = BytesMut::from(&b"SET foo barbazqux"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn test_incomplete_value_at_eof() {
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::from(&b"*2\r\n:1\r\n"[..]);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        assert!(bytes.is_empty());
+        assert!(codec.decode_eof(&mut bytes).is_err());
+        assert_eq!(codec.decode_eof(&mut bytes).unwrap(), None);
+    }
+
+    #[test]
+    fn test_nil_string() {
+        let mut bytes = BytesMut::new();
+        bytes.extend_from_slice(&b"$-1\r\n"[..]);
+
+        let mut codec = RespCodec::default();
+        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(deserialized, RespValue::Nil);
+    }
+
+    #[test]
+    fn test_resp3_null() {
+        let mut bytes = BytesMut::from(&b"_\r\n_"[..]);
+
+        let mut codec = RespCodec::default();
+        assert_eq!(codec.decode(&mut bytes).unwrap(), Some(RespValue::Nil));
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+
+        let mut bytes = BytesMut::from(&b"_x\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn test_nil_round_trip() {
+        let mut bytes = BytesMut::new();
+        let mut codec = RespCodec::default();
+        codec.encode(RespValue::Nil, &mut bytes).unwrap();
+        assert_eq!(b"$-1\r\n".to_vec(), bytes.to_vec());
+
+        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(deserialized, RespValue::Nil);
+    }
+
+    #[test]
+    fn test_nil_resp3() {
+        let mut bytes = BytesMut::new();
+        let mut codec = RespCodec::resp3();
+        codec.encode(RespValue::Nil, &mut bytes).unwrap();
+        assert_eq!(b"_\r\n".to_vec(), bytes.to_vec());
+
+        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(deserialized, RespValue::Nil);
+    }
+
+    #[test]
+    fn test_resp2_encoding() {
+        let mut codec = RespCodec::resp2();
+        assert_eq!(codec.protocol(), RespVersion::Resp2);
+        for (resp_object, encoded) in [
+            (RespValue::Boolean(true), &b":1\r\n"[..]),
+            (RespValue::Boolean(false), &b":0\r\n"[..]),
+            (RespValue::Double(2.5), &b"$3\r\n2.5\r\n"[..]),
+            (RespValue::BigNumber("12".to_string()), &b"$2\r\n12\r\n"[..]),
+            (
+                RespValue::Verbatim {
+                    format: *b"txt",
+                    data: b"abc".to_vec(),
+                },
+                &b"$3\r\nabc\r\n"[..],
+            ),
+            (
+                RespValue::Map(vec![("a".into(), RespValue::Integer(1))]),
+                &b"*2\r\n$1\r\na\r\n:1\r\n"[..],
+            ),
+            (
+                RespValue::Set(vec![RespValue::Integer(1)]),
+                &b"*1\r\n:1\r\n"[..],
+            ),
+            (
+                RespValue::Push(vec![RespValue::Integer(1)]),
+                &b"*1\r\n:1\r\n"[..],
+            ),
+        ] {
+            let mut bytes = BytesMut::new();
+            codec.encode(resp_object, &mut bytes).unwrap();
+            assert_eq!(encoded, &bytes[..]);
+        }
+
+        codec.set_protocol(RespVersion::Resp3);
+        let mut bytes = BytesMut::new();
+        codec.encode(RespValue::Boolean(true), &mut bytes).unwrap();
+        assert_eq!(&b"#t\r\n"[..], &bytes[..]);
+    }
+
+    #[test]
+    fn test_utf8_string_conversion() {
+        let valid = RespValue::from("héllo");
+        assert_eq!(String::from_resp(valid.clone()).unwrap(), "héllo");
+        assert_eq!(&*Utf8String::from_resp(valid).unwrap(), "héllo");
+
+        let invalid = RespValue::BulkString(b"bad \xff bytes".to_vec());
+        assert_eq!(
+            String::from_resp(invalid.clone()).unwrap(),
+            "bad \u{fffd} bytes"
+        );
+        match Utf8String::from_resp(invalid.clone()) {
+            Err(Error::Resp(msg, Some(resp))) => {
+                assert_eq!(msg, "invalid utf-8");
+                assert_eq!(resp, invalid);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        assert_eq!(
+            String::from(Utf8String::from_resp(RespValue::Integer(42)).unwrap()),
+            "42"
+        );
+        assert!(Utf8String::from_resp(resp_array!["a"]).is_err());
+    }
+
+    #[test]
+    fn test_box_conversion() {
+        assert_eq!(*Box::<i64>::from_resp(RespValue::Integer(42)).unwrap(), 42);
+        assert_eq!(
+            *Box::<Vec<String>>::from_resp(resp_array!["a", "b"]).unwrap(),
+            ["a", "b"]
+        );
+        assert!(Box::<i64>::from_resp(resp_array!["a"]).is_err());
+        assert!(matches!(
+            Box::<i64>::from_resp(RespValue::Error("ERR bad".to_string())),
+            Err(Error::Remote(_))
+        ));
+        // The inner type's handling of errors is kept
+        assert!(matches!(
+            *Box::<Result<i64, Error>>::from_resp(RespValue::Error("ERR bad".to_string())).unwrap(),
+            Err(Error::Remote(_))
+        ));
+    }
+
+    #[test]
+    fn test_cow_conversion() {
+        let cow = Cow::<'static, str>::from_resp(RespValue::from("héllo")).unwrap();
+        assert!(matches!(cow, Cow::Owned(_)));
+        assert_eq!(cow, "héllo");
+        assert_eq!(
+            Cow::<'static, str>::from_resp(RespValue::Integer(42)).unwrap(),
+            "42"
+        );
+        assert_eq!(
+            Cow::<'static, str>::from_resp(RespValue::SimpleString("OK".to_string())).unwrap(),
+            "OK"
+        );
+        assert!(Cow::<'static, str>::from_resp(resp_array!["a"]).is_err());
+    }
+
+    #[test]
+    fn test_duration_conversion() {
+        let ttl = Duration::from_millis(90_500);
+        assert_eq!(RespValue::from(ttl), RespValue::Integer(90));
+        assert_eq!(RespValue::from(Millis(ttl)), RespValue::Integer(90_500));
+        assert_eq!(
+            resp_array!["SET", "key", "value", "EX", Duration::from_secs(10)],
+            resp_array!["SET", "key", "value", "EX", 10i64]
+        );
+
+        for secs in [0, 1, 90, i64::MAX as u64] {
+            let duration = Duration::from_secs(secs);
+            assert_eq!(Duration::from_resp(duration.into()).unwrap(), duration);
+        }
+        for millis in [0, 1, 90_500] {
+            let duration = Millis(Duration::from_millis(millis));
+            assert_eq!(Millis::from_resp(duration.into()).unwrap(), duration);
+        }
+        assert_eq!(
+            Duration::from_resp(RespValue::BulkString(b"3".to_vec())).unwrap(),
+            Duration::from_secs(3)
+        );
+
+        for (reply, message) in [(-1, "key has no expiry"), (-2, "key does not exist")] {
+            for result in [
+                Duration::from_resp(RespValue::Integer(reply)),
+                Millis::from_resp(RespValue::Integer(reply)).map(Duration::from),
+            ] {
+                match result {
+                    Err(Error::Resp(msg, Some(_))) => assert_eq!(msg, message),
+                    x => panic!("Expected an error, got: {:?}", x),
+                }
+            }
+        }
+        assert!(Duration::from_resp(RespValue::Integer(-3)).is_err());
+        assert!(Duration::from_resp(resp_array![1i64]).is_err());
+    }
+
+    #[test]
+    fn test_char_conversion() {
+        assert_eq!(char::from_resp(RespValue::from("y")).unwrap(), 'y');
+        assert_eq!(
+            char::from_resp(RespValue::SimpleString("w".to_string())).unwrap(),
+            'w'
+        );
+        assert_eq!(
+            char::from_resp(RespValue::from("\u{e9}")).unwrap(),
+            '\u{e9}'
+        );
+
+        for value in [
+            RespValue::from("yes"),
+            RespValue::from(""),
+            RespValue::BulkString(vec![0xff]),
+            RespValue::Integer(1),
+            RespValue::Nil,
+        ] {
+            match char::from_resp(value.clone()) {
+                Err(Error::Resp(_, Some(resp))) => assert_eq!(resp, value),
+                x => panic!("Expected an error, got: {:?}", x),
+            }
+        }
+    }
+
+    #[test]
+    fn test_address_conversion() {
+        let addr: SocketAddr = "127.0.0.1:6379".parse().unwrap();
+        assert_eq!(
+            SocketAddr::from_resp(RespValue::from("127.0.0.1:6379")).unwrap(),
+            addr
+        );
+        let addr: SocketAddr = "[::1]:6380".parse().unwrap();
+        for string in ["[::1]:6380", "::1:6380"] {
+            assert_eq!(
+                SocketAddr::from_resp(RespValue::SimpleString(string.to_string())).unwrap(),
+                addr
+            );
+        }
+
+        assert_eq!(
+            IpAddr::from_resp(RespValue::from("127.0.0.1")).unwrap(),
+            IpAddr::from([127, 0, 0, 1])
+        );
+        assert_eq!(
+            IpAddr::from_resp(RespValue::from("::1")).unwrap(),
+            "::1".parse::<IpAddr>().unwrap()
+        );
+
+        for value in [
+            RespValue::from("127.0.0.1"),
+            RespValue::from("localhost:6379"),
+            RespValue::from("127.0.0.1:99999"),
+            RespValue::Integer(6379),
+        ] {
+            match SocketAddr::from_resp(value.clone()) {
+                Err(Error::Resp(_, Some(resp))) => assert_eq!(resp, value),
+                x => panic!("Expected an error, got: {:?}", x),
+            }
+        }
+        assert!(IpAddr::from_resp(RespValue::from("127.0.0.1:6379")).is_err());
+        assert!(IpAddr::from_resp(RespValue::BulkString(vec![0xff])).is_err());
+    }
+
+    #[test]
+    fn test_try_from() {
+        let string: String = RespValue::SimpleString("OK".to_string())
+            .try_into()
+            .unwrap();
+        assert_eq!(string, "OK");
+        let int: i64 = RespValue::Integer(42).try_into().unwrap();
+        assert_eq!(int, 42);
+        let int = i64::try_from(RespValue::BulkString(b"-7".to_vec())).unwrap();
+        assert_eq!(int, -7);
+        let bytes: Vec<u8> = RespValue::BulkString(vec![0xff, 0x00]).try_into().unwrap();
+        assert_eq!(bytes, [0xff, 0x00]);
+        let boolean: bool = RespValue::Integer(1).try_into().unwrap();
+        assert!(boolean);
+
+        assert!(matches!(
+            i64::try_from(resp_array!["a"]),
+            Err(Error::Resp(_, Some(_)))
+        ));
+        assert!(matches!(
+            String::try_from(RespValue::Error("ERR bad".to_string())),
+            Err(Error::Remote(_))
+        ));
+        assert!(bool::try_from(RespValue::Nil).is_err());
+    }
+
+    #[test]
+    fn test_from_resp_raw() {
+        let error = RespValue::Error("ERR bad".to_string());
+        assert_eq!(RespValue::from_resp_raw(error.clone()).unwrap(), error);
+        assert!(matches!(
+            RespValue::from_resp(error.clone()),
+            Err(Error::Remote(_))
+        ));
+
+        // Types which can't hold an error fail to convert, rather than returning a remote error
+        assert!(matches!(
+            String::from_resp_raw(error.clone()),
+            Err(Error::Resp(_, Some(_)))
+        ));
+        assert_eq!(
+            String::from_resp_raw(RespValue::SimpleString("OK".to_string())).unwrap(),
+            "OK"
+        );
+
+        let array = resp_array!["a", error.clone()];
+        assert!(matches!(
+            Vec::<RespValue>::from_resp_raw(array.clone()),
+            Err(Error::Remote(_))
+        ));
+        assert_eq!(RespValue::from_resp_raw(array.clone()).unwrap(), array);
+    }
+
+    #[test]
+    fn test_unit_conversion() {
+        for status in ["OK", "PONG", "QUEUED"] {
+            assert!(<()>::from_resp(RespValue::SimpleString(status.to_string())).is_ok());
+        }
+
+        assert!(<()>::from_resp(RespValue::SimpleString("NOPE".to_string())).is_err());
+        assert!(<()>::from_resp(RespValue::from("OK")).is_err());
+        assert!(<()>::from_resp(resp_array!["OK"]).is_err());
+        assert!(<()>::from_resp(RespValue::Error("ERR".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_nil_unit_conversion() {
+        assert!(<()>::from_resp(RespValue::Nil).is_err());
+        assert_eq!(Option::<()>::from_resp(RespValue::Nil).unwrap(), None);
+    }
+
+    #[test]
+    fn test_empty_bulk_string() {
+        let mut bytes = BytesMut::from(&b"$0\r\n\r\n"[..]);
+
+        let mut codec = RespCodec::default();
+        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(deserialized, RespValue::BulkString(Vec::new()));
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn test_bulk_string_missing_terminator() {
+        let mut bytes = BytesMut::from(&b"$3\r\nfooxx"[..]);
+        assert!(RespCodec::default().decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn test_nil_array() {
+        let mut bytes = BytesMut::from(&b"*-1\r\n"[..]);
+
+        let mut codec = RespCodec::default();
+        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(deserialized, RespValue::Nil);
     }
-}
 
-impl Decoder for RespCodec {
-    type Item = RespValue;
-    type Error = Error;
+    #[test]
+    fn test_empty_array() {
+        let mut bytes = BytesMut::from(&b"*0\r\n"[..]);
 
-    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        match decode(buf, 0) {
-            Ok(None) => Ok(None),
-            Ok(Some((pos, item))) => {
-                buf.advance(pos);
-                Ok(Some(item))
+        let mut codec = RespCodec::default();
+        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(deserialized, RespValue::Array(Vec::new()));
+    }
+
+    #[test]
+    fn test_incomplete_huge_array() {
+        let mut bytes = BytesMut::from(&b"*9223372036854775807\r\n:1\r\n"[..]);
+        assert_eq!(RespCodec::default().decode(&mut bytes).unwrap(), None);
+    }
+
+    #[test]
+    fn test_empty_length_header() {
+        for frame in [&b"$\r\n"[..], &b":\r\n"[..]] {
+            let mut bytes = BytesMut::from(frame);
+            match RespCodec::default().decode(&mut bytes) {
+                Err(Error::Decode(msg, 1)) => assert_eq!(msg, "empty length header"),
+                x => panic!("Expected an error, got: {:?}", x),
             }
-            Err(e) => Err(e),
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
+    #[test]
+    fn test_decode_error_offset() {
+        let frame = &b"*3\r\n:1\r\n$3\r\nfoo\r\n!oops\r\n"[..];
+        let mut bytes = BytesMut::from(frame);
+        let err = RespCodec::default().decode(&mut bytes).unwrap_err();
+        assert!(matches!(err, Error::Decode(_, 17)), "{:?}", err);
+        assert_eq!(err.to_string(), "Unexpected byte: 33 at byte 17");
 
-    use bytes::BytesMut;
+        // The offset is from the start of the value, even if it arrived over several reads
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::new();
+        for chunk in frame.chunks(5) {
+            bytes.extend_from_slice(chunk);
+            match codec.decode(&mut bytes) {
+                Ok(None) => (),
+                Err(Error::Decode(_, offset)) => {
+                    assert_eq!(offset, 17);
+                    return;
+                }
+                x => panic!("Unexpected result: {:?}", x),
+            }
+        }
+        panic!("Should be an error");
+    }
 
-    use tokio_util::codec::{Decoder, Encoder};
+    #[test]
+    fn test_scan_incomplete() {
+        for frame in [
+            &b"+OK"[..],
+            b"+OK\r",
+            b":123",
+            b":123\r",
+            b"$3\r",
+            b"+OK\nstill going",
+        ] {
+            let mut bytes = BytesMut::from(frame);
+            assert_eq!(RespCodec::default().decode(&mut bytes).unwrap(), None);
+            assert_eq!(&bytes[..], frame);
+        }
 
-    use super::{Error, FromResp, RespCodec, RespValue};
+        // A bad length is rejected before its terminator arrives
+        let mut bytes = BytesMut::from(&b":12x"[..]);
+        match RespCodec::default().decode(&mut bytes) {
+            Err(Error::Decode(msg, 3)) => assert_eq!(msg, "Unexpected byte in size_string: 120"),
+            x => panic!("Expected an error, got: {:?}", x),
+        }
+        let mut bytes = BytesMut::from(&b":12\rx"[..]);
+        assert!(matches!(
+            RespCodec::default().decode(&mut bytes),
+            Err(Error::Decode(_, 4))
+        ));
+    }
 
-    fn obj_to_bytes(obj: RespValue) -> Vec<u8> {
-        let mut bytes = BytesMut::new();
-        let mut codec = RespCodec;
-        codec.encode(obj, &mut bytes).unwrap();
-        bytes.to_vec()
+    #[test]
+    fn test_scan_bare_lf() {
+        let mut bytes = BytesMut::from(&b"+a\nb\r\n-\nERR\r\n"[..]);
+        let mut codec = RespCodec::default();
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespValue::SimpleString("a\nb".to_string()))
+        );
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespValue::Error("\nERR".to_string()))
+        );
+
+        let mut bytes = BytesMut::from(&b":1\n\r\n"[..]);
+        assert!(matches!(codec.decode(&mut bytes), Err(Error::Decode(_, 2))));
     }
 
     #[test]
-    fn test_resp_array_macro() {
-        let resp_object = resp_array!["SET", "x"];
-        let bytes = obj_to_bytes(resp_object);
-        assert_eq!(b"*2\r\n$3\r\nSET\r\n$1\r\nx\r\n", bytes.as_slice());
+    fn test_scan_bare_cr() {
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::from(&b"+a\rb\r\n+\r\r\n+a\r\r\rb\r\n"[..]);
+        for expected in ["a\rb", "\r", "a\r\r\rb"] {
+            assert_eq!(
+                codec.decode(&mut bytes).unwrap(),
+                Some(RespValue::SimpleString(expected.to_string()))
+            );
+        }
+        assert!(bytes.is_empty());
+    }
 
-        let resp_object = resp_array!["RPUSH", "wyz"].append(vec!["a", "b"]);
-        let bytes = obj_to_bytes(resp_object);
+    #[test]
+    fn test_scan_cr_at_boundary() {
+        let mut codec = RespCodec::default();
+        let mut bytes = BytesMut::from(&b"+abc\r"[..]);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+
+        // The CR isn't a terminator on its own, so a byte other than LF continues the string
+        bytes.extend_from_slice(b"d");
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        bytes.extend_from_slice(b"\r");
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        bytes.extend_from_slice(b"\n");
         assert_eq!(
-            &b"*4\r\n$5\r\nRPUSH\r\n$3\r\nwyz\r\n$1\r\na\r\n$1\r\nb\r\n"[..],
-            bytes.as_slice()
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespValue::SimpleString("abc\rd".to_string()))
         );
 
-        let vals = vec![String::from("a"), String::from("b")];
-        let resp_object = resp_array!["RPUSH", "xyz"].append(&vals);
-        let bytes = obj_to_bytes(resp_object);
+        let mut bytes = BytesMut::from(&b"-ERR\r"[..]);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        bytes.extend_from_slice(b"\n");
         assert_eq!(
-            &b"*4\r\n$5\r\nRPUSH\r\n$3\r\nxyz\r\n$1\r\na\r\n$1\r\nb\r\n"[..],
-            bytes.as_slice()
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespValue::Error("ERR".to_string()))
         );
+        assert!(bytes.is_empty());
     }
 
     #[test]
-    fn test_bulk_string() {
-        let resp_object = RespValue::BulkString(b"THISISATEST".to_vec());
-        let mut bytes = BytesMut::new();
-        let mut codec = RespCodec;
-        codec.encode(resp_object.clone(), &mut bytes).unwrap();
-        assert_eq!(b"$11\r\nTHISISATEST\r\n".to_vec(), bytes.to_vec());
-
-        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
-        assert_eq!(deserialized, resp_object);
+    fn test_length_overflow() {
+        for frame in [
+            &b"$18446744073709551616\r\n"[..],
+            &b"*18446744073709551616\r\n"[..],
+        ] {
+            let mut bytes = BytesMut::from(frame);
+            match RespCodec::default().decode(&mut bytes) {
+                Err(Error::Decode(msg, 1)) => assert!(msg.starts_with("length out of range")),
+                x => panic!("Expected an error, got: {:?}", x),
+            }
+        }
     }
 
     #[test]
-    fn test_array() {
-        let resp_object = RespValue::Array(vec!["TEST1".into(), "TEST2".into()]);
-        let mut bytes = BytesMut::new();
-        let mut codec = RespCodec;
-        codec.encode(resp_object.clone(), &mut bytes).unwrap();
-        assert_eq!(
-            b"*2\r\n$5\r\nTEST1\r\n$5\r\nTEST2\r\n".to_vec(),
-            bytes.to_vec()
-        );
+    fn test_integer() {
+        let mut codec = RespCodec::default();
+        for (resp_object, encoded) in [
+            (RespValue::Integer(-1), &b":-1\r\n"[..]),
+            (RespValue::Integer(12345), &b":12345\r\n"[..]),
+        ] {
+            let mut bytes = BytesMut::new();
+            codec.encode(resp_object.clone(), &mut bytes).unwrap();
+            assert_eq!(encoded, &bytes[..]);
 
-        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
-        assert_eq!(deserialized, resp_object);
+            let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
+            assert_eq!(deserialized, resp_object);
+        }
     }
 
     #[test]
-    fn test_nil_string() {
-        let mut bytes = BytesMut::new();
-        bytes.extend_from_slice(&b"$-1\r\n"[..]);
+    fn test_integer_misplaced_sign() {
+        let mut bytes = BytesMut::from(&b":12-3\r\n"[..]);
+        assert!(RespCodec::default().decode(&mut bytes).is_err());
+    }
 
-        let mut codec = RespCodec;
-        let deserialized = codec.decode(&mut bytes).unwrap().unwrap();
-        assert_eq!(deserialized, RespValue::Nil);
+    #[test]
+    fn test_integer_decode_overflow() {
+        let mut bytes = BytesMut::from(&b":99999999999999999999999999\r\n"[..]);
+        match RespCodec::default().decode(&mut bytes) {
+            Err(Error::Decode(msg, 1)) => assert!(msg.contains("99999999999999999999999999")),
+            x => panic!("Expected an error, got: {:?}", x),
+        }
     }
 
     #[test]
     fn test_integer_overflow() {
-        let resp_object = RespValue::Integer(i64::max_value());
+        let resp_object = RespValue::Integer(i64::MAX);
         let res = i32::from_resp(resp_object);
         assert!(res.is_err());
     }
@@ -781,12 +4994,198 @@ mod tests {
         assert!(res.is_err());
     }
 
+    #[test]
+    fn test_conversion_error_names_type() {
+        match String::from_resp(resp_array!["a"]) {
+            Err(Error::Resp(msg, Some(_))) => {
+                assert_eq!(
+                    msg,
+                    format!(
+                        "cannot convert array into {}",
+                        std::any::type_name::<String>()
+                    )
+                )
+            }
+            _ => panic!("Should be an error"),
+        }
+        match Vec::<(String, i64)>::from_resp(resp_array!["a"]) {
+            Err(Error::Resp(msg, Some(_))) => {
+                assert_eq!(
+                    msg,
+                    format!(
+                        "cannot convert bulk-string into {}",
+                        std::any::type_name::<(String, i64)>()
+                    )
+                );
+            }
+            _ => panic!("Should be an error"),
+        }
+    }
+
     #[test]
     fn test_integer_convesion() {
         let resp_object = RespValue::Integer(50);
         assert_eq!(u32::from_resp(resp_object).unwrap(), 50);
     }
 
+    #[test]
+    fn test_integer_widths() {
+        assert_eq!(i8::from_resp(RespValue::Integer(-128)).unwrap(), -128);
+        assert_eq!(u16::from_resp(RespValue::Integer(65535)).unwrap(), 65535);
+        assert_eq!(
+            i64::from_resp(RespValue::Integer(i64::MIN)).unwrap(),
+            i64::MIN
+        );
+
+        match i16::from_resp(RespValue::Integer(40000)) {
+            Err(Error::Resp(msg, _)) => assert_eq!(msg, "value out of range for i16"),
+            x => panic!("Expected an error, got: {:?}", x),
+        }
+        assert!(u16::from_resp(RespValue::Integer(-1)).is_err());
+    }
+
+    #[test]
+    fn test_integer_from_strings() {
+        assert_eq!(i64::from_resp("-42".into()).unwrap(), -42);
+        assert_eq!(
+            u64::from_resp(RespValue::SimpleString("18446744073709551615".to_string())).unwrap(),
+            u64::MAX
+        );
+        match i8::from_resp("128".into()) {
+            Err(Error::Resp(msg, _)) => assert_eq!(msg, "value out of range for i8"),
+            x => panic!("Expected an error, got: {:?}", x),
+        }
+        match u16::from_resp(RespValue::BigNumber("70000".to_string())) {
+            Err(Error::Resp(msg, _)) => assert_eq!(msg, "value out of range for u16"),
+            x => panic!("Expected an error, got: {:?}", x),
+        }
+        for value in [
+            RespValue::from("4.5"),
+            RespValue::from(""),
+            RespValue::from("12abc"),
+            RespValue::BulkString(vec![0xff]),
+            RespValue::Nil,
+        ] {
+            match u16::from_resp(value.clone()) {
+                Err(Error::Resp(msg, Some(resp))) => {
+                    assert!(!msg.contains("out of range"), "{}", msg);
+                    assert_eq!(resp, value);
+                }
+                x => panic!("Expected an error, got: {:?}", x),
+            }
+        }
+    }
+
+    #[test]
+    fn test_float_conversion() {
+        assert_eq!(f64::from_resp("2.5".into()).unwrap(), 2.5);
+        assert_eq!(f32::from_resp("2.5".into()).unwrap(), 2.5);
+        assert_eq!(f64::from_resp("inf".into()).unwrap(), f64::INFINITY);
+        assert_eq!(f64::from_resp("-inf".into()).unwrap(), f64::NEG_INFINITY);
+        assert!(f64::from_resp("nan".into()).unwrap().is_nan());
+        assert!(f64::from_resp("not a number".into()).is_err());
+    }
+
+    #[test]
+    fn test_bool_conversion() {
+        assert!(bool::from_resp(RespValue::Integer(1)).unwrap());
+        assert!(!bool::from_resp(RespValue::Integer(0)).unwrap());
+        assert!(bool::from_resp(RespValue::SimpleString("OK".to_string())).unwrap());
+        assert!(bool::from_resp("1".into()).is_err());
+    }
+
+    #[test]
+    fn test_option_conversion() {
+        let mut codec = RespCodec::default();
+
+        let mut bytes = BytesMut::from(&b"$-1\r\n"[..]);
+        let value = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(Option::<String>::from_resp(value).unwrap(), None);
+
+        let mut bytes = BytesMut::from(&b"$3\r\nfoo\r\n"[..]);
+        let value = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(
+            Option::<String>::from_resp(value).unwrap(),
+            Some("foo".to_string())
+        );
+
+        let value = RespValue::Error("ERR something".to_string());
+        assert!(Option::<String>::from_resp(value).is_err());
+    }
+
+    #[test]
+    fn test_vec_conversion() {
+        let mut bytes = BytesMut::from(&b"*2\r\n$1\r\na\r\n$1\r\nb\r\n"[..]);
+        let value = RespCodec::default().decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(
+            Vec::<String>::from_resp(value).unwrap(),
+            vec!["a".to_string(), "b".to_string()]
+        );
+
+        let value = RespValue::Array(vec!["a".into(), RespValue::Error("ERR".to_string())]);
+        assert!(matches!(
+            Vec::<String>::from_resp(value),
+            Err(Error::Remote(_))
+        ));
+
+        assert!(matches!(
+            Vec::<String>::from_resp("a".into()),
+            Err(Error::Resp(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_tuple_conversion() {
+        let value = RespValue::Array(vec!["member".into(), RespValue::Integer(5)]);
+        assert_eq!(
+            <(String, i64)>::from_resp(value).unwrap(),
+            ("member".to_string(), 5)
+        );
+
+        let value = RespValue::Array(vec!["a".into(), "b".into(), "c".into()]);
+        match <(String, String)>::from_resp(value) {
+            Err(Error::Resp(msg, _)) => assert_eq!(msg, "Array needs to be 2 elements, is: 3"),
+            x => panic!("Expected an error, got: {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_fixed_array_conversion() {
+        let mut bytes = BytesMut::from(&b"*2\r\n$10\r\n1700000000\r\n$6\r\n123456\r\n"[..]);
+        let time = RespCodec::default().decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(
+            <[i64; 2]>::from_resp(time).unwrap(),
+            [1_700_000_000, 123_456]
+        );
+        assert_eq!(<[String; 0]>::from_resp(resp_array![]).unwrap().len(), 0);
+
+        for (value, len) in [(resp_array!["1"], 1), (resp_array!["1", "2", "3"], 3)] {
+            match <[i64; 2]>::from_resp(value) {
+                Err(Error::Resp(msg, _)) => {
+                    assert_eq!(msg, format!("Array needs to be 2 elements, is: {}", len))
+                }
+                x => panic!("Expected an error, got: {:?}", x),
+            }
+        }
+        assert!(<[i64; 2]>::from_resp(resp_array!["1", "a"]).is_err());
+        assert!(<[i64; 2]>::from_resp(RespValue::Integer(1)).is_err());
+    }
+
+    #[test]
+    fn test_bytes_conversion() {
+        let data = vec![0x00, 0xFF, 0xFE, b'a'];
+        let mut bytes = BytesMut::new();
+        let mut codec = RespCodec::default();
+        codec
+            .encode(RespValue::from(data.clone()), &mut bytes)
+            .unwrap();
+        let value = codec.decode(&mut bytes).unwrap().unwrap();
+
+        assert_eq!(Vec::<u8>::from_resp(value.clone()).unwrap(), data);
+        assert_eq!(bytes::Bytes::from_resp(value).unwrap(), data);
+        assert!(Vec::<u8>::from_resp(RespValue::Integer(1)).is_err());
+    }
+
     #[test]
     fn test_hashmap_conversion() {
         let mut expected = HashMap::new();
@@ -805,6 +5204,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_hashmap_conversion_from_bytes() {
+        let mut bytes = BytesMut::from(&b"*4\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n$1\r\n2\r\n"[..]);
+        let value = RespCodec::default().decode(&mut bytes).unwrap().unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert("a".to_string(), 1);
+        expected.insert("b".to_string(), 2);
+        assert_eq!(HashMap::<String, i64>::from_resp(value).unwrap(), expected);
+    }
+
     #[test]
     fn test_hashmap_conversion_fails_with_odd_length_array() {
         let resp_object = RespValue::Array(vec![
@@ -821,4 +5231,306 @@ mod tests {
             _ => panic!("Should not be able to convert an odd number of elements to a hashmap"),
         }
     }
+
+    #[cfg(feature = "derive")]
+    #[derive(Debug, PartialEq, FromResp)]
+    struct ServerInfo {
+        server: String,
+        version: String,
+        #[resp(rename = "proto")]
+        protocol: i64,
+        id: i64,
+        mode: String,
+        role: String,
+        modules: Vec<String>,
+        #[resp(default)]
+        availability_zone: Option<String>,
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive_from_hello_reply() {
+        let reply = RespValue::Map(vec![
+            ("server".into(), "redis".into()),
+            ("version".into(), "7.2.4".into()),
+            ("proto".into(), RespValue::Integer(3)),
+            ("id".into(), RespValue::Integer(10)),
+            ("mode".into(), "standalone".into()),
+            ("role".into(), "master".into()),
+            ("modules".into(), RespValue::Array(vec![])),
+        ]);
+        assert_eq!(
+            ServerInfo::from_resp(reply).unwrap(),
+            ServerInfo {
+                server: "redis".into(),
+                version: "7.2.4".into(),
+                protocol: 3,
+                id: 10,
+                mode: "standalone".into(),
+                role: "master".into(),
+                modules: vec![],
+                availability_zone: None,
+            }
+        );
+
+        // The RESP2 reply is a flat array of keys and values
+        let reply = resp_array![
+            "server", "redis", "version", "6.2.0", "proto", "2", "id", "11", "mode", "cluster",
+            "role", "replica", "modules"
+        ]
+        .append(Some(resp_array![]))
+        .append(["availability_zone", "eu-west-1a"]);
+        let info = ServerInfo::from_resp(reply).unwrap();
+        assert_eq!(info.protocol, 2);
+        assert_eq!(info.role, "replica");
+        assert_eq!(info.availability_zone.as_deref(), Some("eu-west-1a"));
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive_missing_field() {
+        let reply = RespValue::Map(vec![("server".into(), "redis".into())]);
+        match ServerInfo::from_resp(reply) {
+            Err(Error::Resp(msg, None)) => assert_eq!(msg, "missing field version"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_result_conversion_captures_errors() {
+        let resp_object = RespValue::Array(vec![
+            "value".into(),
+            RespValue::Error("ERR something went wrong".into()),
+            RespValue::Array(vec![]),
+        ]);
+        let results = Vec::<Result<String, Error>>::from_resp(resp_object).unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap(), "value");
+        assert!(matches!(results[1], Err(Error::Remote(ref e)) if e == "ERR something went wrong"));
+        assert!(matches!(results[2], Err(Error::Resp(_, _))));
+
+        let result = Result::<String, Error>::from_resp(RespValue::Error("ERR top".into()));
+        assert!(matches!(result, Ok(Err(Error::Remote(_)))));
+    }
+
+    #[test]
+    fn test_nested_vec_conversion() {
+        let resp_object = resp_array![resp_array!["a", "b"], RespValue::Nil, resp_array!["c"]];
+
+        let nested = Vec::<Vec<String>>::from_resp(resp_object.clone()).unwrap();
+        assert_eq!(
+            nested,
+            vec![
+                vec!["a".to_string(), "b".to_string()],
+                vec![],
+                vec!["c".to_string()]
+            ]
+        );
+
+        let nested = Vec::<Option<Vec<String>>>::from_resp(resp_object).unwrap();
+        assert_eq!(nested[1], None);
+        assert_eq!(nested[2], Some(vec!["c".to_string()]));
+    }
+
+    #[test]
+    fn test_nested_tuple_conversion() {
+        // The format of an `XRANGE` reply
+        let resp_object = resp_array![
+            resp_array!["1-0", resp_array!["field", "value"]],
+            resp_array!["2-0", resp_array!["field", "value2", "other", "value3"]]
+        ];
+        let entries = Vec::<(String, Vec<String>)>::from_resp(resp_object).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, "1-0");
+        assert_eq!(entries[0].1, vec!["field", "value"]);
+        assert_eq!(entries[1].0, "2-0");
+        assert_eq!(entries[1].1, vec!["field", "value2", "other", "value3"]);
+
+        let resp_object = resp_array![resp_array![resp_array!["a"]], resp_array![]];
+        let triple = Vec::<Vec<Vec<String>>>::from_resp(resp_object).unwrap();
+        assert_eq!(triple, vec![vec![vec!["a".to_string()]], vec![]]);
+    }
+
+    #[test]
+    fn test_score_pairs_conversion() {
+        let resp_object = resp_array!["one", "1", "two", "2.5"];
+        let pairs = Pairs::<String, f64>::from_resp(resp_object).unwrap();
+        assert_eq!(
+            Vec::from(pairs),
+            vec![("one".to_string(), 1.0), ("two".to_string(), 2.5)]
+        );
+
+        // RESP3 replies to `ZRANGE ... WITHSCORES` are arrays of pairs
+        let resp_object = resp_array![
+            resp_array!["one", RespValue::Double(1.0)],
+            resp_array!["two", RespValue::Double(2.5)]
+        ];
+        let pairs = Pairs::<String, f64>::from_resp(resp_object).unwrap();
+        assert_eq!(
+            pairs.0,
+            vec![("one".to_string(), 1.0), ("two".to_string(), 2.5)]
+        );
+
+        let res = Pairs::<String, f64>::from_resp(resp_array!["one", "1", "two"]);
+        match res {
+            Err(Error::Resp(msg, _)) => {
+                assert_eq!(msg, "Cannot convert an odd number of elements into pairs")
+            }
+            _ => panic!("Should not be able to convert an odd number of elements to pairs"),
+        }
+    }
+
+    #[test]
+    fn test_chunked_bulk_string() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let mut encoded = BytesMut::new();
+        let mut codec = RespCodec::default();
+        codec
+            .encode(RespValue::BulkString(data.clone()), &mut encoded)
+            .unwrap();
+        codec
+            .encode(RespValue::from("small"), &mut encoded)
+            .unwrap();
+        codec
+            .encode(
+                resp_array![RespValue::BulkString(data.clone())],
+                &mut encoded,
+            )
+            .unwrap();
+
+        let mut codec = ChunkedRespCodec::new(RespCodec::default(), 1024);
+        let mut buf = BytesMut::new();
+        let mut frames = Vec::new();
+        for piece in encoded.chunks(1000) {
+            buf.extend_from_slice(piece);
+            while let Some(frame) = codec.decode(&mut buf).unwrap() {
+                frames.push(frame);
+            }
+        }
+        assert!(buf.is_empty());
+
+        assert_eq!(frames[0], RespFrame::BulkStart(data.len()));
+        let mut received = Vec::new();
+        let mut frames = frames.into_iter().skip(1).peekable();
+        while let Some(RespFrame::BulkChunk(chunk)) = frames.peek() {
+            assert!(chunk.len() <= 1000);
+            received.extend_from_slice(chunk);
+            frames.next();
+        }
+        assert_eq!(received, data);
+        assert_eq!(frames.next(), Some(RespFrame::BulkEnd));
+        assert_eq!(frames.next(), Some(RespFrame::Value("small".into())));
+        // Bulk strings within aggregates are not chunked
+        assert_eq!(
+            frames.next(),
+            Some(RespFrame::Value(resp_array![RespValue::BulkString(data)]))
+        );
+        assert_eq!(frames.next(), None);
+    }
+
+    #[test]
+    fn test_chunked_bulk_string_errors() {
+        let mut codec = ChunkedRespCodec::new(RespCodec::default(), 4);
+        let mut bytes = BytesMut::from(&b"$5\r\nhelloXX"[..]);
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespFrame::BulkStart(5))
+        );
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespFrame::BulkChunk("hello".into()))
+        );
+        assert!(codec.decode(&mut bytes).is_err());
+
+        let mut bytes = BytesMut::from(&b"$5\r\nhel"[..]);
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespFrame::BulkStart(5))
+        );
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespFrame::BulkChunk("hel".into()))
+        );
+        assert!(codec.decode_eof(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn test_chunked_streamed_string() {
+        let mut codec = ChunkedRespCodec::new(RespCodec::resp3(), 4);
+        let mut bytes = BytesMut::from(&b"$"[..]);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        bytes.extend_from_slice(b"?\r\n;5\r\nhello\r\n");
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        bytes.extend_from_slice(b";6\r\n world\r\n;0\r\n$5\r\nagain\r\n");
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespFrame::Value("hello world".into()))
+        );
+        // The codec is back at the top-level afterwards
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(RespFrame::BulkStart(5))
+        );
+    }
+
+    /// A reader that returns at most `n` bytes at a time, to split values across reads
+    struct Trickle<R>(R, usize);
+
+    impl<R: std::io::Read> std::io::Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.1);
+            self.0.read(&mut buf[..n])
+        }
+    }
+
+    #[test]
+    fn test_read_from_cursor() {
+        let bytes = &b"*2\r\n$3\r\nfoo\r\n:1\r\n+OK\r\n$-1\r\n"[..];
+        let values: Vec<_> = RespReader::new(Trickle(std::io::Cursor::new(bytes), 3))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            values,
+            vec![
+                resp_array!["foo", RespValue::Integer(1)],
+                RespValue::SimpleString("OK".to_string()),
+                RespValue::Nil,
+            ]
+        );
+
+        let mut reader = RespReader::new(std::io::Cursor::new(&b"$10\r\nfoo"[..]));
+        assert!(matches!(reader.read_value(), Err(Error::Decode(_, 8))));
+
+        let mut reader = RespReader::with_codec(
+            std::io::Cursor::new(&b"_\r\n#t\r\n"[..]),
+            RespCodec::resp3(),
+        );
+        assert_eq!(reader.read_value().unwrap(), Some(RespValue::Nil));
+        assert_eq!(reader.read_value().unwrap(), Some(RespValue::Boolean(true)));
+        assert_eq!(reader.read_value().unwrap(), None);
+    }
+
+    #[test]
+    fn test_reader_iteration_ends_after_error() {
+        let bytes = &b"+OK\r\n!oops\r\n:1\r\n"[..];
+        let mut reader = RespReader::new(std::io::Cursor::new(bytes));
+        assert_eq!(
+            reader.next().unwrap().unwrap(),
+            RespValue::SimpleString("OK".to_string())
+        );
+        assert!(matches!(reader.next(), Some(Err(Error::Decode(_, _)))));
+        assert!(reader.next().is_none());
+
+        // Unless the codec resynchronises
+        let reader = RespReader::with_codec(
+            std::io::Cursor::new(bytes),
+            RespCodec::default().with_resync(true),
+        );
+        let values: Vec<_> = reader.collect();
+        assert_eq!(values.len(), 3);
+        assert!(values[1].is_err());
+        assert_eq!(*values[2].as_ref().unwrap(), RespValue::Integer(1));
+    }
 }
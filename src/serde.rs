@@ -0,0 +1,814 @@
+/*
+ * Copyright 2017-2022 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Serde support for `RespValue`, enabled with the `serde` feature.
+//!
+//! Values are serialized as follows:
+//!
+//! | RESP                        | Serde                                             |
+//! |-----------------------------|---------------------------------------------------|
+//! | `Nil`                       | none, e.g. `null` in JSON                         |
+//! | `SimpleString`              | string                                            |
+//! | `BulkString`                | string if valid UTF-8, otherwise bytes            |
+//! | `Verbatim`                  | the data as for `BulkString`, the format is lost  |
+//! | `Error`                     | a map with a single `"error"` entry, the message |
+//! | `Integer`                   | `i64`                                             |
+//! | `Double`                    | `f64`                                             |
+//! | `Boolean`                   | `bool`                                            |
+//! | `BigNumber`                 | string, to avoid losing precision                 |
+//! | `Array`, `Set` and `Push`   | sequence                                          |
+//! | `Map`                       | map, keys are serialized as values                |
+//!
+//! Deserializing a `RespValue` is the reverse of the above: strings and bytes become `BulkString`,
+//! integers that don't fit in an `i64` become `BigNumber`, sequences become `Array`, and a map with
+//! a single `"error"` string entry becomes `Error`, other maps become `Map`.
+//!
+//! This mapping is part of the public API and won't change without a major version bump.  Note
+//! that some formats restrict the keys of maps, JSON for example only allows strings and numbers.
+//!
+//! `from_resp_value` goes further and deserializes a `RespValue` directly into any type
+//! implementing `Deserialize`, e.g. the reply of an `HGETALL` into a struct:
+//!
+//! ```
+//! use redis_async::{resp::RespValue, resp_array};
+//!
+//! #[derive(serde::Deserialize)]
+//! struct User {
+//!     name: String,
+//!     age: u32,
+//!     email: Option<String>,
+//! }
+//!
+//! let reply = resp_array!["name", "Alice", "age", "42"];
+//! let user: User = redis_async::serde::from_resp_value(reply).unwrap();
+//! assert_eq!(user.name, "Alice");
+//! assert_eq!(user.age, 42);
+//! assert_eq!(user.email, None);
+//! ```
+//!
+//! With the `json` feature `RespValue` can also be converted to and from a `serde_json::Value`
+//! directly, with `From` and `TryFrom`.  JSON nulls, booleans, numbers, strings, arrays and objects become `Nil`, `Boolean`,
+//! `Integer` (or `BigNumber` for integers beyond `i64`) or `Double`, `BulkString`, `Array` and
+//! `Map` respectively, and converting back gives the same JSON.  The other direction is lossy in a
+//! few places:
+//!
+//! * simple, bulk and verbatim strings all become JSON strings, and the format of a verbatim
+//!   string is lost.  Bulk strings which aren't valid UTF-8 can't be converted.
+//! * sets and pushes become arrays.
+//! * big numbers are only converted if they fit in a `u64` or `i64`.
+//! * doubles which are infinite or `NaN` can't be converted, JSON has no representation for them.
+//! * the keys of maps must be strings, or numbers, which are converted to strings.
+//! * error replies are returned as `Error::Remote`.
+//!
+//! Note too that RESP2 has no booleans, doubles or maps, so a `RespValue` built from JSON and sent
+//! to Redis over RESP2 will come back as integers, bulk strings and arrays.
+
+use std::fmt;
+
+use serde::de::{
+    self,
+    value::{MapDeserializer, SeqDeserializer},
+    Deserialize, DeserializeOwned, Deserializer, IntoDeserializer, MapAccess, SeqAccess, Visitor,
+};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::{
+    error::{self, Error},
+    resp::RespValue,
+};
+
+impl Serialize for RespValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        fn serialize_bytes<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+            match std::str::from_utf8(bytes) {
+                Ok(string) => serializer.serialize_str(string),
+                Err(_) => serializer.serialize_bytes(bytes),
+            }
+        }
+
+        match self {
+            RespValue::Nil => serializer.serialize_none(),
+            RespValue::SimpleString(string) | RespValue::BigNumber(string) => {
+                serializer.serialize_str(string)
+            }
+            RespValue::BulkString(bytes) => serialize_bytes(bytes, serializer),
+            RespValue::Verbatim { data, .. } => serialize_bytes(data, serializer),
+            RespValue::Error(string) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("error", string)?;
+                map.end()
+            }
+            RespValue::Integer(int) => serializer.serialize_i64(*int),
+            RespValue::Double(double) => serializer.serialize_f64(*double),
+            RespValue::Boolean(boolean) => serializer.serialize_bool(*boolean),
+            RespValue::Array(values) | RespValue::Set(values) | RespValue::Push(values) => {
+                serializer.collect_seq(values)
+            }
+            RespValue::Map(pairs) => serializer.collect_map(pairs.iter().map(|(k, v)| (k, v))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RespValue {
+    fn deserialize<D>(deserializer: D) -> Result<RespValue, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(RespValueVisitor)
+    }
+}
+
+struct RespValueVisitor;
+
+impl<'de> Visitor<'de> for RespValueVisitor {
+    type Value = RespValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a RESP value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<RespValue, E> {
+        Ok(RespValue::Boolean(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<RespValue, E> {
+        Ok(RespValue::Integer(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<RespValue, E> {
+        Ok(match i64::try_from(v) {
+            Ok(int) => RespValue::Integer(int),
+            Err(_) => RespValue::BigNumber(v.to_string()),
+        })
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<RespValue, E> {
+        Ok(RespValue::Double(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<RespValue, E> {
+        Ok(v.into())
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<RespValue, E> {
+        Ok(v.into())
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<RespValue, E> {
+        Ok(v.into())
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<RespValue, E> {
+        Ok(v.into())
+    }
+
+    fn visit_none<E>(self) -> Result<RespValue, E> {
+        Ok(RespValue::Nil)
+    }
+
+    fn visit_unit<E>(self) -> Result<RespValue, E> {
+        Ok(RespValue::Nil)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<RespValue, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        RespValue::deserialize(deserializer)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<RespValue, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(RespValue::Array(values))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<RespValue, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut pairs: Vec<(RespValue, RespValue)> =
+            Vec::with_capacity(map.size_hint().unwrap_or(0).min(4096));
+        while let Some(pair) = map.next_entry()? {
+            pairs.push(pair);
+        }
+        match pairs.as_slice() {
+            [(key, RespValue::BulkString(message))] if key.as_str() == Some("error") => {
+                match String::from_utf8(message.clone()) {
+                    Ok(message) => Ok(RespValue::Error(message)),
+                    Err(_) => Ok(RespValue::Map(pairs)),
+                }
+            }
+            _ => Ok(RespValue::Map(pairs)),
+        }
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error::Resp(msg.to_string(), None)
+    }
+}
+
+/// Deserialize a `RespValue` into any type that implements `Deserialize`.
+///
+/// As well as the direct mapping described in the module documentation, this follows the
+/// conventions of Redis replies so that they can be deserialized without any intermediate
+/// steps:
+///
+/// * Structs and maps can be deserialized from `Map`s, and also from `Array`s of alternating keys
+///   and values, i.e. the RESP2 reply to commands like `HGETALL`.
+/// * Numbers and booleans can be deserialized from strings, e.g. the values of a hash.  Booleans
+///   can also be deserialized from integers, zero is `false`.
+/// * `Option`s are `None` if the value is `Nil`.
+/// * Unit enum variants can be deserialized from strings.
+/// * An `Error` reply is returned as an `Error::Remote`.
+pub fn from_resp_value<T>(resp: RespValue) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    T::deserialize(resp)
+}
+
+impl<'de> IntoDeserializer<'de, Error> for RespValue {
+    type Deserializer = RespValue;
+
+    fn into_deserializer(self) -> RespValue {
+        self
+    }
+}
+
+fn string_value(resp: &RespValue) -> Option<&str> {
+    match resp {
+        RespValue::SimpleString(string) | RespValue::BigNumber(string) => Some(string),
+        RespValue::BulkString(bytes) | RespValue::Verbatim { data: bytes, .. } => {
+            std::str::from_utf8(bytes).ok()
+        }
+        _ => None,
+    }
+}
+
+fn parse_error<T>(string: &str) -> Error {
+    error::resp(
+        format!("cannot parse {} from string", std::any::type_name::<T>()),
+        RespValue::from(string),
+    )
+}
+
+macro_rules! deserialize_from_str {
+    ($($method:ident => $visit:ident: $t:ty),* $(,)?) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Error>
+            where
+                V: Visitor<'de>,
+            {
+                match string_value(&self) {
+                    Some(string) => match string.parse::<$t>() {
+                        Ok(value) => visitor.$visit(value),
+                        Err(_) => Err(parse_error::<$t>(string)),
+                    },
+                    None => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for RespValue {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            RespValue::Nil => visitor.visit_unit(),
+            RespValue::SimpleString(string) | RespValue::BigNumber(string) => {
+                visitor.visit_string(string)
+            }
+            RespValue::BulkString(bytes) | RespValue::Verbatim { data: bytes, .. } => {
+                match String::from_utf8(bytes) {
+                    Ok(string) => visitor.visit_string(string),
+                    Err(e) => visitor.visit_byte_buf(e.into_bytes()),
+                }
+            }
+            RespValue::Error(string) => Err(Error::Remote(string)),
+            RespValue::Integer(int) => visitor.visit_i64(int),
+            RespValue::Double(double) => visitor.visit_f64(double),
+            RespValue::Boolean(boolean) => visitor.visit_bool(boolean),
+            RespValue::Array(values) | RespValue::Set(values) | RespValue::Push(values) => {
+                let mut seq = SeqDeserializer::new(values.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            RespValue::Map(pairs) => {
+                let mut map = MapDeserializer::new(pairs.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    deserialize_from_str! {
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_i128 => visit_i128: i128,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_u128 => visit_u128: u128,
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            RespValue::Integer(int) => visitor.visit_bool(int != 0),
+            ref resp => match string_value(resp) {
+                Some("1") | Some("true") => visitor.visit_bool(true),
+                Some("0") | Some("false") => visitor.visit_bool(false),
+                Some(string) => Err(parse_error::<bool>(string)),
+                None => self.deserialize_any(visitor),
+            },
+        }
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            RespValue::Integer(int) => visitor.visit_string(int.to_string()),
+            RespValue::Double(double) => visitor.visit_string(double.to_string()),
+            resp => resp.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            RespValue::BulkString(bytes) | RespValue::Verbatim { data: bytes, .. } => {
+                visitor.visit_byte_buf(bytes)
+            }
+            resp => resp.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            RespValue::Nil => visitor.visit_none(),
+            resp => visitor.visit_some(resp),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            RespValue::Array(values) => {
+                if values.len() % 2 != 0 {
+                    return Err(error::resp(
+                        "Array of key/value pairs has an odd number of elements",
+                        RespValue::Array(values),
+                    ));
+                }
+                let mut values = values.into_iter();
+                let pairs = std::iter::from_fn(|| Some((values.next()?, values.next()?)));
+                let mut map = MapDeserializer::new(pairs);
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            resp => resp.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match string_value(&self) {
+            Some(string) => visitor.visit_enum(string.to_string().into_deserializer()),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        char unit unit_struct seq tuple tuple_struct identifier ignored_any
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Value> for RespValue {
+    fn from(value: serde_json::Value) -> RespValue {
+        match value {
+            serde_json::Value::Null => RespValue::Nil,
+            serde_json::Value::Bool(boolean) => RespValue::Boolean(boolean),
+            serde_json::Value::Number(number) => {
+                if let Some(int) = number.as_i64() {
+                    RespValue::Integer(int)
+                } else if number.is_u64() {
+                    RespValue::BigNumber(number.to_string())
+                } else {
+                    RespValue::Double(number.as_f64().unwrap_or(f64::NAN))
+                }
+            }
+            serde_json::Value::String(string) => RespValue::BulkString(string.into_bytes()),
+            serde_json::Value::Array(values) => {
+                RespValue::Array(values.into_iter().map(RespValue::from).collect())
+            }
+            serde_json::Value::Object(object) => RespValue::Map(
+                object
+                    .into_iter()
+                    .map(|(k, v)| (RespValue::BulkString(k.into_bytes()), v.into()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+fn json_string(bytes: Vec<u8>) -> Result<String, Error> {
+    String::from_utf8(bytes).map_err(|e| {
+        error::resp(
+            "Cannot convert invalid UTF-8 into JSON",
+            RespValue::BulkString(e.into_bytes()),
+        )
+    })
+}
+
+#[cfg(feature = "json")]
+impl TryFrom<RespValue> for serde_json::Value {
+    type Error = Error;
+
+    fn try_from(value: RespValue) -> Result<serde_json::Value, Error> {
+        Ok(match value {
+            RespValue::Nil => serde_json::Value::Null,
+            RespValue::Boolean(boolean) => serde_json::Value::Bool(boolean),
+            RespValue::Integer(int) => int.into(),
+            RespValue::Double(double) => match serde_json::Number::from_f64(double) {
+                Some(number) => serde_json::Value::Number(number),
+                None => return Err(error::resp("Cannot convert into JSON", value)),
+            },
+            RespValue::BigNumber(ref string) => {
+                if let Ok(int) = string.parse::<i64>() {
+                    int.into()
+                } else if let Ok(int) = string.parse::<u64>() {
+                    int.into()
+                } else {
+                    return Err(error::resp("Cannot convert into JSON", value));
+                }
+            }
+            RespValue::SimpleString(string) => serde_json::Value::String(string),
+            RespValue::BulkString(bytes) | RespValue::Verbatim { data: bytes, .. } => {
+                serde_json::Value::String(json_string(bytes)?)
+            }
+            RespValue::Error(string) => return Err(Error::Remote(string)),
+            RespValue::Array(values) | RespValue::Set(values) | RespValue::Push(values) => {
+                serde_json::Value::Array(
+                    values
+                        .into_iter()
+                        .map(serde_json::Value::try_from)
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            RespValue::Map(pairs) => {
+                let mut object = serde_json::Map::with_capacity(pairs.len());
+                for (key, value) in pairs {
+                    let key = match serde_json::Value::try_from(key)? {
+                        serde_json::Value::String(key) => key,
+                        serde_json::Value::Number(key) => key.to_string(),
+                        key => {
+                            return Err(error::resp(
+                                "Cannot convert into a JSON object key",
+                                key.into(),
+                            ))
+                        }
+                    };
+                    object.insert(key, value.try_into()?);
+                }
+                serde_json::Value::Object(object)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use crate::{error::Error, resp::RespValue};
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_value_round_trip() {
+        let document = json!({
+            "name": "Alice",
+            "age": 42,
+            "balance": -12.5,
+            "big": u64::MAX,
+            "admin": false,
+            "manager": null,
+            "tags": ["a", "b", [1, 2, {"nested": true}]],
+            "address": {"city": "Paris", "lines": []},
+        });
+        let resp = RespValue::from(document.clone());
+        match resp {
+            RespValue::Map(ref pairs) => assert_eq!(pairs.len(), 8),
+            ref x => panic!("Expected a map, got: {:?}", x),
+        }
+        assert_eq!(serde_json::Value::try_from(resp).unwrap(), document);
+
+        assert_eq!(RespValue::from(json!("text")), "text".into());
+        assert_eq!(
+            RespValue::from(json!(u64::MAX)),
+            RespValue::BigNumber(u64::MAX.to_string())
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_value_lossy_conversions() {
+        let resp = RespValue::Map(vec![
+            (RespValue::Integer(1), RespValue::SimpleString("OK".into())),
+            (
+                RespValue::SimpleString("set".into()),
+                RespValue::Set(vec![RespValue::Integer(1)]),
+            ),
+            (
+                "verbatim".into(),
+                RespValue::Verbatim {
+                    format: *b"txt",
+                    data: b"text".to_vec(),
+                },
+            ),
+        ]);
+        assert_eq!(
+            serde_json::Value::try_from(resp).unwrap(),
+            json!({"1": "OK", "set": [1], "verbatim": "text"})
+        );
+
+        for value in [
+            RespValue::BulkString(vec![0xff]),
+            RespValue::Double(f64::NAN),
+            RespValue::BigNumber("123456789012345678901234567890".into()),
+            RespValue::Map(vec![(RespValue::Nil, RespValue::Nil)]),
+        ] {
+            assert!(
+                matches!(
+                    serde_json::Value::try_from(value.clone()),
+                    Err(Error::Resp(_, _))
+                ),
+                "{:?}",
+                value
+            );
+        }
+        assert!(matches!(
+            serde_json::Value::try_from(resp_array![RespValue::Error("ERR bad".into())]),
+            Err(Error::Remote(_))
+        ));
+    }
+
+    fn to_json(value: RespValue) -> serde_json::Value {
+        serde_json::to_value(value).expect("Cannot serialize")
+    }
+
+    #[test]
+    fn serialize_scalars() {
+        assert_eq!(to_json(RespValue::Nil), json!(null));
+        assert_eq!(to_json(RespValue::SimpleString("OK".into())), json!("OK"));
+        assert_eq!(to_json(RespValue::from("value")), json!("value"));
+        assert_eq!(
+            to_json(RespValue::BulkString(vec![0xff, 0x00])),
+            json!([255, 0])
+        );
+        assert_eq!(
+            to_json(RespValue::Error("ERR bad".into())),
+            json!({"error": "ERR bad"})
+        );
+        assert_eq!(to_json(RespValue::Integer(-42)), json!(-42));
+        assert_eq!(to_json(RespValue::Double(1.5)), json!(1.5));
+        assert_eq!(to_json(RespValue::Boolean(true)), json!(true));
+        assert_eq!(
+            to_json(RespValue::BigNumber(
+                "3492890328409238509324850943850943825024385".into()
+            )),
+            json!("3492890328409238509324850943850943825024385")
+        );
+        assert_eq!(
+            to_json(RespValue::Verbatim {
+                format: *b"txt",
+                data: b"Some text".to_vec(),
+            }),
+            json!("Some text")
+        );
+    }
+
+    #[test]
+    fn serialize_aggregates() {
+        assert_eq!(
+            to_json(resp_array!["a", RespValue::Integer(1), RespValue::Nil]),
+            json!(["a", 1, null])
+        );
+        assert_eq!(
+            to_json(RespValue::Set(vec!["a".into(), "b".into()])),
+            json!(["a", "b"])
+        );
+        assert_eq!(
+            to_json(RespValue::Push(vec!["message".into(), "chan".into()])),
+            json!(["message", "chan"])
+        );
+        assert_eq!(
+            to_json(RespValue::Map(vec![
+                ("name".into(), "redis".into()),
+                ("nested".into(), resp_array![RespValue::Integer(2)]),
+            ])),
+            json!({"name": "redis", "nested": [2]})
+        );
+    }
+
+    #[test]
+    fn deserialize_from_json() {
+        // serde_json sorts the keys of objects
+        let value: RespValue = serde_json::from_value(json!({
+            "name": "redis",
+            "values": [1, -2, 1.5, true, null],
+            "big": 18446744073709551615u64,
+        }))
+        .expect("Cannot deserialize");
+        assert_eq!(
+            value,
+            RespValue::Map(vec![
+                (
+                    "big".into(),
+                    RespValue::BigNumber("18446744073709551615".into())
+                ),
+                ("name".into(), "redis".into()),
+                (
+                    "values".into(),
+                    resp_array![
+                        RespValue::Integer(1),
+                        RespValue::Integer(-2),
+                        RespValue::Double(1.5),
+                        RespValue::Boolean(true),
+                        RespValue::Nil
+                    ]
+                ),
+            ])
+        );
+
+        let error: RespValue =
+            serde_json::from_value(json!({"error": "ERR bad"})).expect("Cannot deserialize");
+        assert_eq!(error, RespValue::Error("ERR bad".into()));
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Role {
+        Master,
+        Replica,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Server {
+        server: String,
+        proto: u8,
+        mode: Option<String>,
+        role: Role,
+        ready: bool,
+        modules: Vec<String>,
+    }
+
+    #[test]
+    fn from_resp_map() {
+        let reply = RespValue::Map(vec![
+            ("server".into(), "redis".into()),
+            ("proto".into(), RespValue::Integer(3)),
+            ("mode".into(), RespValue::Nil),
+            ("role".into(), "replica".into()),
+            ("ready".into(), RespValue::Boolean(true)),
+            ("modules".into(), resp_array!["search", "json"]),
+            ("ignored".into(), RespValue::Integer(1)),
+        ]);
+        let server: Server = super::from_resp_value(reply).expect("Cannot deserialize");
+        assert_eq!(
+            server,
+            Server {
+                server: "redis".into(),
+                proto: 3,
+                mode: None,
+                role: Role::Replica,
+                ready: true,
+                modules: vec!["search".into(), "json".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn from_resp_array_of_pairs() {
+        let reply = resp_array![
+            "server", "redis", "proto", "2", "mode", "cluster", "role", "master", "ready", "0",
+            "modules"
+        ]
+        .append(Some(resp_array![]));
+        let server: Server = super::from_resp_value(reply).expect("Cannot deserialize");
+        assert_eq!(server.proto, 2);
+        assert_eq!(server.mode.as_deref(), Some("cluster"));
+        assert_eq!(server.role, Role::Master);
+        assert!(!server.ready);
+
+        let counts: HashMap<String, i64> =
+            super::from_resp_value(resp_array!["a", "1", "b", "2"]).expect("Cannot deserialize");
+        assert_eq!(counts["b"], 2);
+
+        let odd: Result<HashMap<String, i64>, _> = super::from_resp_value(resp_array!["a"]);
+        assert!(matches!(odd, Err(Error::Resp(_, _))));
+    }
+
+    #[test]
+    fn from_resp_errors() {
+        let remote: Result<String, _> = super::from_resp_value(RespValue::Error("ERR bad".into()));
+        assert!(matches!(remote, Err(Error::Remote(ref e)) if e == "ERR bad"));
+
+        let unparseable: Result<u32, _> = super::from_resp_value(RespValue::from("abc"));
+        assert!(matches!(unparseable, Err(Error::Resp(_, _))));
+
+        let missing: Result<Server, _> =
+            super::from_resp_value(RespValue::Map(vec![("server".into(), "redis".into())]));
+        assert!(matches!(missing, Err(Error::Resp(_, None))));
+    }
+}